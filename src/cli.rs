@@ -0,0 +1,119 @@
+//! The `robe` command line, over the same modules as the library.
+
+use crate::dispatch::*;
+use crate::domain::{self, Command};
+use crate::errors::RobeError;
+use crate::{help, settings, utils};
+
+/// Run `robe` with `args`, the arguments after the program name.
+/// Errors are printed as plain text or JSON, per `--format`. Returns the exit status.
+pub fn main(args: &[String]) -> i32 {
+    let Err(e) = _main(args) else {
+        return 0;
+    };
+    // known even when the command after the options is wrong
+    let format = domain::parse_options(args)
+        .map(|(options, _)| options.format)
+        .unwrap_or_default();
+    match format {
+        domain::Format::Plain => eprintln!("{}", e),
+        domain::Format::Json => eprintln!("{}", e.to_json()),
+    }
+    e.exit_code()
+}
+
+fn _main(args: &[String]) -> Result<(), RobeError> {
+    let invocation = domain::parse_cli(args)?;
+    let format = invocation.options.format;
+    let command = invocation.command;
+
+    let (settings_fp, config_source) = utils::config_file_path(
+        invocation.options.config.as_deref(),
+        std::env::var(settings::CONFIG_ENV).ok(),
+    );
+
+    if let Command::Help(Some(name)) = &command {
+        let text = help::command_help(name)
+            .ok_or_else(|| RobeError::Internal(format!("No help for command {}", name)))?;
+        print!("{}", text);
+        return Ok(());
+    }
+    if let Command::Version = &command {
+        println!("{}", help::VERSION);
+        return Ok(());
+    }
+    // never fails: shows the default wardrobe when the config cannot be read
+    if let Command::Help(None) = &command {
+        let mut settings = utils::get_settings(&settings_fp, config_source).unwrap_or_default();
+        let wardrobe_source =
+            utils::override_wardrobe(&mut settings, std::env::var(settings::WARDROBE_ENV).ok());
+        println!(
+            "{}",
+            help::help_with_storage_and_config(
+                &settings.wardrobe,
+                wardrobe_source,
+                &settings_fp,
+                config_source,
+            )
+        );
+        return Ok(());
+    }
+    // reads the config itself and needs no wardrobe
+    if let Command::Config(cfg) = &command {
+        return config::config(cfg, &settings_fp, config_source);
+    }
+    if let Command::Completions(c) = &command {
+        completions::completions(c);
+        return Ok(());
+    }
+    // completes from whatever wardrobe can be read, never fails in the shell and changes nothing
+    if let Command::Complete(c) = &command {
+        let registry = utils::get_settings(&settings_fp, config_source)
+            .and_then(|mut settings| {
+                utils::override_wardrobe(&mut settings, std::env::var(settings::WARDROBE_ENV).ok());
+                utils::read_registry(&settings)
+            })
+            .ok();
+        completions::complete(c, registry.as_ref());
+        return Ok(());
+    }
+
+    let mut settings = utils::get_settings(&settings_fp, config_source)?;
+    utils::override_wardrobe(&mut settings, std::env::var(settings::WARDROBE_ENV).ok());
+
+    // commands that only read the wardrobe neither create nor migrate it
+    let registry = match command {
+        Command::List(_)
+        | Command::View(_)
+        | Command::Status(_)
+        | Command::Diff(_)
+        | Command::History(_) => utils::read_registry(&settings)?,
+        _ => utils::get_registry(&settings)?,
+    };
+
+    match command {
+        Command::Add(add) => add::add(&add, &registry, &settings)?,
+        Command::Register(reg) => register::register(&reg, &registry)?,
+        Command::Edit(edit) => edit::edit(&edit, &registry)?,
+        Command::Use(usecmd) => usecmd::usecmd(&usecmd, &registry, &settings)?,
+        Command::Rm(rm) => rm::rm(&rm, &registry)?,
+        Command::Mv(m) => mv::mv(&m, &registry)?,
+        Command::Cp(c) => cp::cp(&c, &registry)?,
+        Command::Target(t) => target::target(&t, &registry)?,
+        Command::Outfit(o) => outfit::outfit(&o, &registry, &settings, format)?,
+        Command::List(ls) => list::list(&ls, &registry, format)?,
+        Command::View(view) => view::view(&view, &registry, format)?,
+        Command::Status(st) => status::status(&st, &registry, format)?,
+        Command::Diff(d) => diff::diff(&d, &registry)?,
+        Command::Undo(u) => undo::undo(&u, &registry)?,
+        Command::History(h) => history::history(&h, &registry, format)?,
+        Command::Restore(r) => restore::restore(&r, &registry, &settings)?,
+        Command::Config(_)
+        | Command::Completions(_)
+        | Command::Complete(_)
+        | Command::Help(_)
+        | Command::Version => unreachable!("handled before loading the settings"),
+    };
+
+    Ok(())
+}
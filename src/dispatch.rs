@@ -1,8 +1,20 @@
 pub mod add;
+pub mod completions;
+pub mod config;
+pub mod cp;
+pub mod diff;
 pub mod edit;
-mod io;
+pub mod history;
+pub(crate) mod hooks;
+pub(crate) mod io;
 pub mod list;
+pub mod mv;
+pub mod outfit;
 pub mod register;
+pub mod restore;
 pub mod rm;
+pub mod status;
+pub mod target;
+pub mod undo;
 pub mod usecmd;
 pub mod view;
@@ -1,30 +1,65 @@
-use crate::dispatch::io;
+use crate::dispatch::{hooks, io};
 use crate::domain::Add;
 use crate::errors::RobeError;
-use crate::registry::Registry;
+use crate::ignore::Ignore;
+use crate::registry::{Event, Registry};
+use crate::settings::Settings;
 
 /// Add a profile to a target.
 /// Requires force if profile already exists.
 /// Fails if target does not exist.
 ///
 /// Behaviour:
-/// copy the current file/dir at `real_path` to `path/to/wardrobe/<target>/<profile>`
-pub fn add(cmd: &Add, registry: &Registry) -> Result<(), RobeError> {
+/// run the `pre-add` hooks of the target and of `<profile>`, failing if one fails
+/// run the `validate` command of the target on the live config, saving nothing if it fails
+/// keep the stored profile under `path/to/wardrobe/<target>/.history/<profile>` before it is overwritten
+/// copy the current file/dir at `real_path` to `path/to/wardrobe/<target>/<profile>[.<ext>]`,
+/// keeping symlinks inside it as symlinks unless `-L` is given,
+/// and leaving out the entries of a directory its ignore patterns match
+/// record `<profile>` as the active profile, since the live config now matches it
+/// a live config linking into the wardrobe stays linked to its profile
+/// run the `post-add` hooks, warning about those that fail
+pub fn add(cmd: &Add, registry: &Registry, settings: &Settings) -> Result<(), RobeError> {
     if let Some(target_registry) = registry.get_target_registry(&cmd.target) {
         if target_registry.profiles.contains(&cmd.profile.to_string()) && !cmd.force {
-            return Err(RobeError::message(format!(
+            return Err(RobeError::AlreadyExists(format!(
                 "Profile {}/{} already exists. Use `-f` to update.",
                 &cmd.target, &cmd.profile
             )));
         }
 
-        let mut target_path = registry.base_path.clone();
-        target_path.push(cmd.target.clone());
-        target_path.push(cmd.profile.clone());
+        let target_path = registry.profile_path(&target_registry, &cmd.profile);
 
-        io::replace_file_or_dir(&target_registry.real_path, &target_path)?;
+        let linked = io::linked_profile(registry, &target_registry);
+        if linked.as_deref() == Some(cmd.profile.as_str()) {
+            // the live config is this profile already
+            return Ok(());
+        }
+
+        hooks::run(registry, &target_registry, &cmd.profile, Event::PreAdd)?;
+        hooks::validate(&target_registry, &target_registry.real_path)?;
+        io::keep_version(registry, &target_registry, &cmd.profile, settings.history)?;
+        let ignore = Ignore::for_target(&target_registry)?;
+        let opts = io::CopyOptions {
+            dereference: cmd.dereference,
+            ignore: Some(&ignore),
+            ..Default::default()
+        };
+        io::replace_file_or_dir(&target_registry.real_path, &target_path, &opts)?.print_warnings();
+        if linked.is_none() {
+            let apply = target_registry.apply;
+            io::record_activation(
+                registry,
+                &cmd.target,
+                &cmd.profile,
+                &target_registry.real_path,
+                &ignore.applied(apply, &target_path),
+                apply,
+            )?;
+        }
+        hooks::run(registry, &target_registry, &cmd.profile, Event::PostAdd)?;
     } else {
-        return Err(RobeError::message(format!(
+        return Err(RobeError::NotFound(format!(
             "Target {} not registered. Use -r <file> to register.",
             &cmd.target
         )));
@@ -54,6 +89,7 @@ mod tests {
             name: "tmux".to_string(),
             real_path: temp.path().join("config.txt"),
             profiles: vec!["work".to_string()],
+            ..Default::default()
         };
         let mut targets = std::collections::HashMap::new();
         targets.insert("tmux".to_string(), tr.clone());
@@ -67,10 +103,11 @@ mod tests {
             target: "tmux".to_string(),
             profile: "work".to_string(),
             force: false,
+            dereference: false,
         };
 
         // Should fail because profile exists and no -f
-        let res = add(&cmd, &registry);
+        let res = add(&cmd, &registry, &Settings::default());
         assert!(res.is_err());
         assert!(format!("{}", res.unwrap_err()).contains("Profile tmux/work already exists"));
     }
@@ -89,6 +126,7 @@ mod tests {
             name: "tmux".to_string(),
             real_path: source_file.clone(),
             profiles: vec!["work".to_string()],
+            ..Default::default()
         };
         let mut targets = std::collections::HashMap::new();
         targets.insert("tmux".to_string(), tr.clone());
@@ -102,16 +140,169 @@ mod tests {
             target: "tmux".to_string(),
             profile: "work".to_string(),
             force: true,
+            dereference: false,
         };
 
         let target_dir = wardrobe.join("tmux");
         fs::create_dir_all(&target_dir).unwrap();
 
         // Should succeed because force = true
-        add(&cmd, &registry).unwrap();
+        add(&cmd, &registry, &Settings::default()).unwrap();
 
         let profile_path = wardrobe.join("tmux").join("work");
         assert!(profile_path.exists());
+
+        // the live config now matches the updated profile
+        let state = fs::read_to_string(wardrobe.join("tmux").join("state.toml")).unwrap();
+        let state: crate::registry::TargetState = toml::from_str(&state).unwrap();
+        assert_eq!(state.profile, "work");
+        assert_eq!(state.fingerprint, io::fingerprint(&source_file, &Ignore::default()).unwrap());
+    }
+
+    #[test]
+    fn test_add_force_drops_newly_ignored_entries() {
+        let temp = tempdir().unwrap();
+        let wardrobe = temp.path().join("wardrobe");
+        let live = temp.path().join("nvim");
+        fs::create_dir_all(&live).unwrap();
+        create_file(&live.join("init.lua"), "new");
+        create_file(&live.join("lazy-lock.json"), "{}");
+        let profile = wardrobe.join("nvim").join("main");
+        fs::create_dir_all(&profile).unwrap();
+        create_file(&profile.join("init.lua"), "old");
+        create_file(&profile.join("lazy-lock.json"), "{}");
+
+        // the pattern was added after the profile was saved
+        let tr = TargetRegistry {
+            name: "nvim".to_string(),
+            real_path: live.clone(),
+            profiles: vec!["main".to_string()],
+            ignore: vec!["lazy-lock.json".to_string()],
+            ..Default::default()
+        };
+        let mut targets = std::collections::HashMap::new();
+        targets.insert("nvim".to_string(), tr);
+        let registry = Registry {
+            base_path: wardrobe.to_path_buf(),
+            targets,
+        };
+        let cmd = Add {
+            target: "nvim".to_string(),
+            profile: "main".to_string(),
+            force: true,
+            dereference: false,
+        };
+
+        add(&cmd, &registry, &Settings::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(profile.join("init.lua")).unwrap(), "new");
+        assert!(!profile.join("lazy-lock.json").exists());
+        assert!(live.join("lazy-lock.json").exists());
+    }
+
+    #[test]
+    fn test_add_force_keeps_earlier_versions() {
+        let temp = tempdir().unwrap();
+        let wardrobe = temp.path().join("wardrobe");
+        let source_file = temp.path().join("config.txt");
+        fs::create_dir_all(wardrobe.join("tmux")).unwrap();
+        create_file(&wardrobe.join("tmux").join("work"), "v0");
+
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: source_file.clone(),
+            profiles: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let mut targets = std::collections::HashMap::new();
+        targets.insert("tmux".to_string(), tr.clone());
+        let registry = Registry {
+            base_path: wardrobe.to_path_buf(),
+            targets,
+        };
+        let cmd = Add {
+            target: "tmux".to_string(),
+            profile: "work".to_string(),
+            force: true,
+            dereference: false,
+        };
+        let settings = Settings {
+            history: 2,
+            ..Default::default()
+        };
+
+        for content in ["v1", "v2", "v3"] {
+            create_file(&source_file, content);
+            add(&cmd, &registry, &settings).unwrap();
+        }
+
+        // only the two most recent of v0, v1 and v2 are kept
+        let versions = io::list_versions(&registry, &tr, "work").unwrap();
+        let contents: Vec<String> = versions
+            .iter()
+            .map(|v| fs::read_to_string(&v.path).unwrap())
+            .collect();
+        assert_eq!(contents, vec!["v1", "v2"]);
+        assert_eq!(
+            fs::read_to_string(wardrobe.join("tmux").join("work")).unwrap(),
+            "v3"
+        );
+
+        // no history at all with `history = 0`
+        let settings = Settings {
+            history: 0,
+            ..Default::default()
+        };
+        add(&cmd, &registry, &settings).unwrap();
+        assert_eq!(io::list_versions(&registry, &tr, "work").unwrap().len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_with_linked_live_config() {
+        let temp = tempdir().unwrap();
+        let wardrobe = temp.path().join("wardrobe");
+        fs::create_dir_all(wardrobe.join("tmux")).unwrap();
+        create_file(&wardrobe.join("tmux").join("work"), "work");
+
+        let real = temp.path().join("tmux.conf");
+        std::os::unix::fs::symlink(wardrobe.join("tmux").join("work"), &real).unwrap();
+
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: real.clone(),
+            profiles: vec!["work".to_string()],
+            mode: crate::registry::Mode::Symlink,
+            ..Default::default()
+        };
+        let mut targets = std::collections::HashMap::new();
+        targets.insert("tmux".to_string(), tr);
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets,
+        };
+
+        // adding the linked profile onto itself is a no-op
+        let cmd = Add {
+            target: "tmux".to_string(),
+            profile: "work".to_string(),
+            force: true,
+            dereference: false,
+        };
+        add(&cmd, &registry, &Settings::default()).unwrap();
+        assert_eq!(fs::read_to_string(wardrobe.join("tmux").join("work")).unwrap(), "work");
+
+        // a copy of the linked profile does not become the active one
+        let cmd = Add {
+            target: "tmux".to_string(),
+            profile: "copy".to_string(),
+            force: false,
+            dereference: false,
+        };
+        add(&cmd, &registry, &Settings::default()).unwrap();
+        assert_eq!(fs::read_to_string(wardrobe.join("tmux").join("copy")).unwrap(), "work");
+        assert!(real.symlink_metadata().unwrap().file_type().is_symlink());
+        assert!(!wardrobe.join("tmux").join("state.toml").exists());
     }
 
     #[test]
@@ -129,11 +320,46 @@ mod tests {
             target: "tmux".to_string(),
             profile: "work".to_string(),
             force: false,
+            dereference: false,
         };
 
         // Should fail because target not registered
-        let res = add(&cmd, &registry);
+        let res = add(&cmd, &registry, &Settings::default());
         assert!(res.is_err());
         assert!(format!("{}", res.unwrap_err()).contains("Target tmux not registered"));
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_invalid_live_config_saves_nothing() {
+        let temp = tempdir().unwrap();
+        let wardrobe = temp.path().join("wardrobe");
+        let source_file = temp.path().join("config.txt");
+        create_file(&source_file, "invalid");
+
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: source_file.clone(),
+            validate: Some("grep -q ok {path}".to_string()),
+            ..Default::default()
+        };
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets: std::collections::HashMap::from([("tmux".to_string(), tr)]),
+        };
+        let cmd = Add {
+            target: "tmux".to_string(),
+            profile: "work".to_string(),
+            force: false,
+            dereference: false,
+        };
+
+        let err = add(&cmd, &registry, &Settings::default()).unwrap_err();
+        assert!(matches!(err, RobeError::Invalid(_)));
+        assert!(!wardrobe.join("tmux").join("work").exists());
+
+        create_file(&source_file, "ok");
+        add(&cmd, &registry, &Settings::default()).unwrap();
+        assert!(wardrobe.join("tmux").join("work").exists());
+    }
 }
@@ -0,0 +1,308 @@
+use crate::dispatch::outfit;
+use crate::domain::{COMMANDS, Complete, Completions, GLOBAL_FLAGS, Shell};
+use crate::registry::Registry;
+use crate::settings::KEYS;
+
+/// Print the completion script of a shell.
+/// The scripts ask `robe __complete` for candidates, so they follow the wardrobe as it changes.
+pub fn completions(cmd: &Completions) {
+    let script = match cmd.shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    };
+    print!("{}", script);
+}
+
+/// Print the candidates for the last of `cmd.words`, one per line.
+/// Nothing is printed when the shell should fall back to completing paths.
+pub fn complete(cmd: &Complete, registry: Option<&Registry>) {
+    for candidate in candidates(&cmd.words, registry) {
+        println!("{}", candidate);
+    }
+}
+
+const BASH: &str = r#"_robe() {
+    local IFS=$'\n'
+    COMPREPLY=($("${COMP_WORDS[0]}" __complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+    if [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == */ ]]; then
+        compopt -o nospace
+    fi
+}
+complete -o default -F _robe robe
+"#;
+
+const ZSH: &str = r#"#compdef robe
+
+_robe() {
+    local -a candidates
+    candidates=(${(f)"$($words[1] __complete "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -Q -S '' -- ${(M)candidates:#*/}
+        compadd -Q -- ${candidates:#*/}
+    else
+        _files
+    fi
+}
+
+if [[ $zsh_eval_context[-1] == loadautofunc ]]; then
+    _robe "$@"
+else
+    compdef _robe robe
+fi
+"#;
+
+const FISH: &str = r#"function __robe_complete
+    set -l tokens (commandline -opc)
+    set -l current (commandline -ct)
+    $tokens[1] __complete $tokens[2..-1] "$current" 2>/dev/null
+end
+
+function __robe_wants_path
+    set -l tokens (commandline -opc)
+    contains -- $tokens[-1] -r --register --config
+end
+
+complete -c robe -f -a '(__robe_complete)'
+complete -c robe -n __robe_wants_path -F
+"#;
+
+/// Candidates for the word under the cursor, the last of `words`.
+fn candidates(words: &[String], registry: Option<&Registry>) -> Vec<String> {
+    let (current, before) = match words.split_last() {
+        Some((current, before)) => (current.as_str(), before),
+        None => ("", words),
+    };
+
+    let all: Vec<String> = match before.last().map(String::as_str) {
+        Some("--format") => strings(&["json", "plain"]),
+        Some("-m" | "--mode") => strings(&["copy", "symlink"]),
+        Some("-a" | "--apply") => strings(&["replace", "overlay"]),
+        Some("--config" | "-r" | "--register") => Vec::new(),
+        _ => match skip_global_options(before) {
+            [] if current.starts_with('-') => strings(GLOBAL_FLAGS),
+            [] => COMMANDS.iter().map(|(name, _)| name.to_string()).collect(),
+            [command, args @ ..] => {
+                let flags = COMMANDS
+                    .iter()
+                    .find(|(name, _)| name == command)
+                    .map(|(_, flags)| *flags)
+                    .unwrap_or_default();
+                if current.starts_with('-') {
+                    strings(flags)
+                } else {
+                    command_args(command, &positionals(args), registry)
+                }
+            }
+        },
+    };
+
+    all.into_iter().filter(|c| c.starts_with(current)).collect()
+}
+
+/// Candidates for the next positional argument of `command`, after `done`.
+fn command_args(command: &str, done: &[&String], registry: Option<&Registry>) -> Vec<String> {
+    let names = |with_targets: bool, with_profiles: bool| {
+        registry
+            .map(|r| target_names(r, with_targets, with_profiles))
+            .unwrap_or_default()
+    };
+    match (command, done) {
+        ("use" | "mv" | "cp" | "history" | "restore", []) => names(false, true),
+        ("mv", [from]) => from
+            .split_once('/')
+            .map(|(target, _)| vec![format!("{}/", target)])
+            .unwrap_or_default(),
+        ("cp", [_]) => names(false, false)
+            .into_iter()
+            .map(|t| format!("{}/", t))
+            .collect(),
+        ("add", []) => names(false, false)
+            .into_iter()
+            .map(|t| format!("{}/", t))
+            .collect(),
+        ("view" | "edit" | "rm", []) | ("diff", [] | [_]) => names(true, true),
+        ("list" | "ls" | "status" | "undo", []) | ("target", [_]) => names(true, false),
+        ("target", []) => strings(&["rename", "set-path"]),
+        ("outfit", []) => strings(&["save", "wear", "list", "rm"]),
+        ("outfit", [action]) if *action == "wear" || *action == "rm" => registry
+            .and_then(|r| outfit::names(r).ok())
+            .unwrap_or_default(),
+        ("outfit", [action, _, ..]) if *action == "save" => names(false, true),
+        ("config", []) => strings(&["check", "list", "get", "set"]),
+        ("config", [action]) if *action == "get" || *action == "set" => strings(&KEYS),
+        ("completions", []) => strings(&["bash", "zsh", "fish"]),
+        ("help", []) => COMMANDS.iter().map(|(name, _)| name.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Sorted `<target>` names and/or `<target>/<profile>` pairs of the wardrobe.
+fn target_names(registry: &Registry, with_targets: bool, with_profiles: bool) -> Vec<String> {
+    let mut targets: Vec<_> = registry.targets.values().collect();
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = Vec::new();
+    for tr in targets {
+        if with_targets || !with_profiles {
+            out.push(tr.name.clone());
+        }
+        if with_profiles {
+            let mut profiles = tr.profiles.clone();
+            profiles.sort();
+            out.extend(profiles.iter().map(|p| format!("{}/{}", tr.name, p)));
+        }
+    }
+    out
+}
+
+/// `words` from the command on.
+fn skip_global_options(words: &[String]) -> &[String] {
+    let mut i = 0;
+    while let Some(word) = words.get(i) {
+        match word.as_str() {
+            "--config" | "--format" => i += 2,
+            w if w.starts_with("--config=") || w.starts_with("--format=") || w == "--json" => {
+                i += 1
+            }
+            _ => break,
+        }
+    }
+    words.get(i..).unwrap_or_default()
+}
+
+/// The positional arguments among `args`, leaving out flags and their values.
+fn positionals(args: &[String]) -> Vec<&String> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" | "--register" | "-m" | "--mode" | "-a" | "--apply" => {
+                args.next();
+            }
+            a if a.starts_with('-') => {}
+            _ => out.push(arg),
+        }
+    }
+    out
+}
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::TargetRegistry;
+    use std::collections::HashMap;
+
+    fn registry() -> Registry {
+        let mut targets = HashMap::new();
+        for (name, profiles) in [("tmux", vec!["work", "home"]), ("nvim", vec!["minimal"])] {
+            targets.insert(
+                name.to_string(),
+                TargetRegistry {
+                    name: name.to_string(),
+                    profiles: profiles.iter().map(|p| p.to_string()).collect(),
+                    ..Default::default()
+                },
+            );
+        }
+        Registry {
+            base_path: "/tmp/wardrobe".into(),
+            targets,
+        }
+    }
+
+    fn complete_words(words: &[&str]) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        candidates(&words, Some(&registry()))
+    }
+
+    #[test]
+    fn test_complete_commands() {
+        assert_eq!(complete_words(&["u"]), vec!["use", "undo"]);
+        assert_eq!(complete_words(&["--json", "st"]), vec!["status"]);
+        assert_eq!(
+            complete_words(&["--config", "/tmp/c.toml", "li"]),
+            vec!["list"]
+        );
+        assert!(complete_words(&["--j"]).contains(&"--json".to_string()));
+        assert_eq!(complete_words(&["--format", ""]), vec!["json", "plain"]);
+        assert_eq!(complete_words(&[]).len(), COMMANDS.len());
+    }
+
+    #[test]
+    fn test_complete_profiles() {
+        assert_eq!(
+            complete_words(&["use", "tmux/"]),
+            vec!["tmux/home", "tmux/work"]
+        );
+        assert_eq!(complete_words(&["use", "-f", "n"]), vec!["nvim/minimal"]);
+        assert_eq!(
+            complete_words(&["view", ""]),
+            vec!["nvim", "nvim/minimal", "tmux", "tmux/home", "tmux/work"]
+        );
+        assert_eq!(complete_words(&["add", ""]), vec!["nvim/", "tmux/"]);
+        assert_eq!(complete_words(&["status", ""]), vec!["nvim", "tmux"]);
+        assert_eq!(
+            complete_words(&["diff", "tmux/work", "tmux/h"]),
+            vec!["tmux/home"]
+        );
+        assert!(complete_words(&["use", "tmux/work", ""]).is_empty());
+        assert_eq!(complete_words(&["mv", "tmux/work", ""]), vec!["tmux/"]);
+        assert_eq!(complete_words(&["history", "tmux/w"]), vec!["tmux/work"]);
+        assert_eq!(
+            complete_words(&["cp", "-f", "tmux/work", ""]),
+            vec!["nvim/", "tmux/"]
+        );
+    }
+
+    #[test]
+    fn test_complete_flags_and_values() {
+        assert_eq!(
+            complete_words(&["use", "--"]),
+            vec!["--force", "--save", "--apply"]
+        );
+        assert_eq!(
+            complete_words(&["use", "-a", ""]),
+            vec!["replace", "overlay"]
+        );
+        assert_eq!(
+            complete_words(&["use", "--apply", "overlay", ""]),
+            vec!["nvim/minimal", "tmux/home", "tmux/work"]
+        );
+        assert_eq!(
+            complete_words(&["add", "tmux/work", "-r", "/tmp/x", "-m", ""]),
+            vec!["copy", "symlink"]
+        );
+        assert!(complete_words(&["add", "tmux/work", "-r", ""]).is_empty());
+        assert_eq!(
+            complete_words(&["config", "get", ""]),
+            vec!["wardrobe", "backups", "history"]
+        );
+        assert_eq!(complete_words(&["completions", "f"]), vec!["fish"]);
+        assert_eq!(complete_words(&["target", "s"]), vec!["set-path"]);
+        assert_eq!(
+            complete_words(&["target", "rename", ""]),
+            vec!["nvim", "tmux"]
+        );
+        assert!(complete_words(&["target", "set-path", "tmux", ""]).is_empty());
+        assert_eq!(complete_words(&["outfit", "w"]), vec!["wear"]);
+        assert_eq!(
+            complete_words(&["outfit", "save", "work", "tmux/work", "n"]),
+            vec!["nvim/minimal"]
+        );
+        assert_eq!(
+            complete_words(&["help", "co"]),
+            vec!["config", "completions"]
+        );
+    }
+
+    #[test]
+    fn test_complete_without_wardrobe() {
+        let words = vec!["use".to_string(), "".to_string()];
+        assert!(candidates(&words, None).is_empty());
+    }
+}
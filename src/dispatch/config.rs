@@ -0,0 +1,366 @@
+use crate::domain::{Config, ConfigAction};
+use crate::errors::RobeError;
+use crate::settings::{KEYS, Settings, Source, WARDROBE_ENV};
+use crate::utils;
+use std::fs;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Value};
+
+/// Inspect or edit the config file at `fp`.
+pub fn config(cmd: &Config, fp: &str, source: Source) -> Result<(), RobeError> {
+    let wardrobe_env = std::env::var(WARDROBE_ENV).ok().filter(|w| !w.is_empty());
+    let out = match &cmd.action {
+        ConfigAction::Check => check(fp, source, wardrobe_env)?,
+        ConfigAction::List => list(fp, source, wardrobe_env)?,
+        ConfigAction::Get(key) => get(fp, source, wardrobe_env, key)?,
+        ConfigAction::Set(key, value) => {
+            let out = set(fp, key, value)?;
+            if key == "wardrobe" && wardrobe_env.is_some() {
+                eprintln!(
+                    "robe: warning: ${} overrides the wardrobe of the config file.",
+                    WARDROBE_ENV
+                );
+            }
+            out
+        }
+    };
+    print!("{}", out);
+    Ok(())
+}
+
+/// Validate the config without running a command.
+///
+/// Behaviour:
+/// parse the config file at `fp`, failing with the line and column of any error
+/// check that the effective wardrobe is a directory, or can be created as one
+/// print the effective settings and where they come from
+fn check(fp: &str, source: Source, wardrobe_env: Option<String>) -> Result<String, RobeError> {
+    let entries = effective(fp, source, wardrobe_env)?;
+
+    let wardrobe = Path::new(&entries.settings.wardrobe);
+    if wardrobe.exists() && !wardrobe.is_dir() {
+        return Err(RobeError::Config {
+            file: fp.to_string(),
+            location: None,
+            message: format!("wardrobe {} is not a directory", wardrobe.display()),
+        });
+    }
+
+    let status = if Path::new(fp).exists() {
+        "ok"
+    } else {
+        "not found, using defaults"
+    };
+    let mut out = format!("{} ({}): {}\n", fp, source, status);
+    for key in KEYS {
+        out.push_str(&format!("  {}", entries.format(key)));
+    }
+    Ok(out)
+}
+
+/// List every setting with its effective value and where it comes from.
+fn list(fp: &str, source: Source, wardrobe_env: Option<String>) -> Result<String, RobeError> {
+    let entries = effective(fp, source, wardrobe_env)?;
+    Ok(KEYS.iter().map(|key| entries.format(key)).collect())
+}
+
+/// Show the effective value of `key` and where it comes from.
+fn get(
+    fp: &str,
+    source: Source,
+    wardrobe_env: Option<String>,
+    key: &str,
+) -> Result<String, RobeError> {
+    if !KEYS.contains(&key) {
+        return Err(unknown_key(key));
+    }
+    Ok(effective(fp, source, wardrobe_env)?.format(key))
+}
+
+/// Write `key = value` to the config file.
+///
+/// Behaviour:
+/// validate `value` for `key`: the wardrobe must be an absolute path to a directory or to nothing yet,
+/// backups and history must be numbers
+/// create the config file and its parent directories if absent
+/// keep the comments and formatting of an existing file
+/// refuse to write a file robe could not load afterwards, e.g. one with a mistyped value
+fn set(fp: &str, key: &str, value: &str) -> Result<String, RobeError> {
+    let value = parse_value(key, value)?;
+    let display = value.to_string();
+
+    let contents = match fs::read_to_string(fp) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => "".to_string(),
+        Err(e) => return Err(RobeError::io("read", Path::new(fp))(e)),
+    };
+    let mut doc: DocumentMut = contents.parse().map_err(|e: toml_edit::TomlError| {
+        RobeError::config(fp, &contents, e.span(), e.message())
+    })?;
+    match doc.get_mut(key).and_then(Item::as_value_mut) {
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = value;
+            *old.decor_mut() = decor;
+        }
+        None => doc[key] = Item::Value(value),
+    }
+
+    let contents = doc.to_string();
+    utils::parse_settings(fp, &contents)?;
+
+    if let Some(parent) = Path::new(fp).parent() {
+        fs::create_dir_all(parent).map_err(RobeError::io("create", parent))?;
+    }
+    fs::write(fp, contents).map_err(RobeError::io("write", Path::new(fp)))?;
+    Ok(format!("{} = {} (written to {})\n", key, display, fp))
+}
+
+/// The value of `key` as written in the config file, if `raw` is valid for it.
+fn parse_value(key: &str, raw: &str) -> Result<Value, RobeError> {
+    let invalid = |reason: String| RobeError::BadUsage(format!("Invalid {}: {}.", key, reason));
+    match key {
+        "wardrobe" => {
+            let path = Path::new(raw);
+            if raw.trim().is_empty() {
+                return Err(invalid("must not be empty".to_string()));
+            }
+            if !path.is_absolute() {
+                return Err(invalid(format!("{} is not an absolute path", raw)));
+            }
+            if path.exists() && !path.is_dir() {
+                return Err(invalid(format!("{} is not a directory", raw)));
+            }
+            Ok(Value::from(raw))
+        }
+        "backups" | "history" => raw
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| i64::try_from(n).ok())
+            .map(Value::from)
+            .ok_or_else(|| invalid(format!("{} is not a number", raw))),
+        _ => Err(unknown_key(key)),
+    }
+}
+
+fn unknown_key(key: &str) -> RobeError {
+    RobeError::BadUsage(format!(
+        "Unknown setting {}. Settings are: {}.",
+        key,
+        KEYS.join(", ")
+    ))
+}
+
+/// The effective settings and where each of them comes from.
+struct Effective {
+    settings: Settings,
+    sources: Vec<(&'static str, Source)>,
+}
+
+impl Effective {
+    /// `key = value (source)`
+    fn format(&self, key: &str) -> String {
+        let value = match key {
+            "wardrobe" => Value::from(self.settings.wardrobe.as_str()),
+            "history" => Value::from(self.settings.history as i64),
+            _ => Value::from(self.settings.backups as i64),
+        };
+        let source = self
+            .sources
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, source)| *source)
+            .unwrap_or_default();
+        format!("{} = {} ({})\n", key, value, source)
+    }
+}
+
+fn effective(
+    fp: &str,
+    source: Source,
+    wardrobe_env: Option<String>,
+) -> Result<Effective, RobeError> {
+    let mut settings = utils::get_settings(fp, source)?;
+    let wardrobe_source = utils::override_wardrobe(&mut settings, wardrobe_env);
+    let in_file: toml::Table = fs::read_to_string(fp)
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    let sources = KEYS
+        .iter()
+        .map(|&key| {
+            let source = match wardrobe_source {
+                Source::Env(_) if key == "wardrobe" => wardrobe_source,
+                _ if in_file.contains_key(key) => Source::File,
+                _ => Source::Default,
+            };
+            (key, source)
+        })
+        .collect();
+    Ok(Effective { settings, sources })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check_valid_config() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        fs::write(&fp, "wardrobe = \"/tmp/robe\"\nbackups = 2\n")?;
+
+        let out = check(&fp.to_string_lossy(), Source::Flag("--config"), None)?;
+
+        assert!(out.starts_with(&format!("{} (from --config): ok\n", fp.display())));
+        assert!(out.contains("  wardrobe = \"/tmp/robe\" (from config file)\n"));
+        assert!(out.contains("  backups = 2 (from config file)\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_missing_default_config() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+
+        let out = check(
+            &fp.to_string_lossy(),
+            Source::Default,
+            Some("/tmp/robe-env".to_string()),
+        )?;
+
+        assert!(out.contains("(default): not found, using defaults\n"));
+        assert!(out.contains("(from $ROBE_WARDROBE)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_invalid_config() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        fs::write(&fp, "backups = \"many\"\n")?;
+
+        let err = check(&fp.to_string_lossy(), Source::Default, None).unwrap_err();
+        assert!(format!("{}", err).contains("config.toml:1:11: "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_wardrobe_is_a_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        let wardrobe = dir.path().join("wardrobe");
+        fs::write(&wardrobe, "")?;
+        fs::write(
+            &fp,
+            format!("wardrobe = {:?}\n", wardrobe.to_string_lossy()),
+        )?;
+
+        let err = check(&fp.to_string_lossy(), Source::Default, None).unwrap_err();
+        assert!(format!("{}", err).contains("is not a directory"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_creates_config_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("robe").join("config.toml");
+        let fp = fp.to_string_lossy();
+
+        let out = set(&fp, "backups", "3")?;
+        assert_eq!(out, format!("backups = 3 (written to {})\n", fp));
+        assert_eq!(fs::read_to_string(&*fp)?, "backups = 3\n");
+
+        let out = get(&fp, Source::Default, None, "backups")?;
+        assert_eq!(out, "backups = 3 (from config file)\n");
+
+        set(&fp, "history", "0")?;
+        let out = get(&fp, Source::Default, None, "history")?;
+        assert_eq!(out, "history = 0 (from config file)\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_keeps_comments_and_other_keys() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        fs::write(
+            &fp,
+            "# my settings\nbackups = 2 # keep a few\nextra = true\n",
+        )?;
+        let fp = fp.to_string_lossy();
+
+        set(&fp, "backups", "7")?;
+        set(&fp, "history", "4")?;
+
+        assert_eq!(
+            fs::read_to_string(&*fp)?,
+            "# my settings\nbackups = 7 # keep a few\nextra = true\nhistory = 4\n"
+        );
+        let settings = utils::get_settings(&fp, Source::Default)?;
+        assert_eq!(settings.backups, 7);
+        assert_eq!(settings.history, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_refuses_a_file_that_would_not_load() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        fs::write(&fp, "backups = 2\nwardrobe = 3\n")?;
+        let fp = fp.to_string_lossy();
+
+        let err = set(&fp, "history", "4").unwrap_err();
+
+        assert_eq!(err.exit_code(), 7);
+        assert!(format!("{}", err).contains("config.toml:2:12: invalid type"));
+        assert_eq!(fs::read_to_string(&*fp)?, "backups = 2\nwardrobe = 3\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_rejects_invalid_values() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        let file = dir.path().join("file");
+        fs::write(&file, "")?;
+        let fp = fp.to_string_lossy();
+
+        for (key, value, expected) in [
+            ("backups", "many", "Invalid backups: many is not a number."),
+            ("history", "-1", "Invalid history: -1 is not a number."),
+            ("wardrobe", "", "must not be empty"),
+            ("wardrobe", "rel/path", "is not an absolute path"),
+            ("wardrobe", &file.to_string_lossy(), "is not a directory"),
+            ("wardorbe", "/tmp", "Unknown setting wardorbe."),
+        ] {
+            let err = set(&fp, key, value).unwrap_err();
+            assert!(format!("{}", err).contains(expected), "{}", err);
+        }
+        assert!(!Path::new(&*fp).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_shows_sources() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let fp = dir.path().join("config.toml");
+        fs::write(&fp, "wardrobe = \"/tmp/robe\"\n")?;
+        let fp = fp.to_string_lossy();
+
+        let out = list(&fp, Source::Default, None)?;
+        assert_eq!(
+            out,
+            "wardrobe = \"/tmp/robe\" (from config file)\nbackups = 5 (default)\nhistory = 10 (default)\n"
+        );
+
+        let out = list(&fp, Source::Default, Some("/tmp/robe-env".to_string()))?;
+        assert!(out.starts_with("wardrobe = \"/tmp/robe-env\" (from $ROBE_WARDROBE)\n"));
+
+        assert!(get(&fp, Source::Default, None, "colour").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::dispatch::io;
+use crate::domain::Cp;
+use crate::errors::RobeError;
+use crate::registry::{Registry, TargetRegistry};
+
+/// Copy a profile, within its target or to another target of the same kind.
+/// Requires force if the new profile already exists.
+///
+/// Behaviour:
+/// copy `path/to/wardrobe/<from_target>/<from_profile>` to `path/to/wardrobe/<target>/<profile>[.<ext>]`,
+/// keeping symlinks inside it as symlinks
+/// live configs and active profiles are left untouched
+pub fn cp(cmd: &Cp, registry: &Registry) -> Result<(), RobeError> {
+    let from_registry = registry.target_registry(&cmd.from_target)?;
+    from_registry.assert_profile_exists(&cmd.from_profile)?;
+    let target_registry = registry.target_registry(&cmd.target)?;
+
+    if cmd.from_target == cmd.target && cmd.from_profile == cmd.profile {
+        return Err(RobeError::BadUsage(format!(
+            "Cannot copy {}/{} onto itself.",
+            &cmd.target, &cmd.profile
+        )));
+    }
+    if kind(&from_registry) != kind(&target_registry) {
+        return Err(RobeError::Conflict(format!(
+            "Cannot copy {}/{} into {}: {} holds a {}, {} a {}.",
+            &cmd.from_target,
+            &cmd.from_profile,
+            &cmd.target,
+            &cmd.from_target,
+            kind(&from_registry),
+            &cmd.target,
+            kind(&target_registry)
+        )));
+    }
+    check_destination(registry, &target_registry, &cmd.profile, cmd.force)?;
+
+    let from = registry.profile_path(&from_registry, &cmd.from_profile);
+    let to = registry.profile_path(&target_registry, &cmd.profile);
+    io::replace_file_or_dir(&from, &to, &io::CopyOptions::default())?.print_metadata_warnings();
+    Ok(())
+}
+
+/// Fails unless `profile` can be written into `target_registry`: its name must be valid,
+/// it must be new unless forced, and the live config must not link to it.
+pub fn check_destination(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+    force: bool,
+) -> Result<(), RobeError> {
+    target_registry.assert_profile_name(profile)?;
+    if !target_registry.profiles.iter().any(|p| p == profile) {
+        return Ok(());
+    }
+    if !force {
+        return Err(RobeError::AlreadyExists(format!(
+            "Profile {}/{} already exists. Use `-f` to overwrite.",
+            &target_registry.name, profile
+        )));
+    }
+    if io::linked_profile(registry, target_registry).as_deref() == Some(profile) {
+        return Err(RobeError::Conflict(format!(
+            "Profile {}/{} is linked at {}. Use another profile first.",
+            &target_registry.name,
+            profile,
+            target_registry.real_path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn kind(target_registry: &TargetRegistry) -> &'static str {
+    if target_registry.file_name.is_some() {
+        "file"
+    } else {
+        "directory"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// `tmux` and `zsh` hold files, `nvim` a directory.
+    fn setup(wardrobe: &Path) -> Registry {
+        let mut targets = HashMap::new();
+        for (name, file_name) in [
+            ("tmux", Some("tmux.conf")),
+            ("zsh", Some(".zshrc")),
+            ("nvim", None),
+        ] {
+            targets.insert(
+                name.to_string(),
+                TargetRegistry {
+                    name: name.to_string(),
+                    real_path: wardrobe.join("live").join(name),
+                    profiles: vec!["work".to_string(), "home".to_string()],
+                    file_name: file_name.map(str::to_string),
+                    ..Default::default()
+                },
+            );
+        }
+        let registry = Registry {
+            base_path: wardrobe.to_path_buf(),
+            targets,
+        };
+        for tr in registry.targets.values() {
+            for profile in &tr.profiles {
+                let path = registry.profile_path(tr, profile);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                if tr.file_name.is_some() {
+                    fs::write(&path, format!("{} {}", tr.name, profile)).unwrap();
+                } else {
+                    fs::create_dir_all(&path).unwrap();
+                    fs::write(path.join("init.lua"), profile).unwrap();
+                }
+            }
+        }
+        registry
+    }
+
+    fn cp_cmd(from: (&str, &str), to: (&str, &str), force: bool) -> Cp {
+        Cp {
+            from_target: from.0.to_string(),
+            from_profile: from.1.to_string(),
+            target: to.0.to_string(),
+            profile: to.1.to_string(),
+            force,
+        }
+    }
+
+    #[test]
+    fn test_cp_within_target() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        cp(
+            &cp_cmd(("tmux", "work"), ("tmux", "office"), false),
+            &registry,
+        )?;
+
+        let wardrobe = dir.path().join("tmux");
+        assert_eq!(
+            fs::read_to_string(wardrobe.join("office.conf"))?,
+            "tmux work"
+        );
+        assert_eq!(fs::read_to_string(wardrobe.join("work.conf"))?, "tmux work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cp_across_targets_keeps_extension() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        cp(&cp_cmd(("tmux", "work"), ("zsh", "tmux"), false), &registry)?;
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("zsh").join("tmux"))?,
+            "tmux work"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cp_dir() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        cp(&cp_cmd(("nvim", "work"), ("nvim", "home"), true), &registry)?;
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("nvim").join("home").join("init.lua"))?,
+            "work"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cp_existing_requires_force() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        let err = cp(
+            &cp_cmd(("tmux", "work"), ("tmux", "home"), false),
+            &registry,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RobeError::AlreadyExists(_)));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("tmux").join("home.conf"))?,
+            "tmux home"
+        );
+
+        cp(&cp_cmd(("tmux", "work"), ("tmux", "home"), true), &registry)?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("tmux").join("home.conf"))?,
+            "tmux work"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cp_refuses_other_kind_and_itself() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        let err = cp(
+            &cp_cmd(("tmux", "work"), ("nvim", "tmux"), false),
+            &registry,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+        assert!(!dir.path().join("nvim").join("tmux").exists());
+
+        let err = cp(&cp_cmd(("tmux", "work"), ("tmux", "work"), true), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+
+        let err = cp(&cp_cmd(("tmux", "none"), ("tmux", "x"), false), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cp_refuses_to_overwrite_linked_profile() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let mut registry = setup(dir.path());
+        let tmux = registry.targets.get_mut("tmux").unwrap();
+        tmux.real_path = dir.path().join("tmux.conf");
+        std::os::unix::fs::symlink(dir.path().join("tmux").join("home.conf"), &tmux.real_path)?;
+
+        let err = cp(&cp_cmd(("tmux", "work"), ("tmux", "home"), true), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("tmux.conf"))?,
+            "tmux home"
+        );
+        Ok(())
+    }
+}
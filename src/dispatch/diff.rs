@@ -0,0 +1,798 @@
+use crate::domain::{Diff, DiffSide};
+use crate::errors::RobeError;
+use crate::ignore::Ignore;
+use crate::registry::{Apply, Registry};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONTEXT: usize = 3;
+/// Past this many edits the diff gives up on a minimal result and replaces the whole file.
+const MAX_EDIT_DISTANCE: usize = 4096;
+
+/// Compare two profiles, or a profile against the live config.
+///
+/// Behaviour:
+/// file targets → unified diff
+/// dir targets  → added/removed/changed listing followed by a unified diff per file
+/// `--stat` prints a summary of insertions and deletions instead of the diffs
+/// entries matched by the ignore patterns of their target are left out
+/// files only in a live dir whose last profile was overlaid, and not part of that profile,
+/// are listed as unmanaged instead of added or removed
+pub fn diff(cmd: &Diff, registry: &Registry) -> Result<(), RobeError> {
+    let from = resolve(&cmd.from, registry)?;
+    let to = resolve(&cmd.to, registry)?;
+
+    let from_dir = from.path.is_dir();
+    let to_dir = to.path.is_dir();
+    if from.path.exists() && to.path.exists() && from_dir != to_dir {
+        return Err(RobeError::Conflict(format!(
+            "Cannot diff {} against {}: one is a file and the other a directory.",
+            from.label, to.label
+        )));
+    }
+
+    let out = if from_dir || to_dir {
+        diff_dirs(&from, &to, cmd.stat)?
+    } else {
+        let fd = diff_file(&from.path, &to.path)?;
+        if cmd.stat {
+            format_stat(&[(to.label.clone(), &fd)])
+        } else {
+            format_file_diff(&from.label, &to.label, &fd)
+        }
+    };
+
+    print!("{}", out);
+
+    Ok(())
+}
+
+/// One side of the diff.
+struct Side {
+    path: PathBuf,
+    label: String,
+    /// the ignore patterns of its target
+    ignore: Ignore,
+    /// for a live dir its last profile was overlaid on, what that profile manages
+    managed: Option<Ignore>,
+}
+
+impl Side {
+    fn new(path: &Path, label: &str, ignore: Ignore) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            label: label.to_string(),
+            ignore,
+            managed: None,
+        }
+    }
+
+    fn is_unmanaged(&self, rel: &Path) -> bool {
+        self.managed
+            .as_ref()
+            .is_some_and(|managed| managed.is_ignored(rel, false))
+    }
+}
+
+fn resolve(side: &DiffSide, registry: &Registry) -> Result<Side, RobeError> {
+    let tr = registry.target_registry(&side.target)?;
+    let ignore = Ignore::for_target(&tr)?;
+    match &side.profile {
+        Some(profile) => {
+            tr.assert_profile_exists(profile)?;
+            let label = format!("{}/{}", side.target, profile);
+            Ok(Side::new(
+                &registry.profile_path(&tr, profile),
+                &label,
+                ignore,
+            ))
+        }
+        None => {
+            let mut live = Side::new(&tr.real_path, &tr.real_path.display().to_string(), ignore);
+            if let Some(state) = &tr.state
+                && state.apply == Apply::Overlay
+            {
+                live.managed = Some(Ignore::for_state(registry, &tr, state)?);
+            }
+            Ok(live)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, PartialEq)]
+enum FileDiff {
+    Text {
+        old: Vec<String>,
+        new: Vec<String>,
+        edits: Vec<Edit>,
+    },
+    Binary {
+        same: bool,
+    },
+}
+
+impl FileDiff {
+    fn is_same(&self) -> bool {
+        match self {
+            Self::Text { edits, .. } => edits.iter().all(|e| matches!(e, Edit::Equal(..))),
+            Self::Binary { same } => *same,
+        }
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        match self {
+            Self::Text { edits, .. } => edits.iter().fold((0, 0), |(i, d), e| match e {
+                Edit::Insert(_) => (i + 1, d),
+                Edit::Delete(_) => (i, d + 1),
+                Edit::Equal(..) => (i, d),
+            }),
+            Self::Binary { .. } => (0, 0),
+        }
+    }
+}
+
+fn diff_file(from: &Path, to: &Path) -> Result<FileDiff, RobeError> {
+    Ok(diff_bytes(read_or_empty(from)?, read_or_empty(to)?))
+}
+
+fn diff_bytes(old: Vec<u8>, new: Vec<u8>) -> FileDiff {
+    match (String::from_utf8(old), String::from_utf8(new)) {
+        (Ok(old), Ok(new)) => {
+            let old = split_lines(&old);
+            let new = split_lines(&new);
+            let edits = diff_lines(&old, &new);
+            FileDiff::Text { old, new, edits }
+        }
+        (old, new) => {
+            let old = old.map(String::into_bytes).unwrap_or_else(|e| e.into_bytes());
+            let new = new.map(String::into_bytes).unwrap_or_else(|e| e.into_bytes());
+            FileDiff::Binary { same: old == new }
+        }
+    }
+}
+
+/// Contents of an entry inside a dir; a symlink reads as the path it points to.
+fn read_entry(path: &Path) -> Result<Vec<u8>, RobeError> {
+    match fs::read_link(path) {
+        Ok(dest) => Ok(format!("-> {}\n", dest.display()).into_bytes()),
+        Err(_) => read_or_empty(path),
+    }
+}
+
+fn read_or_empty(path: &Path) -> Result<Vec<u8>, RobeError> {
+    if path.exists() {
+        fs::read(path).map_err(RobeError::io("read", path))
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn split_lines(s: &str) -> Vec<String> {
+    s.split_inclusive('\n').map(str::to_string).collect()
+}
+
+/// Compare the dirs of `from` and `to`, each without the entries its patterns ignore.
+fn diff_dirs(from: &Side, to: &Side, stat: bool) -> Result<String, RobeError> {
+    let from_files = collect_files(&from.path, &from.ignore)?;
+    let to_files = collect_files(&to.path, &to.ignore)?;
+
+    let mut rel_paths: Vec<&PathBuf> = from_files.keys().chain(to_files.keys()).collect();
+    rel_paths.sort();
+    rel_paths.dedup();
+
+    let mut changes: Vec<(String, Change, FileDiff)> = Vec::new();
+    let mut unmanaged: Vec<String> = Vec::new();
+    for rel in rel_paths {
+        let change = match (from_files.contains_key(rel), to_files.contains_key(rel)) {
+            (true, false) if from.is_unmanaged(rel) => None,
+            (false, true) if to.is_unmanaged(rel) => None,
+            (true, false) => Some(Change::Removed),
+            (false, true) => Some(Change::Added),
+            _ => Some(Change::Changed),
+        };
+        let Some(change) = change else {
+            unmanaged.push(rel.to_string_lossy().to_string());
+            continue;
+        };
+        let fd = diff_bytes(
+            read_entry(&from.path.join(rel))?,
+            read_entry(&to.path.join(rel))?,
+        );
+        if change != Change::Changed || !fd.is_same() {
+            changes.push((rel.to_string_lossy().to_string(), change, fd));
+        }
+    }
+
+    if stat {
+        let stats: Vec<_> = changes.iter().map(|(p, _, fd)| (p.clone(), fd)).collect();
+        return Ok(format_stat(&stats));
+    }
+
+    let mut out = "".to_string();
+    for (rel, change, _) in &changes {
+        let label = match change {
+            Change::Added => "added:  ",
+            Change::Removed => "removed:",
+            Change::Changed => "changed:",
+        };
+        out.push_str(&format!("{} {}\n", label, rel));
+    }
+    for rel in &unmanaged {
+        out.push_str(&format!("unmanaged: {}\n", rel));
+    }
+    for (rel, _, fd) in &changes {
+        out.push('\n');
+        out.push_str(&format_file_diff(
+            &format!("{}/{}", from.label, rel),
+            &format!("{}/{}", to.label, rel),
+            fd,
+        ));
+    }
+    Ok(out)
+}
+
+/// Regular files and symlinks below `root` but for those `ignore` leaves alone,
+/// keyed by their path relative to `root`.
+pub fn collect_files(
+    root: &Path,
+    ignore: &Ignore,
+) -> Result<BTreeMap<PathBuf, PathBuf>, RobeError> {
+    let mut files = BTreeMap::new();
+    if root.is_dir() {
+        collect_files_rec(root, Path::new(""), ignore, &mut files)?;
+    }
+    Ok(files)
+}
+
+fn collect_files_rec(
+    dir: &Path,
+    rel: &Path,
+    ignore: &Ignore,
+    files: &mut BTreeMap<PathBuf, PathBuf>,
+) -> Result<(), RobeError> {
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
+        let rel_path = rel.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(RobeError::io("read the metadata of", &entry.path()))?;
+        if ignore.is_ignored(&rel_path, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            collect_files_rec(&entry.path(), &rel_path, ignore, files)?;
+        } else {
+            files.insert(rel_path, entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn format_stat(files: &[(String, &FileDiff)]) -> String {
+    let mut out = "".to_string();
+    let width = files.iter().map(|(p, _)| p.len()).max().unwrap_or(0);
+    let mut changed = 0;
+    let mut insertions = 0;
+    let mut deletions = 0;
+
+    for (path, fd) in files {
+        if fd.is_same() {
+            continue;
+        }
+        changed += 1;
+        match fd {
+            FileDiff::Binary { .. } => {
+                out.push_str(&format!(" {:width$} | binary\n", path, width = width));
+            }
+            FileDiff::Text { .. } => {
+                let (i, d) = fd.counts();
+                insertions += i;
+                deletions += d;
+                out.push_str(&format!(" {:width$} | +{} -{}\n", path, i, d, width = width));
+            }
+        }
+    }
+
+    out.push_str(&format!(
+        " {} file{} changed, {} insertion{}(+), {} deletion{}(-)\n",
+        changed,
+        plural(changed),
+        insertions,
+        plural(insertions),
+        deletions,
+        plural(deletions)
+    ));
+    out
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "" } else { "s" }
+}
+
+fn format_file_diff(from_label: &str, to_label: &str, fd: &FileDiff) -> String {
+    match fd {
+        FileDiff::Binary { same: true } => "".to_string(),
+        FileDiff::Binary { same: false } => {
+            format!("Binary files {} and {} differ\n", from_label, to_label)
+        }
+        FileDiff::Text { old, new, edits } => {
+            if fd.is_same() {
+                return "".to_string();
+            }
+            let mut out = format!("--- {}\n+++ {}\n", from_label, to_label);
+            for hunk in hunks(edits) {
+                out.push_str(&format_hunk(old, new, hunk));
+            }
+            out
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Groups edits into hunks of changes surrounded by up to `CONTEXT` equal lines.
+fn hunks(edits: &[Edit]) -> Vec<&[Edit]> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(CONTEXT);
+        let mut end = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - end <= 2 * CONTEXT {
+            i += 1;
+            end = changes[i];
+        }
+        out.push(&edits[start..(end + CONTEXT + 1).min(edits.len())]);
+        i += 1;
+    }
+    out
+}
+
+fn format_hunk(old: &[String], new: &[String], hunk: &[Edit]) -> String {
+    let old_start = hunk.iter().find_map(|e| match e {
+        Edit::Equal(o, _) | Edit::Delete(o) => Some(*o),
+        Edit::Insert(_) => None,
+    });
+    let new_start = hunk.iter().find_map(|e| match e {
+        Edit::Equal(_, n) | Edit::Insert(n) => Some(*n),
+        Edit::Delete(_) => None,
+    });
+    let old_count = hunk.iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+    let new_count = hunk.iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+
+    // an empty side is reported at the line before the hunk, as diff(1) does
+    let old_start = old_start.map(|s| s + 1).unwrap_or_else(|| insert_point(hunk, true));
+    let new_start = new_start.map(|s| s + 1).unwrap_or_else(|| insert_point(hunk, false));
+
+    let mut out = format!(
+        "@@ -{},{} +{},{} @@\n",
+        old_start, old_count, new_start, new_count
+    );
+    for e in hunk {
+        let (prefix, line) = match e {
+            Edit::Equal(o, _) => (' ', &old[*o]),
+            Edit::Delete(o) => ('-', &old[*o]),
+            Edit::Insert(n) => ('+', &new[*n]),
+        };
+        out.push(prefix);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    out
+}
+
+/// Number of lines before a hunk that only touches the other side.
+fn insert_point(hunk: &[Edit], old_side: bool) -> usize {
+    match hunk.first() {
+        Some(Edit::Insert(n)) if old_side => *n,
+        Some(Edit::Delete(o)) if !old_side => *o,
+        _ => 0,
+    }
+}
+
+/// Shortest edit script between `a` and `b` (Myers, 1986).
+fn diff_lines(a: &[String], b: &[String]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    for e in myers(a_mid, b_mid) {
+        edits.push(match e {
+            Edit::Equal(o, n) => Edit::Equal(o + prefix, n + prefix),
+            Edit::Delete(o) => Edit::Delete(o + prefix),
+            Edit::Insert(n) => Edit::Insert(n + prefix),
+        });
+    }
+    for i in 0..suffix {
+        edits.push(Edit::Equal(a.len() - suffix + i, b.len() - suffix + i));
+    }
+    edits
+}
+
+fn myers(a: &[String], b: &[String]) -> Vec<Edit> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = (n + m) as usize;
+    if max > 2 * MAX_EDIT_DISTANCE {
+        return replace_all(a, b);
+    }
+
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // trace[d] holds v for diagonals -d..=d before round d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        if d as usize > MAX_EDIT_DISTANCE {
+            return replace_all(a, b);
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    let mut edits = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d) as usize];
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let px = at(prev_k);
+            (px, px - prev_k)
+        };
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                edits.push(Edit::Insert(y as usize));
+            } else {
+                x -= 1;
+                edits.push(Edit::Delete(x as usize));
+            }
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+fn replace_all(a: &[String], b: &[String]) -> Vec<Edit> {
+    (0..a.len())
+        .map(Edit::Delete)
+        .chain((0..b.len()).map(Edit::Insert))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::TargetRegistry;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    fn side(path: &Path, label: &str) -> Side {
+        Side::new(path, label, Ignore::default())
+    }
+
+    fn lines(s: &str) -> Vec<String> {
+        split_lines(s)
+    }
+
+    fn apply(a: &[String], b: &[String], edits: &[Edit]) -> (Vec<String>, Vec<String>) {
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for e in edits {
+            match e {
+                Edit::Equal(o, n) => {
+                    assert_eq!(a[*o], b[*n]);
+                    old.push(a[*o].clone());
+                    new.push(b[*n].clone());
+                }
+                Edit::Delete(o) => old.push(a[*o].clone()),
+                Edit::Insert(n) => new.push(b[*n].clone()),
+            }
+        }
+        (old, new)
+    }
+
+    #[test]
+    fn test_diff_lines_reconstructs_both_sides() {
+        let cases = [
+            ("", ""),
+            ("a\n", ""),
+            ("", "a\n"),
+            ("a\nb\nc\n", "a\nc\n"),
+            ("a\nb\nc\na\nb\nb\na\n", "c\nb\na\nb\na\nc\n"),
+            ("x\ny\n", "y\nx\n"),
+        ];
+        for (a, b) in cases {
+            let (a, b) = (lines(a), lines(b));
+            let edits = diff_lines(&a, &b);
+            assert_eq!(apply(&a, &b, &edits), (a.clone(), b.clone()));
+        }
+    }
+
+    #[test]
+    fn test_diff_lines_is_minimal() {
+        let a = lines("a\nb\nc\na\nb\nb\na\n");
+        let b = lines("c\nb\na\nb\na\nc\n");
+        let changes = diff_lines(&a, &b)
+            .iter()
+            .filter(|e| !matches!(e, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+    }
+
+    #[test]
+    fn test_format_file_diff() {
+        let old = lines("one\ntwo\nthree\n");
+        let new = lines("one\n2\nthree\n");
+        let edits = diff_lines(&old, &new);
+        let fd = FileDiff::Text { old, new, edits };
+
+        let expected = "\
+--- tmux/a
++++ tmux/b
+@@ -1,3 +1,3 @@
+ one
+-two
++2
+ three
+";
+        assert_eq!(format_file_diff("tmux/a", "tmux/b", &fd), expected);
+    }
+
+    #[test]
+    fn test_format_file_diff_splits_distant_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                18 => "eighteen\n".to_string(),
+                i => format!("{}\n", i),
+            })
+            .collect();
+        let (old, new) = (lines(&old), lines(&new));
+        let edits = diff_lines(&old, &new);
+        let fd = FileDiff::Text { old, new, edits };
+
+        let out = format_file_diff("a", "b", &fd);
+
+        assert!(out.contains("@@ -1,5 +1,5 @@\n"));
+        assert!(out.contains("@@ -15,6 +15,6 @@\n"));
+    }
+
+    #[test]
+    fn test_format_file_diff_empty_side_and_missing_newline() {
+        let old = lines("");
+        let new = lines("a\nb");
+        let edits = diff_lines(&old, &new);
+        let fd = FileDiff::Text { old, new, edits };
+
+        let expected = "\
+--- a
++++ b
+@@ -0,0 +1,2 @@
++a
++b
+\\ No newline at end of file
+";
+        assert_eq!(format_file_diff("a", "b", &fd), expected);
+    }
+
+    #[test]
+    fn test_format_stat() {
+        let old = lines("a\nb\n");
+        let new = lines("a\nc\nd\n");
+        let edits = diff_lines(&old, &new);
+        let fd = FileDiff::Text { old, new, edits };
+
+        let out = format_stat(&[("init.lua".to_string(), &fd)]);
+
+        assert_eq!(
+            out,
+            " init.lua | +2 -1\n 1 file changed, 2 insertions(+), 1 deletion(-)\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_dirs_lists_changes() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(a.join("lua"))?;
+        fs::create_dir_all(b.join("lua"))?;
+        fs::write(a.join("init.lua"), "print(1)\n")?;
+        fs::write(b.join("init.lua"), "print(2)\n")?;
+        fs::write(a.join("lua").join("same.lua"), "x\n")?;
+        fs::write(b.join("lua").join("same.lua"), "x\n")?;
+        fs::write(a.join("old.lua"), "old\n")?;
+        fs::write(b.join("lua").join("new.lua"), "new\n")?;
+
+        let out = diff_dirs(&side(&a, "nvim/a"), &side(&b, "nvim/b"), false)?;
+
+        assert!(out.contains("changed: init.lua\n"));
+        assert!(out.contains("added:   lua/new.lua\n"));
+        assert!(out.contains("removed: old.lua\n"));
+        assert!(!out.contains("same.lua"));
+        assert!(out.contains("--- nvim/a/init.lua\n+++ nvim/b/init.lua\n"));
+        assert!(out.contains("-print(1)\n+print(2)\n"));
+
+        let stat = diff_dirs(&side(&a, "nvim/a"), &side(&b, "nvim/b"), true)?;
+        assert!(stat.contains(" 3 files changed, 2 insertions(+), 2 deletions(-)\n"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_diff_dirs_compares_symlinks() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(a.join("lua"))?;
+        fs::create_dir_all(b.join("lua"))?;
+        std::os::unix::fs::symlink("lua", a.join("modules"))?;
+        std::os::unix::fs::symlink("lua", b.join("modules"))?;
+        std::os::unix::fs::symlink("a.lua", a.join("init.lua"))?;
+        std::os::unix::fs::symlink("b.lua", b.join("init.lua"))?;
+
+        let out = diff_dirs(&side(&a, "nvim/a"), &side(&b, "nvim/b"), false)?;
+
+        assert!(!out.contains("modules"));
+        assert!(out.contains("changed: init.lua\n"));
+        assert!(out.contains("--> a.lua\n+-> b.lua\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_dirs_leaves_out_ignored_entries() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(a.join(".git"))?;
+        fs::create_dir_all(&b)?;
+        fs::write(a.join(".git").join("HEAD"), "main\n")?;
+        fs::write(a.join("lazy-lock.json"), "{}\n")?;
+        fs::write(b.join("lazy-lock.json"), "{\"a\": 1}\n")?;
+        fs::write(b.join("init.lua"), "print(1)\n")?;
+        let ignore = Ignore::new([".git/", "lazy-lock.json"]);
+        let a = Side::new(&a, "nvim/a", ignore.clone());
+        let b = Side::new(&b, "nvim/b", ignore);
+
+        let out = diff_dirs(&a, &b, false)?;
+
+        assert_eq!(out.lines().next(), Some("added:   init.lua"));
+        assert!(!out.contains(".git"));
+        assert!(!out.contains("lazy-lock.json"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_dirs_lists_unmanaged_live_files() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let profile = dir.path().join("work");
+        let live = dir.path().join("live");
+        fs::create_dir_all(&profile)?;
+        fs::create_dir_all(live.join("plugins"))?;
+        fs::write(profile.join("init.lua"), "print(1)\n")?;
+        fs::write(profile.join("keys.lua"), "map()\n")?;
+        fs::write(live.join("init.lua"), "print(2)\n")?;
+        fs::write(live.join("plugins").join("data.json"), "{}\n")?;
+        let mut to = side(&live, "live");
+        to.managed = Some(Ignore::default().applied(Apply::Overlay, &profile));
+
+        let out = diff_dirs(&side(&profile, "nvim/work"), &to, false)?;
+
+        assert!(
+            out.starts_with("changed: init.lua\nremoved: keys.lua\nunmanaged: plugins/data.json\n")
+        );
+        assert!(!out.contains("+++ live/plugins"));
+
+        let stat = diff_dirs(&side(&profile, "nvim/work"), &to, true)?;
+        assert!(!stat.contains("data.json"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_profile_against_live() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let wardrobe = dir.path().join("wardrobe");
+        fs::create_dir_all(wardrobe.join("tmux"))?;
+        fs::write(wardrobe.join("tmux").join("work"), "a\n")?;
+        let real = dir.path().join("tmux.conf");
+        fs::write(&real, "b\n")?;
+
+        let mut targets = HashMap::new();
+        targets.insert(
+            "tmux".to_string(),
+            TargetRegistry {
+                name: "tmux".to_string(),
+                real_path: real,
+                profiles: vec!["work".to_string()],
+                ..Default::default()
+            },
+        );
+        let registry = Registry {
+            base_path: wardrobe,
+            targets,
+        };
+
+        let side = |profile: Option<&str>| DiffSide {
+            target: "tmux".to_string(),
+            profile: profile.map(str::to_string),
+        };
+
+        let cmd = Diff {
+            from: side(Some("work")),
+            to: side(None),
+            stat: false,
+        };
+        assert!(diff(&cmd, &registry).is_ok());
+
+        let cmd = Diff {
+            from: side(Some("missing")),
+            to: side(None),
+            stat: false,
+        };
+        assert!(diff(&cmd, &registry).is_err());
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::dispatch::{hooks, io};
 use crate::domain::Edit;
 use crate::errors::RobeError;
 use crate::registry::Registry;
@@ -16,27 +17,36 @@ where F: Fn(&str, &str) -> Result<(), RobeError> {
     let fp: PathBuf = match &cmd.profile {
         Some(profile) => {
             target_registry.assert_profile_exists(profile)?;
-            registry.base_path.join(&cmd.target).join(profile)
+            registry.profile_path(&target_registry, profile)
         }
-        None => target_registry.real_path.clone(),
+        // edit a linked profile directly, so editors that replace files on save keep the link
+        None => match io::linked_profile(registry, &target_registry) {
+            Some(profile) => registry.profile_path(&target_registry, &profile),
+            None => target_registry.real_path.clone(),
+        },
     };
 
     let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".into());
 
-    runner(&editor, &fp.to_string_lossy().to_string())?;
+    runner(&editor, &fp.to_string_lossy())?;
 
-    Ok(())
+    // the edits are kept either way, the user fixes them with another edit
+    hooks::validate(&target_registry, &fp)
 }
 
 pub fn edit(cmd: &Edit, registry: &Registry) -> Result<(), RobeError> {
     edit_with_runner(cmd, registry, |editor, path| {
         let args = vec![path];
-        Command::new(editor).args(args).status()?;
+        Command::new(editor)
+            .args(args)
+            .status()
+            .map_err(RobeError::io("run", std::path::Path::new(editor)))?;
         Ok(())
     })
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
     use tempfile::tempdir;
@@ -58,6 +68,7 @@ mod tests {
                 name: "myconfig".into(),
                 real_path: file_path.clone(),
                 profiles: vec!["default".into()],
+                ..Default::default()
             },
         );
 
@@ -94,6 +105,7 @@ mod tests {
                 name: "myconfig".into(),
                 real_path: real_file_path.clone(),
                 profiles: vec!["default".into()],
+                ..Default::default()
             },
         );
 
@@ -130,6 +142,7 @@ mod tests {
                 name: "myconfig".into(),
                 real_path: file_path.clone(),
                 profiles: vec![],
+                ..Default::default()
             },
         );
 
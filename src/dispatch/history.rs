@@ -0,0 +1,110 @@
+use crate::dispatch::io;
+use crate::domain::{Format, History};
+use crate::errors::RobeError;
+use crate::ignore::Ignore;
+use crate::registry::Registry;
+use crate::utils;
+use serde::Serialize;
+
+/// List the earlier versions of a profile, most recent first.
+///
+/// Behaviour:
+/// list `path/to/wardrobe/<target>/.history/<profile>`, numbered from 1 for the most recent
+/// show when each version was taken, its size and its fingerprint
+pub fn history(cmd: &History, registry: &Registry, format: Format) -> Result<(), RobeError> {
+    let target_registry = registry.target_registry(&cmd.target)?;
+    target_registry.assert_profile_exists(&cmd.profile)?;
+
+    let mut versions = Vec::new();
+    let stored = io::list_versions(registry, &target_registry, &cmd.profile)?;
+    for (i, version) in stored.iter().rev().enumerate() {
+        versions.push(VersionJson {
+            n: i + 1,
+            path: version.path.to_string_lossy().to_string(),
+            created_at: version.created_at / 1000,
+            size: io::size(&version.path)?,
+            hash: io::fingerprint(&version.path, &Ignore::default())?,
+        });
+    }
+
+    let history = HistoryJson {
+        target: cmd.target.clone(),
+        profile: cmd.profile.clone(),
+        versions,
+    };
+    if format == Format::Json {
+        println!("{}", serde_json::to_string(&history)?);
+    } else {
+        print!("{}", format_history(&history));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryJson {
+    target: String,
+    profile: String,
+    versions: Vec<VersionJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct VersionJson {
+    /// 1 for the most recent version
+    n: usize,
+    path: String,
+    /// seconds since the unix epoch
+    created_at: u64,
+    size: u64,
+    hash: String,
+}
+
+fn format_history(history: &HistoryJson) -> String {
+    if history.versions.is_empty() {
+        return format!(
+            "No earlier versions of {}/{}.\n",
+            history.target, history.profile
+        );
+    }
+    let mut out = format!("History of {}/{}:\n", history.target, history.profile);
+    for v in &history.versions {
+        out.push_str(&format!(
+            "  @{:<3} {}  {}  {} bytes  {}\n",
+            v.n,
+            v.created_at,
+            utils::format_timestamp(v.created_at),
+            v.size,
+            v.hash
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_history() {
+        let mut history = HistoryJson {
+            target: "tmux".to_string(),
+            profile: "work".to_string(),
+            versions: Vec::new(),
+        };
+        assert_eq!(
+            format_history(&history),
+            "No earlier versions of tmux/work.\n"
+        );
+
+        history.versions.push(VersionJson {
+            n: 1,
+            path: "/w/tmux/.history/work/0000000042000".to_string(),
+            created_at: 42,
+            size: 15,
+            hash: "0123456789abcdef".to_string(),
+        });
+        assert_eq!(
+            format_history(&history),
+            "History of tmux/work:\n  @1   42  1970-01-01 00:00:42 UTC  15 bytes  0123456789abcdef\n"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::errors::RobeError;
+use crate::registry::{Event, Registry, TargetRegistry};
+use std::path::Path;
+use std::process::Command;
+
+/// Run the hooks of `target_registry` for `event` on `profile`: those of the target first,
+/// then those of the profile, each through the shell in the current directory.
+///
+/// The hooks find `ROBE_EVENT`, `ROBE_TARGET`, `ROBE_PROFILE`, `ROBE_REAL_PATH` and
+/// `ROBE_PROFILE_PATH` in their environment. A failing `pre-` hook fails the command before
+/// anything is changed; a failing `post-` hook is only a warning, as the command is done.
+pub fn run(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+    event: Event,
+) -> Result<(), RobeError> {
+    for hook in target_registry.hooks_for(profile, event) {
+        let status = shell(hook)
+            .env("ROBE_EVENT", event.name())
+            .env("ROBE_TARGET", &target_registry.name)
+            .env("ROBE_PROFILE", profile)
+            .env("ROBE_REAL_PATH", &target_registry.real_path)
+            .env(
+                "ROBE_PROFILE_PATH",
+                registry.profile_path(target_registry, profile),
+            )
+            .status();
+        let failure = match status {
+            Ok(status) if status.success() => continue,
+            Ok(status) => format!(
+                "The {} hook of {}/{} failed with {}: {}",
+                event.name(),
+                &target_registry.name,
+                profile,
+                status,
+                hook
+            ),
+            Err(e) => format!(
+                "The {} hook of {}/{} could not be run: {}",
+                event.name(),
+                &target_registry.name,
+                profile,
+                e
+            ),
+        };
+        if event.is_pre() {
+            return Err(RobeError::Hook(failure));
+        }
+        eprintln!("robe: warning: {}", failure);
+    }
+    Ok(())
+}
+
+/// Check the config at `path` with the `validate` command of `target_registry`, if it has one,
+/// `{path}` standing for `path`. Fails with the output of the command if it rejects the config.
+pub fn validate(target_registry: &TargetRegistry, path: &Path) -> Result<(), RobeError> {
+    let Some(validate) = &target_registry.validate else {
+        return Ok(());
+    };
+    let command = validate.replace("{path}", &quote(path));
+    let output = shell(&command)
+        .env("ROBE_TARGET", &target_registry.name)
+        .env("ROBE_REAL_PATH", &target_registry.real_path)
+        .output()
+        .map_err(|e| {
+            RobeError::Invalid(format!(
+                "The validate command of {} could not be run: {}",
+                &target_registry.name, e
+            ))
+        })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut message = format!(
+        "The validate command of {} failed with {}: {}",
+        &target_registry.name, output.status, command
+    );
+    for out in [&output.stdout, &output.stderr] {
+        let out = String::from_utf8_lossy(out);
+        if !out.trim().is_empty() {
+            message.push('\n');
+            message.push_str(out.trim_end());
+        }
+    }
+    Err(RobeError::Invalid(message))
+}
+
+#[cfg(unix)]
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy())
+}
+
+#[cfg(unix)]
+fn shell(hook: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(hook);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(hook: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(hook);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::registry::Hooks;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn target(dir: &std::path::Path, hooks: Hooks, work: Hooks) -> (Registry, TargetRegistry) {
+        let registry = Registry {
+            base_path: dir.join("wardrobe"),
+            ..Default::default()
+        };
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: dir.join("tmux.conf"),
+            profiles: vec!["work".to_string()],
+            hooks,
+            profile_hooks: BTreeMap::from([("work".to_string(), work)]),
+            ..Default::default()
+        };
+        (registry, tr)
+    }
+
+    #[test]
+    fn test_run_passes_environment_in_order() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let log = dir.path().join("log");
+        let hooks = Hooks {
+            post_use: Some(format!(
+                "echo \"$ROBE_EVENT $ROBE_TARGET $ROBE_PROFILE\" >> {}",
+                log.display()
+            )),
+            ..Default::default()
+        };
+        let work = Hooks {
+            post_use: Some(format!(
+                "echo \"$ROBE_REAL_PATH $ROBE_PROFILE_PATH\" >> {}",
+                log.display()
+            )),
+            ..Default::default()
+        };
+        let (registry, tr) = target(dir.path(), hooks, work);
+
+        run(&registry, &tr, "work", Event::PostUse)?;
+        run(&registry, &tr, "work", Event::PreUse)?;
+
+        let expected = format!(
+            "post-use tmux work\n{} {}\n",
+            tr.real_path.display(),
+            registry.base_path.join("tmux").join("work").display()
+        );
+        assert_eq!(fs::read_to_string(&log)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_pre_hook_fails() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let hooks = Hooks {
+            pre_add: Some("exit 3".to_string()),
+            post_add: Some("exit 3".to_string()),
+            ..Default::default()
+        };
+        let (registry, tr) = target(dir.path(), hooks, Hooks::default());
+
+        let err = run(&registry, &tr, "work", Event::PreAdd).unwrap_err();
+        assert!(matches!(err, RobeError::Hook(_)));
+        assert!(format!("{}", err).contains("The pre-add hook of tmux/work failed"));
+
+        run(&registry, &tr, "work", Event::PostAdd)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_substitutes_path() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (_, mut tr) = target(dir.path(), Hooks::default(), Hooks::default());
+        let config = dir.path().join("it's.conf");
+        fs::write(&config, "listen 80;")?;
+
+        validate(&tr, &config)?;
+
+        tr.validate = Some("grep -q listen {path}".to_string());
+        validate(&tr, &config)?;
+
+        tr.validate = Some("echo bad line 3 >&2; false {path}".to_string());
+        let err = validate(&tr, &config).unwrap_err();
+        assert!(matches!(err, RobeError::Invalid(_)));
+        assert!(format!("{}", err).ends_with("\nbad line 3"));
+        Ok(())
+    }
+}
@@ -1,190 +1,1867 @@
 use crate::errors::RobeError;
-use crate::registry::{Registry, TargetMetadata, TargetRegistry};
+use crate::ignore::Ignore;
+use crate::registry::{
+    Apply, BACKUP_DIR, META_FILE, Registry, STATE_FILE, TargetMetadata, TargetRegistry, TargetState,
+};
+use crate::utils;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
-pub fn copy_file(from: &PathBuf, to: &PathBuf) -> Result<(), RobeError> {
-    fs::copy(from, to)?;
+/// How a directory is copied.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyOptions<'a> {
+    /// copy what symlinks inside the directory point to, instead of the links themselves
+    pub dereference: bool,
+    /// entries not copied
+    pub ignore: Option<&'a Ignore>,
+    /// leave the entries of the destination `ignore` matches as they are, as in a live config;
+    /// a profile being saved drops them instead
+    pub keep_ignored: bool,
+}
+
+impl<'a> CopyOptions<'a> {
+    fn ignores(&self, rel: &Path, is_dir: bool) -> bool {
+        self.ignore
+            .is_some_and(|ignore| ignore.is_ignored(rel, is_dir))
+    }
+
+    /// The ignore patterns whose entries are kept in the destination, if any.
+    fn kept(&self) -> Option<&'a Ignore> {
+        self.ignore.filter(|_| self.keep_ignored)
+    }
+}
+
+/// What the user should know about a copy that succeeded.
+#[derive(Debug, Default)]
+pub struct CopyReport {
+    /// symlinks pointing outside of the copied dir, whose targets were not copied
+    pub outside_links: Vec<String>,
+    /// permissions or modification times that could not be carried over,
+    /// and fifos, sockets or devices left out
+    pub not_preserved: Vec<String>,
+}
+
+impl CopyReport {
+    pub fn print_warnings(&self) {
+        for warning in self.outside_links.iter().chain(&self.not_preserved) {
+            eprintln!("robe: warning: {}", warning);
+        }
+    }
+
+    /// Only the metadata warnings, for copies out of the wardrobe whose links
+    /// were already reported when they were captured.
+    pub fn print_metadata_warnings(&self) {
+        for warning in &self.not_preserved {
+            eprintln!("robe: warning: {}", warning);
+        }
+    }
+}
+
+pub fn copy_file(from: &Path, to: &Path) -> Result<(), RobeError> {
+    fs::copy(from, to).map_err(RobeError::io(&format!("copy {} to", from.display()), to))?;
+    Ok(())
+}
+
+const STAGED_SUFFIX: &str = "robe-new";
+const REPLACED_SUFFIX: &str = "robe-old";
+const JOURNAL_SUFFIX: &str = "robe-journal";
+/// a journal still being written, while `to` is untouched
+const UNFINISHED_JOURNAL_SUFFIX: &str = "robe-journal-new";
+/// the entries of a live directory the staged copy replaces, while it is moved in
+const ASIDE_SUFFIX: &str = "robe-aside";
+/// the entries moved in so far, one relative path per line
+const MERGED_SUFFIX: &str = "robe-merged";
+
+/// Replace the file/dir at `to` with a copy of `from`.
+///
+/// The copy is staged next to `to` and renamed into place, so `to` holds either the old or
+/// the new contents, never a mix. When no sibling can be staged, or the rename crosses a
+/// filesystem boundary (e.g. `to` is a mount point), the copy is done in place instead with
+/// a journal of the old contents that is rolled back on failure, or by the next replace of
+/// `to` if the copy was interrupted.
+///
+/// Symlinks inside a directory are copied as symlinks, unless `opts.dereference` is set.
+pub fn replace_file_or_dir(
+    from: &Path,
+    to: &Path,
+    opts: &CopyOptions,
+) -> Result<CopyReport, RobeError> {
+    replace_file_or_dir_checked(from, to, opts, |_| Ok(()))
+}
+
+/// `replace_file_or_dir`, running `check` on the staged copy right before it is swapped in.
+/// `to` is left untouched if `check` fails. Without a staged copy, `from` is checked instead.
+/// With `opts.keep_ignored`, the entries of the directory `to` that `opts.ignore` leaves alone
+/// stay where they are: only the others are replaced, see `merge_into_place`.
+pub fn replace_file_or_dir_checked<F>(
+    from: &Path,
+    to: &Path,
+    opts: &CopyOptions,
+    check: F,
+) -> Result<CopyReport, RobeError>
+where
+    F: Fn(&Path) -> Result<(), RobeError>,
+{
+    if !from.is_file() && !from.is_dir() {
+        return Err(RobeError::NotFound(format!(
+            "{} is not a file or directory.",
+            from.display()
+        )));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(RobeError::io("create", parent))?;
+    }
+    recover_interrupted(to)?;
+
+    let staged = sibling(to, STAGED_SUFFIX);
+    let created = if from.is_dir() {
+        fs::create_dir(&staged)
+    } else {
+        fs::File::create(&staged).map(|_| ())
+    };
+    if created.is_err() {
+        check(from)?;
+        return journaled_replace(from, to, opts);
+    }
+
+    let mut report = match replace_in_place(from, &staged, opts).and_then(|report| {
+        check(&staged)?;
+        Ok(report)
+    }) {
+        Ok(report) => report,
+        Err(e) => {
+            remove_path(&staged).map_err(RobeError::io("remove", &staged))?;
+            return Err(e);
+        }
+    };
+
+    let swapped = match opts.kept() {
+        Some(ignore) if from.is_dir() && is_real_dir(to) => merge_into_place(&staged, to, ignore)
+            .map(|merged| {
+                report.not_preserved.extend(merged.not_preserved);
+            }),
+        _ => swap_into_place(&staged, to),
+    };
+    match swapped {
+        Ok(()) => Ok(report),
+        Err(e) if is_cross_device(&e) => {
+            remove_path(&staged).map_err(RobeError::io("remove", &staged))?;
+            journaled_replace(from, to, opts)
+        }
+        Err(e) => {
+            remove_path(&staged).map_err(RobeError::io("remove", &staged))?;
+            Err(RobeError::io("replace", to)(e))
+        }
+    }
+}
+
+/// Replace the file/dir at `to` with a symlink to `profile`, swapping it in like
+/// `replace_file_or_dir` does.
+pub fn link_into_place(profile: &Path, to: &Path) -> Result<(), RobeError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(RobeError::io("create", parent))?;
+    }
+    recover_interrupted(to)?;
+
+    let staged = sibling(to, STAGED_SUFFIX);
+    let original = std::path::absolute(profile).map_err(RobeError::io("resolve", profile))?;
+    symlink(&original, &staged, profile.is_dir())
+        .map_err(RobeError::io("create symlink", &staged))?;
+    if let Err(e) = swap_into_place(&staged, to) {
+        remove_path(&staged).map_err(RobeError::io("remove", &staged))?;
+        return Err(RobeError::io("replace", to)(e));
+    }
+    Ok(())
+}
+
+/// The profile `real_path` currently links to, if it is a symlink into the wardrobe.
+pub fn linked_profile(registry: &Registry, target_registry: &TargetRegistry) -> Option<String> {
+    let dest = fs::read_link(&target_registry.real_path).ok()?;
+    let dest = match target_registry.real_path.parent() {
+        Some(parent) if dest.is_relative() => parent.join(dest),
+        _ => dest,
+    };
+    let dest_canonical = dest.canonicalize().ok();
+
+    target_registry
+        .profiles
+        .iter()
+        .find(|p| {
+            let profile_path = registry.profile_path(target_registry, p);
+            profile_path == dest
+                || (dest_canonical.is_some() && profile_path.canonicalize().ok() == dest_canonical)
+        })
+        .cloned()
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path, _is_dir: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path, is_dir: bool) -> std::io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(original, link)
+    } else {
+        std::os::windows::fs::symlink_file(original, link)
+    }
+}
+
+fn replace_in_place(from: &Path, to: &Path, opts: &CopyOptions) -> Result<CopyReport, RobeError> {
+    if from.is_dir() {
+        return replace_dir_all(from, to, opts);
+    }
+    let mut copy = TreeCopy::new(from, opts)?;
+    copy.file(from, to, Path::new(""))?;
+    Ok(copy.report)
+}
+
+/// `<parent>/.<name>.<suffix>`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Whether `path` is a directory itself, not a symlink to one.
+fn is_real_dir(path: &Path) -> bool {
+    path.symlink_metadata().is_ok_and(|m| m.is_dir())
+}
+
+/// Move `staged` to `to`. A file is renamed straight over the old one, so `to` never goes
+/// missing; a directory cannot be, so the old one is kept aside until the new one is in place.
+fn swap_into_place(staged: &Path, to: &Path) -> std::io::Result<()> {
+    if !is_real_dir(staged) && !is_real_dir(to) {
+        return fs::rename(staged, to);
+    }
+
+    let replaced = sibling(to, REPLACED_SUFFIX);
+    let had_old = to.symlink_metadata().is_ok();
+
+    if had_old {
+        fs::rename(to, &replaced)?;
+    }
+    if let Err(e) = fs::rename(staged, to) {
+        if had_old {
+            fs::rename(&replaced, to)?;
+        }
+        return Err(e);
+    }
+    if had_old {
+        // the switch already happened; a leftover is cleaned up by the next replace
+        let _ = remove_path(&replaced);
+    }
     Ok(())
 }
 
-pub fn replace_file_or_dir(from: &PathBuf, to: &PathBuf) -> Result<(), RobeError> {
-    if from.is_file() {
-        copy_file(from, to)
-    } else if from.is_dir() {
-        replace_dir_all(from, to)
-    } else {
-        Err(RobeError::Internal(
-            "Robe does not allow symlinks.".to_string(),
-        ))
+/// Move the entries of the staged copy of a directory into the live directory `to`, leaving
+/// those of `to` that `ignore` matches where they are, however large or special they are.
+///
+/// The entries of `to` being replaced are first moved aside, then the staged ones are moved
+/// in, each logged before it is. If a move fails, or robe is interrupted before the staged
+/// copy is gone, the logged entries are removed and those set aside are moved back.
+fn merge_into_place(staged: &Path, to: &Path, ignore: &Ignore) -> std::io::Result<CopyReport> {
+    let aside = sibling(to, ASIDE_SUFFIX);
+    let merged = sibling(to, MERGED_SUFFIX);
+    let mut report = CopyReport::default();
+    let moved = fs::create_dir(&aside).and_then(|()| {
+        set_aside(to, &aside, Path::new(""), ignore)?;
+        let mut log = fs::File::create(&merged)?;
+        move_in(staged, to, Path::new(""), &mut log, &mut report)
+    });
+    if let Err(e) = moved {
+        if aside.exists()
+            && let Err(rollback) = unmerge(to)
+        {
+            return Err(std::io::Error::other(format!(
+                "{}; moving the previous entries back failed too: {}; they are kept in {}",
+                e,
+                rollback,
+                aside.display()
+            )));
+        }
+        return Err(e);
+    }
+    // from here on, an interruption keeps the new entries
+    remove_path(staged)?;
+    remove_path(&aside)?;
+    fs::remove_file(&merged)?;
+    Ok(report)
+}
+
+/// Move the entries below `dir` that `ignore` does not match to the same place below `aside`.
+/// A directory holding ignored entries stays, with only those left in it.
+fn set_aside(dir: &Path, aside: &Path, rel: &Path, ignore: &Ignore) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let rel_path = rel.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+        if ignore.is_ignored(&rel_path, is_dir) {
+            continue;
+        }
+        let dst = aside.join(entry.file_name());
+        if is_dir && holds_ignored(&path, &rel_path, ignore)? {
+            fs::create_dir(&dst)?;
+            set_aside(&path, &dst, &rel_path, ignore)?;
+        } else {
+            fs::rename(&path, &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `ignore` matches anything below the directory `dir`.
+fn holds_ignored(dir: &Path, rel: &Path, ignore: &Ignore) -> std::io::Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let rel_path = rel.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+        if ignore.is_ignored(&rel_path, is_dir)
+            || (is_dir && holds_ignored(&entry.path(), &rel_path, ignore)?)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Move the entries of `staged` into `dir`, logging each one to `log` first.
+/// The directories already in `dir` are merged into and given the metadata of the staged ones.
+fn move_in(
+    staged: &Path,
+    dir: &Path,
+    rel: &Path,
+    log: &mut fs::File,
+    report: &mut CopyReport,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    for entry in fs::read_dir(staged)? {
+        let entry = entry?;
+        let src = entry.path();
+        let dst = dir.join(entry.file_name());
+        let rel_path = rel.join(entry.file_name());
+        if !is_real_dir(&dst) {
+            if dst.symlink_metadata().is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} is left alone, but the new contents have it too",
+                        rel_path.display()
+                    ),
+                ));
+            }
+            writeln!(log, "{}", rel_path.to_string_lossy())?;
+            fs::rename(&src, &dst)?;
+        } else if entry.file_type()?.is_dir() {
+            move_in(&src, &dst, &rel_path, log, report)?;
+        } else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} is a directory left alone, but the new contents have a file there",
+                    rel_path.display()
+                ),
+            ));
+        }
+    }
+
+    // only once the entries are in, as moving them touches the dir
+    let meta = fs::metadata(staged)?;
+    let what = if rel.as_os_str().is_empty() {
+        dir.display().to_string()
+    } else {
+        rel.display().to_string()
+    };
+    if let Err(e) = set_modified(dir, &meta) {
+        report.not_preserved.push(format!(
+            "Could not keep the modification time of {}: {}",
+            what, e
+        ));
+    }
+    if let Err(e) = fs::set_permissions(dir, meta.permissions()) {
+        report
+            .not_preserved
+            .push(format!("Could not keep the permissions of {}: {}", what, e));
+    }
+    Ok(())
+}
+
+/// Undo an unfinished `merge_into_place` of `to`: remove the entries logged as moved in,
+/// then move back those set aside.
+fn unmerge(to: &Path) -> std::io::Result<()> {
+    let merged = sibling(to, MERGED_SUFFIX);
+    match fs::read_to_string(&merged) {
+        Ok(log) => {
+            for rel in log.lines().rev() {
+                let path = to.join(rel);
+                if path.symlink_metadata().is_ok() {
+                    remove_path(&path)?;
+                }
+            }
+            fs::remove_file(&merged)?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let aside = sibling(to, ASIDE_SUFFIX);
+    move_back(&aside, to)?;
+    remove_path(&aside)
+}
+
+/// Move the entries below `aside` back to the same place below `dir`.
+fn move_back(aside: &Path, dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(aside)? {
+        let entry = entry?;
+        let dst = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() && is_real_dir(&dst) {
+            move_back(&entry.path(), &dst)?;
+        } else {
+            fs::rename(entry.path(), &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Clean up after a replace that was interrupted, e.g. by Ctrl-C.
+/// If `to` went missing between the two renames of a swap, the old contents are put back.
+/// If the entries of a live directory were being moved, the old ones are moved back, unless
+/// the new ones were all in already.
+/// If it was being copied over in place, the old contents are copied back from the journal.
+fn recover_interrupted(to: &Path) -> Result<(), RobeError> {
+    let staged = sibling(to, STAGED_SUFFIX);
+    let aside = sibling(to, ASIDE_SUFFIX);
+    if aside.symlink_metadata().is_ok() && staged.symlink_metadata().is_ok() {
+        unmerge(to).map_err(RobeError::io("restore", to))?;
+    } else if aside.symlink_metadata().is_ok() {
+        remove_path(&aside).map_err(RobeError::io("remove", &aside))?;
+    }
+    let merged = sibling(to, MERGED_SUFFIX);
+    if merged.symlink_metadata().is_ok() {
+        fs::remove_file(&merged).map_err(RobeError::io("remove", &merged))?;
+    }
+    let replaced = sibling(to, REPLACED_SUFFIX);
+    if replaced.symlink_metadata().is_ok() {
+        if to.symlink_metadata().is_ok() {
+            remove_path(&replaced).map_err(RobeError::io("remove", &replaced))?;
+        } else {
+            fs::rename(&replaced, to).map_err(RobeError::io("restore", to))?;
+        }
+    }
+    if staged.symlink_metadata().is_ok() {
+        remove_path(&staged).map_err(RobeError::io("remove", &staged))?;
+    }
+    let unfinished = sibling(to, UNFINISHED_JOURNAL_SUFFIX);
+    if unfinished.symlink_metadata().is_ok() {
+        remove_path(&unfinished).map_err(RobeError::io("remove", &unfinished))?;
+    }
+    let journal = sibling(to, JOURNAL_SUFFIX);
+    if journal.symlink_metadata().is_ok() {
+        roll_back(&journal, to)?;
+    }
+    Ok(())
+}
+
+fn is_cross_device(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::CrossesDevices | std::io::ErrorKind::ResourceBusy
+    )
+}
+
+/// Copy `from` over `to` in place, journaling the old contents of `to` next to it
+/// and copying them back if anything fails, or on the next replace if robe is interrupted.
+fn journaled_replace(from: &Path, to: &Path, opts: &CopyOptions) -> Result<CopyReport, RobeError> {
+    let journal = if to.exists() {
+        Some(write_journal(to)?)
+    } else {
+        None
+    };
+
+    match replace_in_place(from, to, opts) {
+        Ok(report) => {
+            if let Some(journal) = &journal {
+                remove_path(journal).map_err(RobeError::io("remove", journal))?;
+            }
+            Ok(report)
+        }
+        Err(e) => {
+            let Some(journal) = journal else {
+                let _ = remove_path(to);
+                return Err(e);
+            };
+            match roll_back(&journal, to) {
+                Ok(()) => Err(e),
+                Err(_) => Err(RobeError::message(format!(
+                    "{} Failed to roll back {}; its previous contents are kept at {}.",
+                    e,
+                    to.display(),
+                    journal.display()
+                ))),
+            }
+        }
+    }
+}
+
+/// Copy the contents of `to` to its journal, only named as such once complete.
+/// Where no sibling can be written, the journal goes to the temp dir instead, where it
+/// still covers failures but not interruptions.
+fn write_journal(to: &Path) -> Result<PathBuf, RobeError> {
+    let journal = sibling(to, JOURNAL_SUFFIX);
+    let unfinished = sibling(to, UNFINISHED_JOURNAL_SUFFIX);
+    if replace_in_place(to, &unfinished, &CopyOptions::default()).is_ok()
+        && fs::rename(&unfinished, &journal).is_ok()
+    {
+        return Ok(journal);
+    }
+    let _ = remove_path(&unfinished);
+
+    let name = to
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let journal = std::env::temp_dir().join(format!(
+        "robe-journal-{}-{}-{}",
+        std::process::id(),
+        utils::now_millis(),
+        name
+    ));
+    replace_in_place(to, &journal, &CopyOptions::default())?;
+    Ok(journal)
+}
+
+/// Copy the journaled contents back over `to`, then drop the journal.
+fn roll_back(journal: &Path, to: &Path) -> Result<(), RobeError> {
+    if to.symlink_metadata().is_ok() && to.is_dir() != journal.is_dir() {
+        remove_path(to).map_err(RobeError::io("remove", to))?;
+    }
+    replace_in_place(journal, to, &CopyOptions::default())?;
+    remove_path(journal).map_err(RobeError::io("remove", journal))
+}
+
+/// Remove the file, symlink or directory at `path`.
+pub fn remove_path(path: &Path) -> std::io::Result<()> {
+    let meta = path.symlink_metadata()?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Replace the contents of the dir at `to` with a copy of the dir at `from`.
+pub fn replace_dir_all(
+    from: &Path,
+    to: &Path,
+    opts: &CopyOptions,
+) -> Result<CopyReport, RobeError> {
+    let mut copy = TreeCopy::new(from, opts)?;
+    copy.dir(from, to, Path::new(""))?;
+    Ok(copy.report)
+}
+
+/// State of one copy of a file or directory.
+/// Permissions and modification times are carried over; whatever cannot be is reported.
+struct TreeCopy<'a> {
+    /// the copied dir, as given and resolved, to tell which links stay inside of it
+    roots: Vec<PathBuf>,
+    root_label: String,
+    opts: CopyOptions<'a>,
+    /// resolved dirs currently being copied, to detect link loops when dereferencing
+    ancestors: Vec<PathBuf>,
+    report: CopyReport,
+}
+
+impl<'a> TreeCopy<'a> {
+    fn new(root: &Path, opts: &CopyOptions<'a>) -> Result<Self, RobeError> {
+        Ok(Self {
+            roots: vec![
+                normalize(&std::path::absolute(root).map_err(RobeError::io("resolve", root))?),
+                fs::canonicalize(root).map_err(RobeError::io("resolve", root))?,
+            ],
+            root_label: root.display().to_string(),
+            opts: *opts,
+            ancestors: Vec::new(),
+            report: CopyReport::default(),
+        })
+    }
+
+    fn file(&mut self, from: &Path, to: &Path, rel: &Path) -> Result<(), RobeError> {
+        // permissions come along with the contents
+        copy_file(from, to)?;
+        let meta = fs::metadata(from).map_err(RobeError::io("read the metadata of", from))?;
+        if let Err(e) = set_modified(to, &meta) {
+            self.not_preserved("modification time", rel, e);
+        }
+        Ok(())
+    }
+
+    fn dir(&mut self, from: &Path, to: &Path, rel: &Path) -> Result<(), RobeError> {
+        let resolved = fs::canonicalize(from).map_err(RobeError::io("resolve", from))?;
+        if self.ancestors.contains(&resolved) {
+            return Err(RobeError::Conflict(format!(
+                "Symlink loop: {} links back to {}.",
+                from.display(),
+                resolved.display()
+            )));
+        }
+
+        if to.exists() {
+            self.clean(from, to, rel)?;
+        } else {
+            fs::create_dir_all(to).map_err(RobeError::io("create", to))?;
+        }
+
+        self.ancestors.push(resolved);
+        for entry in fs::read_dir(from).map_err(RobeError::io("list", from))? {
+            let entry = entry.map_err(RobeError::io("list", from))?;
+            let file_type = entry
+                .file_type()
+                .map_err(RobeError::io("read the metadata of", &entry.path()))?;
+            let src_path = entry.path();
+            let dst_path = to.join(entry.file_name());
+            let rel_path = rel.join(entry.file_name());
+
+            if self.opts.ignores(&rel_path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_symlink() {
+                self.link(&src_path, &dst_path, &rel_path)?;
+            } else if file_type.is_dir() {
+                self.dir(&src_path, &dst_path, &rel_path)?;
+            } else if file_type.is_file() {
+                self.file(&src_path, &dst_path, &rel_path)?;
+            } else {
+                self.special(&rel_path);
+            }
+        }
+        self.ancestors.pop();
+
+        // only once the entries are in, as adding them touches the dir
+        let meta = fs::metadata(from).map_err(RobeError::io("read the metadata of", from))?;
+        if let Err(e) = set_modified(to, &meta) {
+            self.not_preserved("modification time", rel, e);
+        }
+        if let Err(e) = fs::set_permissions(to, meta.permissions()) {
+            self.not_preserved("permissions", rel, e);
+        }
+        Ok(())
+    }
+
+    fn link(&mut self, from: &Path, to: &Path, rel: &Path) -> Result<(), RobeError> {
+        if self.opts.dereference {
+            let meta = fs::metadata(from).map_err(RobeError::io("follow symlink", from))?;
+            if meta.is_dir() {
+                return self.dir(from, to, rel);
+            }
+            if !meta.is_file() {
+                self.special(rel);
+                return Ok(());
+            }
+            return self.file(from, to, rel);
+        }
+
+        let dest = fs::read_link(from).map_err(RobeError::io("read symlink", from))?;
+        let resolved = normalize(
+            &std::path::absolute(from)
+                .map_err(RobeError::io("resolve", from))?
+                .with_file_name(&dest),
+        );
+        if !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            self.report.outside_links.push(format!(
+                "{} links to {}, outside of {}; only the link is kept.",
+                rel.display(),
+                dest.display(),
+                self.root_label
+            ));
+        }
+        let is_dir = fs::metadata(from).map(|m| m.is_dir()).unwrap_or(false);
+        symlink(&dest, to, is_dir).map_err(RobeError::io("create symlink", to))?;
+        Ok(())
+    }
+
+    /// Empty the dir at `to` before `from` is copied into it. Kept ignored entries stay, with
+    /// the dirs holding them; the dirs `from` has too are emptied in turn when copied.
+    fn clean(&self, from: &Path, to: &Path, rel: &Path) -> Result<(), RobeError> {
+        if self.opts.kept().is_none() {
+            return clean_directory(to);
+        }
+        for entry in fs::read_dir(to).map_err(RobeError::io("list", to))? {
+            let entry = entry.map_err(RobeError::io("list", to))?;
+            let path = entry.path();
+            let rel_path = rel.join(entry.file_name());
+            let is_dir = entry
+                .file_type()
+                .map_err(RobeError::io("read the metadata of", &path))?
+                .is_dir();
+            if self.opts.ignores(&rel_path, is_dir) {
+                continue;
+            }
+            if !is_dir {
+                remove_path(&path).map_err(RobeError::io("remove", &path))?;
+                continue;
+            }
+            let from_path = from.join(entry.file_name());
+            if from_path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+                continue;
+            }
+            self.clean(&from_path, &path, &rel_path)?;
+            let mut left = fs::read_dir(&path).map_err(RobeError::io("list", &path))?;
+            if left.next().is_none() {
+                fs::remove_dir(&path).map_err(RobeError::io("remove", &path))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A fifo, socket or device would block or make no sense when copied, so it is left out.
+    fn special(&mut self, rel: &Path) {
+        self.report.not_preserved.push(format!(
+            "Left out {}: a fifo, socket or device is not copied.",
+            rel.display()
+        ));
+    }
+
+    fn not_preserved(&mut self, what: &str, rel: &Path, e: std::io::Error) {
+        let path = if rel.as_os_str().is_empty() {
+            self.root_label.clone()
+        } else {
+            rel.display().to_string()
+        };
+        self.report
+            .not_preserved
+            .push(format!("Could not keep the {} of {}: {}", what, path, e));
+    }
+}
+
+/// Give the file/dir at `to` the modification time in `meta`.
+fn set_modified(to: &Path, meta: &fs::Metadata) -> std::io::Result<()> {
+    // read-only files and dirs cannot be opened for writing, but their owner may still set times
+    let file = fs::File::options()
+        .write(true)
+        .open(to)
+        .or_else(|_| fs::File::open(to))?;
+    file.set_modified(meta.modified()?)
+}
+
+/// `path` with `.` and `..` resolved lexically, without following links.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn clean_directory(target: &Path) -> Result<(), RobeError> {
+    for entry in fs::read_dir(target).map_err(RobeError::io("list", target))? {
+        let entry = entry.map_err(RobeError::io("list", target))?;
+        let path = entry.path();
+        let file_t = entry
+            .file_type()
+            .map_err(RobeError::io("read the metadata of", &path))?;
+        if file_t.is_dir() {
+            fs::remove_dir_all(&path).map_err(RobeError::io("remove", &path))?;
+        } else {
+            fs::remove_file(&path).map_err(RobeError::io("remove", &path))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn store_metadata(
+    registry: &Registry,
+    meta: &TargetMetadata,
+    target_name: &str,
+) -> Result<(), RobeError> {
+    let target_root_dir = &registry.base_path.join(target_name);
+    let p = Path::join(target_root_dir, META_FILE);
+    fs::create_dir_all(target_root_dir).map_err(RobeError::io("create", target_root_dir))?;
+    fs::write(&p, toml::to_string_pretty(meta)?).map_err(RobeError::io("write", &p))?;
+    Ok(())
+}
+
+pub fn store_state(
+    registry: &Registry,
+    state: &TargetState,
+    target_name: &str,
+) -> Result<(), RobeError> {
+    let target_root_dir = &registry.base_path.join(target_name);
+    let p = Path::join(target_root_dir, STATE_FILE);
+    fs::create_dir_all(target_root_dir).map_err(RobeError::io("create", target_root_dir))?;
+    fs::write(&p, toml::to_string_pretty(state)?).map_err(RobeError::io("write", &p))?;
+    Ok(())
+}
+
+/// Records `profile` as the one currently worn by `target_name`, applied with `apply`,
+/// fingerprinting the live contents at `real_path` but for what `ignore` leaves alone.
+pub fn record_activation(
+    registry: &Registry,
+    target_name: &str,
+    profile: &str,
+    real_path: &Path,
+    ignore: &Ignore,
+    apply: Apply,
+) -> Result<(), RobeError> {
+    let state = TargetState {
+        profile: profile.to_string(),
+        activated_at: utils::now(),
+        fingerprint: fingerprint(real_path, ignore)?,
+        apply,
+    };
+    store_state(registry, &state, target_name)
+}
+
+/// Content fingerprint of a file or directory (FNV-1a, 64 bit).
+/// Directories hash their relative entry names and file contents in sorted order,
+/// skipping the entries `ignore` leaves alone. Fifos, sockets and devices are never opened:
+/// only their names and that they are special are hashed.
+pub fn fingerprint(path: &Path, ignore: &Ignore) -> Result<String, RobeError> {
+    let mut hash = FNV_OFFSET;
+    if path.is_dir() {
+        fingerprint_dir(path, Path::new(""), ignore, &mut hash)?;
+    } else {
+        fnv1a(
+            &mut hash,
+            &fs::read(path).map_err(RobeError::io("read", path))?,
+        );
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// Size in bytes of a file, or of everything inside a directory.
+/// Symlinks inside a directory count as the links themselves.
+pub fn size(path: &Path) -> Result<u64, RobeError> {
+    let meta = fs::metadata(path).map_err(RobeError::io("read the metadata of", path))?;
+    if meta.is_dir() {
+        dir_size(path, Path::new(""), &Ignore::default())
+    } else {
+        Ok(meta.len())
+    }
+}
+
+/// Size in bytes of everything inside the directory at `rel` in `root`,
+/// but for the entries `ignore` leaves alone.
+pub fn size_within(root: &Path, rel: &Path, ignore: &Ignore) -> Result<u64, RobeError> {
+    dir_size(&root.join(rel), rel, ignore)
+}
+
+fn dir_size(dir: &Path, rel: &Path, ignore: &Ignore) -> Result<u64, RobeError> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
+        let path = entry.path();
+        let rel = rel.join(entry.file_name());
+        let meta = entry
+            .metadata()
+            .map_err(RobeError::io("read the metadata of", &path))?;
+        if ignore.is_ignored(&rel, meta.is_dir()) {
+            continue;
+        }
+        total += if meta.is_dir() {
+            dir_size(&path, &rel, ignore)?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(total)
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(hash: &mut u64, bytes: &[u8]) {
+    for b in bytes {
+        *hash ^= u64::from(*b);
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+fn fingerprint_dir(
+    dir: &Path,
+    rel: &Path,
+    ignore: &Ignore,
+    hash: &mut u64,
+) -> Result<(), RobeError> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(RobeError::io("list", dir))?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let rel_path = rel.join(entry.file_name());
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(RobeError::io("read the metadata of", &path))?;
+        if ignore.is_ignored(&rel_path, file_type.is_dir()) {
+            continue;
+        }
+        fnv1a(hash, rel_path.to_string_lossy().as_bytes());
+        if file_type.is_symlink() {
+            fnv1a(hash, b"@\0");
+            fnv1a(
+                hash,
+                fs::read_link(&path)
+                    .map_err(RobeError::io("read symlink", &path))?
+                    .to_string_lossy()
+                    .as_bytes(),
+            );
+            fnv1a(hash, b"\0");
+        } else if file_type.is_dir() {
+            fnv1a(hash, b"/\0");
+            fingerprint_dir(&entry.path(), &rel_path, ignore, hash)?;
+        } else if !file_type.is_file() {
+            fnv1a(hash, b"!\0");
+        } else {
+            fnv1a(hash, b"\0");
+            fnv1a(
+                hash,
+                &fs::read(&path).map_err(RobeError::io("read", &path))?,
+            );
+            fnv1a(hash, b"\0");
+        }
+    }
+    Ok(())
+}
+
+pub fn delete_profile(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+) -> Result<(), RobeError> {
+    let profile_path = registry.profile_path(target_registry, profile);
+
+    if profile_path.is_file() {
+        fs::remove_file(&profile_path).map_err(RobeError::io("remove", &profile_path))?;
+    }
+    if profile_path.is_dir() {
+        fs::remove_dir_all(&profile_path).map_err(RobeError::io("remove", &profile_path))?;
+    }
+    let history = registry.history_path(target_registry, profile);
+    if history.is_dir() {
+        fs::remove_dir_all(&history).map_err(RobeError::io("remove", &history))?;
+    }
+    Ok(())
+}
+
+pub fn delete_target(target_name: &str, registry: &Registry) -> Result<(), RobeError> {
+    let mut target_path = registry.base_path.clone();
+    target_path.push(target_name);
+    fs::remove_dir_all(&target_path).map_err(RobeError::io("remove", &target_path))?;
+    Ok(())
+}
+
+/// A copy of the live config taken before a profile was activated.
+/// Stored as `<target>/.backups/<millis>`, with the state it replaced in `<millis>.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: u64,
+}
+
+impl Backup {
+    fn state_path(&self) -> PathBuf {
+        self.path.with_extension("toml")
+    }
+
+    pub fn state(&self) -> Result<Option<TargetState>, RobeError> {
+        match fs::read_to_string(self.state_path()) {
+            Ok(s) => Ok(Some(toml::from_str(&s)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(RobeError::io("read", &self.state_path())(e)),
+        }
+    }
+}
+
+/// Stash the live config of a target, keeping at most `keep` backups.
+pub fn backup_live(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    keep: usize,
+) -> Result<Option<Backup>, RobeError> {
+    if keep == 0 || !target_registry.real_path.exists() {
+        return Ok(None);
+    }
+
+    let dir = registry
+        .base_path
+        .join(&target_registry.name)
+        .join(BACKUP_DIR);
+    fs::create_dir_all(&dir).map_err(RobeError::io("create", &dir))?;
+
+    let last = list_backups(registry, &target_registry.name)?
+        .last()
+        .map(|b| b.created_at);
+    let created_at = next_stamp(last);
+    let backup = Backup {
+        path: dir.join(stamp_name(created_at)),
+        created_at,
+    };
+
+    replace_file_or_dir(
+        &target_registry.real_path,
+        &backup.path,
+        &CopyOptions::default(),
+    )?
+    .print_metadata_warnings();
+    if let Some(state) = &target_registry.state {
+        fs::write(backup.state_path(), toml::to_string_pretty(state)?)
+            .map_err(RobeError::io("write", &backup.state_path()))?;
+    }
+
+    prune_backups(registry, &target_registry.name, keep)?;
+    Ok(Some(backup))
+}
+
+/// Drop all but the `keep` most recent backups of a target.
+pub fn prune_backups(registry: &Registry, target_name: &str, keep: usize) -> Result<(), RobeError> {
+    let backups = list_backups(registry, target_name)?;
+    for old in backups.iter().take(backups.len().saturating_sub(keep)) {
+        delete_backup(old)?;
+    }
+    Ok(())
+}
+
+/// Now in milliseconds, but always after `last`, so names stay strictly increasing
+/// even for copies taken within the same millisecond.
+fn next_stamp(last: Option<u64>) -> u64 {
+    match last {
+        Some(last) => utils::now_millis().max(last + 1),
+        None => utils::now_millis(),
+    }
+}
+
+fn stamp_name(created_at: u64) -> String {
+    format!("{:013}", created_at)
+}
+
+/// Entries of `dir` named after the millisecond they were taken at, oldest first.
+fn stamped(dir: &Path) -> Result<Vec<(PathBuf, u64)>, RobeError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
+        if let Ok(created_at) = entry.file_name().to_string_lossy().parse::<u64>() {
+            entries.push((entry.path(), created_at));
+        }
+    }
+    entries.sort_by_key(|(_, created_at)| *created_at);
+    Ok(entries)
+}
+
+/// Backups of a target, oldest first.
+pub fn list_backups(registry: &Registry, target_name: &str) -> Result<Vec<Backup>, RobeError> {
+    let dir = registry.base_path.join(target_name).join(BACKUP_DIR);
+    Ok(stamped(&dir)?
+        .into_iter()
+        .map(|(path, created_at)| Backup { path, created_at })
+        .collect())
+}
+
+/// Put a backup back at `real_path` together with the state it replaced, then drop it.
+pub fn restore_backup(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    backup: &Backup,
+) -> Result<(), RobeError> {
+    replace_file_or_dir(
+        &backup.path,
+        &target_registry.real_path,
+        &CopyOptions::default(),
+    )?
+    .print_metadata_warnings();
+    match backup.state()? {
+        Some(state) => store_state(registry, &state, &target_registry.name)?,
+        None => {
+            let state_path = registry
+                .base_path
+                .join(&target_registry.name)
+                .join(STATE_FILE);
+            if state_path.exists() {
+                fs::remove_file(&state_path).map_err(RobeError::io("remove", &state_path))?;
+            }
+        }
+    }
+    delete_backup(backup)
+}
+
+pub fn delete_backup(backup: &Backup) -> Result<(), RobeError> {
+    if backup.path.is_dir() {
+        fs::remove_dir_all(&backup.path).map_err(RobeError::io("remove", &backup.path))?;
+    } else {
+        fs::remove_file(&backup.path).map_err(RobeError::io("remove", &backup.path))?;
+    }
+    if backup.state_path().exists() {
+        fs::remove_file(backup.state_path())
+            .map_err(RobeError::io("remove", &backup.state_path()))?;
+    }
+    Ok(())
+}
+
+/// An earlier version of a profile, kept when the profile was overwritten.
+/// Stored as `<target>/.history/<profile>/<millis>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    pub path: PathBuf,
+    pub created_at: u64,
+}
+
+/// Keep a copy of the stored `profile` in its history before it is overwritten,
+/// then drop all but the `keep` most recent versions. Keeps nothing if `keep` is 0.
+pub fn keep_version(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+    keep: usize,
+) -> Result<(), RobeError> {
+    if keep == 0 || !registry.profile_path(target_registry, profile).exists() {
+        return Ok(());
+    }
+    save_version(registry, target_registry, profile)?;
+    prune_history(registry, target_registry, profile, keep)
+}
+
+/// Copy the stored `profile` into its history as the most recent version.
+pub fn save_version(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+) -> Result<Version, RobeError> {
+    let dir = registry.history_path(target_registry, profile);
+    fs::create_dir_all(&dir).map_err(RobeError::io("create", &dir))?;
+
+    let last = list_versions(registry, target_registry, profile)?
+        .last()
+        .map(|v| v.created_at);
+    let created_at = next_stamp(last);
+    let version = Version {
+        path: dir.join(stamp_name(created_at)),
+        created_at,
+    };
+    replace_file_or_dir(
+        &registry.profile_path(target_registry, profile),
+        &version.path,
+        &CopyOptions::default(),
+    )?;
+    Ok(version)
+}
+
+/// Drop all but the `keep` most recent versions of `profile`.
+pub fn prune_history(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+    keep: usize,
+) -> Result<(), RobeError> {
+    let versions = list_versions(registry, target_registry, profile)?;
+    for old in versions.iter().take(versions.len().saturating_sub(keep)) {
+        remove_path(&old.path).map_err(RobeError::io("remove", &old.path))?;
+    }
+    Ok(())
+}
+
+/// Earlier versions of a profile, oldest first.
+pub fn list_versions(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    profile: &str,
+) -> Result<Vec<Version>, RobeError> {
+    let dir = registry.history_path(target_registry, profile);
+    Ok(stamped(&dir)?
+        .into_iter()
+        .map(|(path, created_at)| Version { path, created_at })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs::{self};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("a.txt");
+        let dst = dir.path().join("b.txt");
+
+        fs::write(&src, "hello")?;
+        copy_file(&src, &dst)?;
+
+        assert!(dst.exists());
+        assert_eq!(fs::read_to_string(dst)?, "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_size() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("sub"))?;
+        fs::write(src.join("a.txt"), "hello")?;
+        fs::write(src.join("sub").join("b.txt"), "hi")?;
+
+        assert_eq!(size(&src.join("a.txt"))?, 5);
+        assert_eq!(size(&src)?, 7);
+        assert!(size(&dir.path().join("missing")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_dir_all() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("f1.txt"), "file1")?;
+        fs::create_dir(src.join("sub"))?;
+        fs::write(src.join("sub").join("f2.txt"), "file2")?;
+
+        replace_dir_all(&src, &dst, &CopyOptions::default())?;
+
+        assert!(dst.exists());
+        assert!(dst.join("f1.txt").exists());
+        assert_eq!(fs::read_to_string(dst.join("f1.txt"))?, "file1");
+        assert!(dst.join("sub").join("f2.txt").exists());
+        assert_eq!(fs::read_to_string(dst.join("sub").join("f2.txt"))?, "file2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("f.txt");
+        let dst = dir.path().join("g.txt");
+
+        fs::write(&src, "hi")?;
+        replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
+
+        assert_eq!(fs::read_to_string(dst)?, "hi");
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_dir() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(src.join("sub"))?;
+        fs::write(src.join("sub").join("f.txt"), "hi")?;
+
+        replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
+
+        assert!(dst.join("sub").join("f.txt").exists());
+        assert_eq!(fs::read_to_string(dst.join("sub").join("f.txt"))?, "hi");
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_dir_should_delete_old_files() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(dst.join("sub"))?;
+        fs::write(dst.join("sub").join("f1.txt"), "hi")?;
+        fs::write(dst.join("sub").join("f2.txt"), "hi")?;
+        fs::write(dst.join("sub").join("f3.txt"), "hi")?;
+
+        fs::create_dir_all(src.join("sub"))?;
+        fs::write(src.join("sub").join("f1.txt"), "hi 2")?;
+
+        replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
+
+        assert!(dst.join("sub").join("f1.txt").exists());
+        assert!(!dst.join("sub").join("f2.txt").exists());
+        assert!(!dst.join("sub").join("f3.txt").exists());
+        assert_eq!(fs::read_to_string(dst.join("sub").join("f1.txt"))?, "hi 2");
+        assert_eq!(fs::read_to_string(src.join("sub").join("f1.txt"))?, "hi 2");
+        Ok(())
+    }
+
+    fn leftovers(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|n| n.contains("robe-"))
+            .collect()
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_leaves_no_staging_behind() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("f.txt"), "new")?;
+        fs::create_dir_all(&dst)?;
+        fs::write(dst.join("f.txt"), "old")?;
+
+        replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
+
+        assert_eq!(fs::read_to_string(dst.join("f.txt"))?, "new");
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_switches_kind() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::write(&src, "file")?;
+        fs::create_dir_all(dst.join("sub"))?;
+
+        replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
+
+        assert!(dst.is_file());
+        assert_eq!(fs::read_to_string(&dst)?, "file");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_file_or_dir_failure_keeps_old_contents() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("a.txt"), "new")?;
+        // following a dangling link fails halfway through the staged copy
+        std::os::unix::fs::symlink(dir.path().join("missing"), src.join("z.txt"))?;
+        fs::create_dir_all(&dst)?;
+        fs::write(dst.join("a.txt"), "old")?;
+
+        let opts = CopyOptions {
+            dereference: true,
+            ..Default::default()
+        };
+        let err = replace_file_or_dir(&src, &dst, &opts).unwrap_err();
+        assert!(format!("{}", err).starts_with("robe: Cannot follow symlink "));
+        assert!(format!("{}", err).contains("z.txt: "));
+
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert_eq!(fs::read_dir(&dst)?.count(), 1);
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journaled_replace_rolls_back() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("a.txt"), "new")?;
+        std::os::unix::fs::symlink(dir.path().join("missing"), src.join("z.txt"))?;
+        fs::create_dir_all(dst.join("sub"))?;
+        fs::write(dst.join("a.txt"), "old")?;
+        fs::write(dst.join("sub").join("b.txt"), "old b")?;
+
+        let opts = CopyOptions {
+            dereference: true,
+            ..Default::default()
+        };
+        assert!(journaled_replace(&src, &dst, &opts).is_err());
+
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert_eq!(fs::read_to_string(dst.join("sub").join("b.txt"))?, "old b");
+        assert!(!dst.join("z.txt").exists());
+        Ok(())
+    }
+
+    fn ignored_fixture(dir: &Path) -> Result<(PathBuf, PathBuf), RobeError> {
+        let profile = dir.join("profile");
+        let live = dir.join("live");
+        fs::create_dir_all(profile.join("cache"))?;
+        fs::write(profile.join("init.lua"), "new")?;
+        fs::write(profile.join("cache").join("stale"), "stale")?;
+        fs::create_dir_all(live.join(".git"))?;
+        fs::create_dir_all(live.join("lua").join("cache"))?;
+        fs::write(live.join("init.lua"), "old")?;
+        fs::write(live.join("old.lua"), "old")?;
+        fs::write(live.join(".git").join("HEAD"), "main")?;
+        fs::write(live.join("lua").join("cache").join("x"), "x")?;
+        Ok((profile, live))
+    }
+
+    fn assert_ignored_kept(live: &Path) -> Result<(), RobeError> {
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "new");
+        assert!(!live.join("old.lua").exists());
+        assert!(!live.join("cache").exists());
+        assert_eq!(fs::read_to_string(live.join(".git").join("HEAD"))?, "main");
+        assert_eq!(
+            fs::read_to_string(live.join("lua").join("cache").join("x"))?,
+            "x"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_file_or_dir_leaves_ignored_entries() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (profile, live) = ignored_fixture(dir.path())?;
+        let ignore = Ignore::new([".git/", "cache/"]);
+        let opts = CopyOptions {
+            ignore: Some(&ignore),
+            keep_ignored: true,
+            ..Default::default()
+        };
+
+        replace_file_or_dir(&profile, &live, &opts)?;
+
+        assert_ignored_kept(&live)?;
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_dir_all_leaves_ignored_entries() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (profile, live) = ignored_fixture(dir.path())?;
+        let ignore = Ignore::new([".git/", "cache/"]);
+        let opts = CopyOptions {
+            ignore: Some(&ignore),
+            keep_ignored: true,
+            ..Default::default()
+        };
+
+        // the in-place copy, as done when no staged copy can be swapped in
+        replace_dir_all(&profile, &live, &opts)?;
+
+        assert_ignored_kept(&live)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_without_keep_ignored_drops_ignored_entries() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let ignore = Ignore::new([".git/", "cache/"]);
+        let opts = CopyOptions {
+            ignore: Some(&ignore),
+            ..Default::default()
+        };
+
+        // a profile saved over an older one, staged or in place
+        let (profile, live) = ignored_fixture(dir.path())?;
+        replace_file_or_dir(&live, &profile, &opts)?;
+        assert!(!profile.join("cache").exists());
+        assert!(!profile.join(".git").exists());
+        assert_eq!(fs::read_to_string(profile.join("old.lua"))?, "old");
+
+        fs::create_dir_all(profile.join("cache"))?;
+        replace_dir_all(&live, &profile, &opts)?;
+        assert!(!profile.join("cache").exists());
+        assert!(!profile.join("lua").join("cache").exists());
+        Ok(())
     }
-}
 
-pub fn replace_dir_all(from: &PathBuf, to: &PathBuf) -> Result<(), RobeError> {
-    if to.exists() {
-        clean_directory(to)?;
-    } else {
-        fs::create_dir_all(to)?;
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_file_or_dir_moves_only_managed_entries() -> Result<(), RobeError> {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let dir = tempdir()?;
+        let (profile, live) = ignored_fixture(dir.path())?;
+        // a fifo blocks whoever opens it, so it must not be copied
+        let fifo = live.join(".git").join("fifo");
+        assert!(
+            std::process::Command::new("mkfifo")
+                .arg(&fifo)
+                .status()?
+                .success()
+        );
+        let git = fs::metadata(live.join(".git"))?.ino();
+        let ignore = Ignore::new([".git/", "cache/"]);
+        let opts = CopyOptions {
+            ignore: Some(&ignore),
+            keep_ignored: true,
+            ..Default::default()
+        };
+
+        replace_file_or_dir(&profile, &live, &opts)?;
+
+        assert_ignored_kept(&live)?;
+        assert_eq!(fs::metadata(live.join(".git"))?.ino(), git);
+        assert!(fs::symlink_metadata(&fifo)?.file_type().is_fifo());
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
     }
 
-    for entry in fs::read_dir(from)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let src_path = entry.path();
-        let dst_path = Path::join(to, entry.file_name());
+    #[test]
+    fn test_replace_file_or_dir_moves_back_on_failure() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (profile, live) = ignored_fixture(dir.path())?;
+        // the live dir leaves its `cache` dir alone, the profile has a file there
+        fs::create_dir_all(live.join("cache"))?;
+        fs::write(live.join("cache").join("x"), "x")?;
+        fs::remove_dir_all(profile.join("cache"))?;
+        fs::write(profile.join("cache"), "file")?;
+        let ignore = Ignore::new([".git/", "cache/"]);
+        let opts = CopyOptions {
+            ignore: Some(&ignore),
+            keep_ignored: true,
+            ..Default::default()
+        };
 
-        if file_type.is_dir() {
-            replace_dir_all(&src_path, &dst_path)?;
-        } else {
-            fs::copy(src_path, dst_path)?;
-        }
+        let err = replace_file_or_dir(&profile, &live, &opts).unwrap_err();
+
+        assert!(format!("{}", err).contains("cache is a directory left alone"));
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "old");
+        assert_eq!(fs::read_to_string(live.join("old.lua"))?, "old");
+        assert_eq!(fs::read_to_string(live.join("cache").join("x"))?, "x");
+        assert_eq!(fs::read_to_string(live.join(".git").join("HEAD"))?, "main");
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
     }
-    Ok(())
-}
 
-fn clean_directory(target: &PathBuf) -> Result<(), RobeError> {
-    for entry in fs::read_dir(target)? {
-        let entry = entry?;
-        let file_t = entry.file_type()?;
-        if file_t.is_dir() {
-            fs::remove_dir_all(entry.path())?;
-        } else {
-            fs::remove_file(entry.path())?;
-        }
+    #[test]
+    fn test_recover_interrupted_merge() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("live");
+        let staged = sibling(&live, STAGED_SUFFIX);
+        let aside = sibling(&live, ASIDE_SUFFIX);
+        fs::create_dir_all(live.join(".git"))?;
+        fs::write(live.join(".git").join("HEAD"), "main")?;
+
+        // interrupted while moving in: init.lua is new, extra.lua was still to come
+        fs::create_dir_all(&staged)?;
+        fs::write(staged.join("extra.lua"), "new")?;
+        fs::write(live.join("init.lua"), "new")?;
+        fs::write(sibling(&live, MERGED_SUFFIX), "init.lua\n")?;
+        fs::create_dir_all(&aside)?;
+        fs::write(aside.join("init.lua"), "old")?;
+        fs::write(aside.join("old.lua"), "old")?;
+
+        recover_interrupted(&live)?;
+
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "old");
+        assert_eq!(fs::read_to_string(live.join("old.lua"))?, "old");
+        assert!(!live.join("extra.lua").exists());
+        assert_eq!(fs::read_to_string(live.join(".git").join("HEAD"))?, "main");
+        assert!(leftovers(dir.path()).is_empty());
+
+        // interrupted once every new entry was in: only the old ones are left to drop
+        fs::create_dir_all(&aside)?;
+        fs::write(aside.join("init.lua"), "older")?;
+        recover_interrupted(&live)?;
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "old");
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
     }
-    Ok(())
-}
 
-pub fn store_metadata(
-    registry: &Registry,
-    meta: &TargetMetadata,
-    target_name: &str,
-) -> Result<(), RobeError> {
-    let target_root_dir = &registry.base_path.join(target_name);
-    let p = Path::join(target_root_dir, "meta.toml");
-    fs::create_dir_all(target_root_dir)?;
-    fs::write(&p, toml::to_string_pretty(meta)?)?;
-    Ok(())
-}
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_dir_all_keeps_symlinks() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("nvim");
+        let dst = dir.path().join("profile");
+        fs::create_dir_all(src.join("lua"))?;
+        fs::write(src.join("lua").join("init.lua"), "init")?;
+        std::os::unix::fs::symlink("lua/init.lua", src.join("init.lua"))?;
+        std::os::unix::fs::symlink("lua", src.join("modules"))?;
+        std::os::unix::fs::symlink(dir.path().join("shared"), src.join("shared"))?;
 
-pub fn delete_profile(
-    registry: &Registry,
-    target_registry: &TargetRegistry,
-    profile: &str,
-) -> Result<(), RobeError> {
-    let mut profile_path = registry.base_path.clone();
-    profile_path.push(target_registry.name.clone());
-    profile_path.push(profile);
+        let report = replace_dir_all(&src, &dst, &CopyOptions::default())?;
 
-    if profile_path.is_file() {
-        fs::remove_file(&profile_path)?;
+        assert_eq!(
+            fs::read_link(dst.join("init.lua"))?,
+            PathBuf::from("lua/init.lua")
+        );
+        assert_eq!(fs::read_link(dst.join("modules"))?, PathBuf::from("lua"));
+        assert_eq!(fs::read_to_string(dst.join("init.lua"))?, "init");
+        // only the link pointing outside of the copied dir is worth a warning
+        assert_eq!(report.outside_links.len(), 1);
+        assert!(report.outside_links[0].starts_with("shared links to"));
+        Ok(())
     }
-    if profile_path.is_dir() {
-        fs::remove_dir_all(&profile_path)?;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_dir_all_dereference() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("nvim");
+        let dst = dir.path().join("profile");
+        fs::create_dir_all(dir.path().join("shared"))?;
+        fs::write(dir.path().join("shared").join("a.lua"), "a")?;
+        fs::create_dir_all(&src)?;
+        std::os::unix::fs::symlink(dir.path().join("shared"), src.join("shared"))?;
+
+        let opts = CopyOptions {
+            dereference: true,
+            ..Default::default()
+        };
+        let report = replace_dir_all(&src, &dst, &opts)?;
+
+        assert!(
+            !dst.join("shared")
+                .symlink_metadata()?
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(dst.join("shared").join("a.lua"))?, "a");
+        assert!(report.outside_links.is_empty());
+        Ok(())
     }
-    Ok(())
-}
 
-pub fn delete_target(target_name: &str, registry: &Registry) -> Result<(), RobeError> {
-    let mut target_path = registry.base_path.clone();
-    target_path.push(target_name);
-    fs::remove_dir_all(target_path)?;
-    Ok(())
-}
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_dir_all_dereference_detects_loops() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("nvim");
+        fs::create_dir_all(src.join("sub"))?;
+        std::os::unix::fs::symlink("..", src.join("sub").join("up"))?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::fs::{self};
-    use std::path::PathBuf;
-    use tempfile::tempdir;
+        let opts = CopyOptions {
+            dereference: true,
+            ..Default::default()
+        };
+        let err = replace_dir_all(&src, &dir.path().join("profile"), &opts).unwrap_err();
+        assert!(format!("{}", err).contains("Symlink loop"));
 
+        // copied as links, the same tree is fine
+        replace_dir_all(&src, &dir.path().join("profile"), &CopyOptions::default())?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn mode(path: &Path) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[cfg(unix)]
+    fn set_mode(path: &Path, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn mtime(path: &Path) -> std::time::SystemTime {
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    #[cfg(unix)]
+    fn set_mtime(path: &Path, secs: u64) {
+        let t = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        fs::File::open(path).unwrap().set_modified(t).unwrap();
+    }
+
+    #[cfg(unix)]
     #[test]
-    fn test_copy_file() -> Result<(), RobeError> {
+    fn test_replace_file_or_dir_keeps_file_metadata() -> Result<(), RobeError> {
         let dir = tempdir()?;
-        let src = dir.path().join("a.txt");
-        let dst = dir.path().join("b.txt");
+        let src = dir.path().join("netrc");
+        let dst = dir.path().join("profile");
+        fs::write(&src, "machine example.com")?;
+        fs::write(&dst, "old")?;
+        set_mode(&src, 0o600);
+        set_mtime(&src, 1_000_000_000);
 
-        fs::write(&src, "hello")?;
-        copy_file(&src, &dst)?;
+        let report = replace_file_or_dir(&src, &dst, &CopyOptions::default())?;
 
-        assert!(dst.exists());
-        assert_eq!(fs::read_to_string(dst)?, "hello");
+        assert_eq!(mode(&dst), 0o600);
+        assert_eq!(mtime(&dst), mtime(&src));
+        assert!(report.not_preserved.is_empty());
         Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_copy_dir_all() -> Result<(), RobeError> {
+    fn test_replace_file_or_dir_keeps_dir_metadata() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let src = dir.path().join("i3");
+        let profile = dir.path().join("profile");
+        let live = dir.path().join("live");
+        fs::create_dir_all(src.join("scripts"))?;
+        fs::write(src.join("scripts").join("volume.sh"), "#!/bin/sh")?;
+        fs::write(src.join("secrets"), "token")?;
+        set_mode(&src.join("scripts").join("volume.sh"), 0o755);
+        set_mode(&src.join("secrets"), 0o600);
+        set_mode(&src.join("scripts"), 0o750);
+        set_mode(&src, 0o700);
+        set_mtime(&src.join("scripts").join("volume.sh"), 1_000_000_000);
+        set_mtime(&src.join("scripts"), 1_100_000_000);
+        set_mtime(&src, 1_200_000_000);
+
+        // capture, then wear it in place of an existing dir, through the journal
+        let report = replace_file_or_dir(&src, &profile, &CopyOptions::default())?;
+        assert!(report.not_preserved.is_empty());
+        fs::create_dir_all(&live)?;
+        journaled_replace(&profile, &live, &CopyOptions::default())?;
+
+        for copy in [&profile, &live] {
+            assert_eq!(mode(&copy.join("scripts").join("volume.sh")), 0o755);
+            assert_eq!(mode(&copy.join("secrets")), 0o600);
+            assert_eq!(mode(&copy.join("scripts")), 0o750);
+            assert_eq!(mode(copy), 0o700);
+            for rel in ["scripts/volume.sh", "scripts", ""] {
+                assert_eq!(mtime(&copy.join(rel)), mtime(&src.join(rel)));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_journaled_replace() -> Result<(), RobeError> {
         let dir = tempdir()?;
         let src = dir.path().join("src");
         let dst = dir.path().join("dst");
 
-        fs::create_dir_all(&src)?;
-        fs::write(src.join("f1.txt"), "file1")?;
-        fs::create_dir(src.join("sub"))?;
-        fs::write(src.join("sub").join("f2.txt"), "file2")?;
+        fs::write(&src, "new")?;
+        fs::write(&dst, "old")?;
 
-        replace_dir_all(&src, &dst)?;
+        journaled_replace(&src, &dst, &CopyOptions::default())?;
 
-        assert!(dst.exists());
-        assert!(dst.join("f1.txt").exists());
-        assert_eq!(fs::read_to_string(dst.join("f1.txt"))?, "file1");
-        assert!(dst.join("sub").join("f2.txt").exists());
-        assert_eq!(fs::read_to_string(dst.join("sub").join("f2.txt"))?, "file2");
+        assert_eq!(fs::read_to_string(&dst)?, "new");
         Ok(())
     }
 
     #[test]
-    fn test_replace_file_or_dir_file() -> Result<(), RobeError> {
+    fn test_swap_into_place_renames_files_over_the_old_one() -> Result<(), RobeError> {
         let dir = tempdir()?;
-        let src = dir.path().join("f.txt");
-        let dst = dir.path().join("g.txt");
+        let dst = dir.path().join("dst");
+        let staged = sibling(&dst, STAGED_SUFFIX);
+        fs::write(&dst, "old")?;
+        fs::write(&staged, "new")?;
+        // where a two-step swap would move the old file aside, and fail
+        let replaced = sibling(&dst, REPLACED_SUFFIX);
+        fs::create_dir_all(replaced.join("busy"))?;
 
-        fs::write(&src, "hi")?;
-        replace_file_or_dir(&src, &dst)?;
+        swap_into_place(&staged, &dst)?;
 
-        assert_eq!(fs::read_to_string(dst)?, "hi");
+        assert_eq!(fs::read_to_string(&dst)?, "new");
+        assert!(!staged.exists());
+        assert!(replaced.join("busy").is_dir());
         Ok(())
     }
 
     #[test]
-    fn test_replace_file_or_dir_dir() -> Result<(), RobeError> {
+    fn test_journaled_replace_keeps_its_journal_next_to_the_destination() -> Result<(), RobeError> {
         let dir = tempdir()?;
         let src = dir.path().join("src");
         let dst = dir.path().join("dst");
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("a.txt"), "new")?;
+        fs::create_dir_all(dst.join("sub"))?;
+        fs::write(dst.join("a.txt"), "old")?;
 
-        fs::create_dir_all(src.join("sub"))?;
-        fs::write(src.join("sub").join("f.txt"), "hi")?;
+        let journal = write_journal(&dst)?;
+        assert_eq!(journal, sibling(&dst, JOURNAL_SUFFIX));
+        assert_eq!(fs::read_to_string(journal.join("a.txt"))?, "old");
+        remove_path(&journal)?;
+
+        journaled_replace(&src, &dst, &CopyOptions::default())?;
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "new");
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_interrupted_journaled_replace() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let dst = dir.path().join("dst");
+        let journal = sibling(&dst, JOURNAL_SUFFIX);
 
-        replace_file_or_dir(&src, &dst)?;
+        // interrupted halfway through copying the new contents over the old ones
+        fs::create_dir_all(journal.join("sub"))?;
+        fs::write(journal.join("a.txt"), "old")?;
+        fs::write(journal.join("sub").join("b.txt"), "old b")?;
+        fs::create_dir_all(&dst)?;
+        fs::write(dst.join("a.txt"), "new")?;
+        fs::write(dst.join("c.txt"), "half")?;
 
-        assert!(dst.join("sub").join("f.txt").exists());
-        assert_eq!(fs::read_to_string(dst.join("sub").join("f.txt"))?, "hi");
+        recover_interrupted(&dst)?;
+
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert_eq!(fs::read_to_string(dst.join("sub").join("b.txt"))?, "old b");
+        assert!(!dst.join("c.txt").exists());
+        assert!(leftovers(dir.path()).is_empty());
+
+        // interrupted while journaling, before anything was copied over
+        let unfinished = sibling(&dst, UNFINISHED_JOURNAL_SUFFIX);
+        fs::create_dir_all(&unfinished)?;
+        fs::write(unfinished.join("a.txt"), "ol")?;
+
+        recover_interrupted(&dst)?;
+
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert!(leftovers(dir.path()).is_empty());
         Ok(())
     }
 
     #[test]
-    fn test_replace_file_or_dir_dir_should_delete_old_files() -> Result<(), RobeError> {
+    fn test_recover_interrupted_swap() -> Result<(), RobeError> {
         let dir = tempdir()?;
-        let src = dir.path().join("src");
         let dst = dir.path().join("dst");
+        let replaced = sibling(&dst, REPLACED_SUFFIX);
+        let staged = sibling(&dst, STAGED_SUFFIX);
 
-        fs::create_dir_all(dst.join("sub"))?;
-        fs::write(dst.join("sub").join("f1.txt"), "hi")?;
-        fs::write(dst.join("sub").join("f2.txt"), "hi")?;
-        fs::write(dst.join("sub").join("f3.txt"), "hi")?;
+        // interrupted between moving the old contents aside and moving the new ones in
+        fs::write(&replaced, "old")?;
+        fs::write(&staged, "half")?;
 
-        fs::create_dir_all(src.join("sub"))?;
-        fs::write(src.join("sub").join("f1.txt"), "hi 2")?;
+        recover_interrupted(&dst)?;
 
-        replace_file_or_dir(&src, &dst)?;
+        assert_eq!(fs::read_to_string(&dst)?, "old");
+        assert!(leftovers(dir.path()).is_empty());
+        Ok(())
+    }
 
-        assert!(dst.join("sub").join("f1.txt").exists());
-        assert!(!dst.join("sub").join("f2.txt").exists());
-        assert!(!dst.join("sub").join("f3.txt").exists());
-        assert_eq!(fs::read_to_string(dst.join("sub").join("f1.txt"))?, "hi 2");
-        assert_eq!(fs::read_to_string(src.join("sub").join("f1.txt"))?, "hi 2");
+    #[cfg(unix)]
+    #[test]
+    fn test_link_into_place_and_linked_profile() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let wardrobe = dir.path().join("wardrobe");
+        let real = dir.path().join("nvim");
+
+        fs::create_dir_all(wardrobe.join("nvim").join("work"))?;
+        fs::write(wardrobe.join("nvim").join("work").join("init.lua"), "work")?;
+        fs::create_dir_all(&real)?;
+        fs::write(real.join("init.lua"), "live")?;
+
+        let tr = TargetRegistry {
+            name: "nvim".to_string(),
+            real_path: real.clone(),
+            profiles: vec!["work".to_string(), "home".to_string()],
+            ..Default::default()
+        };
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets: HashMap::new(),
+        };
+
+        assert_eq!(linked_profile(&registry, &tr), None);
+
+        link_into_place(&wardrobe.join("nvim").join("work"), &real)?;
+
+        assert!(real.symlink_metadata()?.file_type().is_symlink());
+        assert_eq!(fs::read_to_string(real.join("init.lua"))?, "work");
+        assert_eq!(linked_profile(&registry, &tr), Some("work".to_string()));
+        assert!(leftovers(dir.path()).is_empty());
         Ok(())
     }
 
@@ -205,6 +1882,7 @@ mod tests {
             name: target_name.into(),
             real_path: PathBuf::from("_"),
             profiles: vec![profile_name.to_string()],
+            ..Default::default()
         };
 
         let mut targets = HashMap::new();
@@ -212,7 +1890,7 @@ mod tests {
 
         let registry = crate::registry::Registry {
             base_path: wardrobe.path().to_path_buf(),
-            targets: targets,
+            targets,
         };
 
         delete_profile(&registry, &target_registry, profile_name)?;
@@ -250,7 +1928,7 @@ mod tests {
         let real_path = dir.path().join("real_path");
         fs::write(&real_path, "")?;
 
-        let meta = TargetMetadata::create(&PathBuf::from(dir.path().join("real_path")))?;
+        let meta = TargetMetadata::create(&dir.path().join("real_path"))?;
 
         store_metadata(&registry, &meta, "target_name")?;
 
@@ -266,6 +1944,248 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_store_state() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+
+        let registry = Registry {
+            base_path: dir.path().to_path_buf(),
+            targets: HashMap::new(),
+        };
+
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 42,
+            fingerprint: "0123456789abcdef".to_string(),
+            ..Default::default()
+        };
+
+        store_state(&registry, &state, "target_name")?;
+
+        let s = fs::read_to_string(dir.path().join("target_name").join("state.toml"))?;
+        let extracted: TargetState = toml::from_str(&s)?;
+
+        assert_eq!(state, extracted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+
+        fs::write(&a, "same")?;
+        fs::write(&b, "same")?;
+        assert_eq!(
+            fingerprint(&a, &Ignore::default())?,
+            fingerprint(&b, &Ignore::default())?
+        );
+
+        fs::write(&b, "different")?;
+        assert_ne!(
+            fingerprint(&a, &Ignore::default())?,
+            fingerprint(&b, &Ignore::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_dir() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+
+        for d in [&a, &b] {
+            fs::create_dir_all(d.join("sub"))?;
+            fs::write(d.join("f.txt"), "hi")?;
+            fs::write(d.join("sub").join("g.txt"), "there")?;
+        }
+        assert_eq!(
+            fingerprint(&a, &Ignore::default())?,
+            fingerprint(&b, &Ignore::default())?
+        );
+
+        fs::rename(b.join("f.txt"), b.join("renamed.txt"))?;
+        assert_ne!(
+            fingerprint(&a, &Ignore::default())?,
+            fingerprint(&b, &Ignore::default())?
+        );
+
+        fs::rename(b.join("renamed.txt"), b.join("f.txt"))?;
+        fs::write(b.join("sub").join("new.txt"), "")?;
+        assert_ne!(
+            fingerprint(&a, &Ignore::default())?,
+            fingerprint(&b, &Ignore::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_dir_skips_ignored_entries() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let d = dir.path().join("d");
+        fs::create_dir_all(d.join(".git"))?;
+        fs::write(d.join("init.lua"), "hi")?;
+        let ignore = Ignore::new([".git/", "*.swp"]);
+
+        let before = fingerprint(&d, &ignore)?;
+        fs::write(d.join(".git").join("HEAD"), "main")?;
+        fs::write(d.join(".init.lua.swp"), "swap")?;
+        assert_eq!(fingerprint(&d, &ignore)?, before);
+        assert_ne!(fingerprint(&d, &Ignore::default())?, before);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fingerprint_dir_hashes_link_targets() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let d = dir.path().join("d");
+        fs::create_dir_all(&d)?;
+        std::os::unix::fs::symlink("missing", d.join("link"))?;
+
+        let before = fingerprint(&d, &Ignore::default())?;
+        fs::remove_file(d.join("link"))?;
+        std::os::unix::fs::symlink("elsewhere", d.join("link"))?;
+        assert_ne!(fingerprint(&d, &Ignore::default())?, before);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fingerprint_dir_does_not_open_fifos() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let d = dir.path().join("d");
+        fs::create_dir_all(&d)?;
+        fs::write(d.join("fifo"), "")?;
+        let as_file = fingerprint(&d, &Ignore::default())?;
+        fs::remove_file(d.join("fifo"))?;
+        assert!(
+            std::process::Command::new("mkfifo")
+                .arg(d.join("fifo"))
+                .status()?
+                .success()
+        );
+
+        let as_fifo = fingerprint(&d, &Ignore::default())?;
+
+        assert_ne!(as_fifo, as_file);
+        assert_eq!(fingerprint(&d, &Ignore::default())?, as_fifo);
+        Ok(())
+    }
+
+    fn backup_registry(
+        dir: &Path,
+        real_path: &Path,
+        state: Option<TargetState>,
+    ) -> (Registry, TargetRegistry) {
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: real_path.to_path_buf(),
+            profiles: vec![],
+            state,
+            ..Default::default()
+        };
+        let registry = Registry {
+            base_path: dir.join("wardrobe"),
+            targets: HashMap::new(),
+        };
+        (registry, tr)
+    }
+
+    #[test]
+    fn test_backup_live_and_restore() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let real = dir.path().join("tmux.conf");
+        fs::write(&real, "before")?;
+
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 1,
+            fingerprint: fingerprint(&real, &Ignore::default())?,
+            ..Default::default()
+        };
+        let (registry, tr) = backup_registry(dir.path(), &real, Some(state.clone()));
+
+        let backup = backup_live(&registry, &tr, 5)?.unwrap();
+        assert_eq!(fs::read_to_string(&backup.path)?, "before");
+        assert_eq!(backup.state()?, Some(state.clone()));
+        assert_eq!(list_backups(&registry, "tmux")?, vec![backup.clone()]);
+
+        fs::write(&real, "after")?;
+        restore_backup(&registry, &tr, &backup)?;
+
+        assert_eq!(fs::read_to_string(&real)?, "before");
+        let restored = fs::read_to_string(registry.base_path.join("tmux").join("state.toml"))?;
+        assert_eq!(toml::from_str::<TargetState>(&restored)?, state);
+        assert!(list_backups(&registry, "tmux")?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_backup_live_leaves_out_fifos() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let real = dir.path().join("nvim");
+        fs::create_dir_all(real.join("run"))?;
+        fs::write(real.join("init.lua"), "before")?;
+        assert!(
+            std::process::Command::new("mkfifo")
+                .arg(real.join("run").join("server"))
+                .status()?
+                .success()
+        );
+        let (registry, tr) = backup_registry(dir.path(), &real, None);
+
+        let backup = backup_live(&registry, &tr, 5)?.unwrap();
+
+        assert_eq!(fs::read_to_string(backup.path.join("init.lua"))?, "before");
+        assert!(backup.path.join("run").is_dir());
+        assert!(!backup.path.join("run").join("server").exists());
+
+        let report = replace_dir_all(&real, &dir.path().join("copy"), &CopyOptions::default())?;
+        assert_eq!(
+            report.not_preserved,
+            vec!["Left out run/server: a fifo, socket or device is not copied."]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_live_prunes_old_backups() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let real = dir.path().join("nvim");
+        fs::create_dir_all(&real)?;
+
+        let (registry, tr) = backup_registry(dir.path(), &real, None);
+
+        for i in 0..4 {
+            fs::write(real.join("init.lua"), format!("{}", i))?;
+            backup_live(&registry, &tr, 2)?;
+        }
+
+        let backups = list_backups(&registry, "tmux")?;
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(backups[0].path.join("init.lua"))?, "2");
+        assert_eq!(fs::read_to_string(backups[1].path.join("init.lua"))?, "3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_live_disabled_or_missing() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let real = dir.path().join("tmux.conf");
+
+        let (registry, tr) = backup_registry(dir.path(), &real, None);
+        assert!(backup_live(&registry, &tr, 5)?.is_none());
+
+        fs::write(&real, "x")?;
+        assert!(backup_live(&registry, &tr, 0)?.is_none());
+        assert!(list_backups(&registry, "tmux")?.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_clean_dir() -> Result<(), RobeError> {
         let dir = tempdir()?;
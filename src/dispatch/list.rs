@@ -1,8 +1,20 @@
-use crate::domain::List;
+use crate::dispatch::io;
+use crate::domain::{Format, List};
 use crate::errors::RobeError;
-use crate::registry::Registry;
+use crate::registry::{Mode, Registry, TargetRegistry};
+use serde::Serialize;
+
+pub fn list(cmd: &List, registry: &Registry, format: Format) -> Result<(), RobeError> {
+    if format == Format::Json {
+        let mut targets = match &cmd.target {
+            Some(t) => vec![registry.target_registry(t)?],
+            None => registry.targets.values().cloned().collect(),
+        };
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        println!("{}", format_json(registry, &targets)?);
+        return Ok(());
+    }
 
-pub fn list(cmd: &List, registry: &Registry) -> Result<(), RobeError> {
     let formatted = match &cmd.target {
         Some(t) => {
             let tr = registry.target_registry(t)?;
@@ -19,6 +31,57 @@ pub fn list(cmd: &List, registry: &Registry) -> Result<(), RobeError> {
     Ok(())
 }
 
+#[derive(Debug, Serialize)]
+struct ListJson {
+    targets: Vec<TargetJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetJson {
+    name: String,
+    real_path: String,
+    mode: Mode,
+    /// the profile linked at `real_path`, or else the last activated one
+    active: Option<String>,
+    profiles: Vec<ProfileJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileJson {
+    name: String,
+    path: String,
+    size: u64,
+}
+
+/// `{"targets": [...]}`, each target with its profiles and their sizes, sorted by name.
+fn format_json(registry: &Registry, targets: &[TargetRegistry]) -> Result<String, RobeError> {
+    let mut json = ListJson {
+        targets: Vec::new(),
+    };
+    for tr in targets {
+        let mut names = tr.profiles.clone();
+        names.sort();
+        let mut profiles = Vec::new();
+        for profile in &names {
+            let path = registry.profile_path(tr, profile);
+            profiles.push(ProfileJson {
+                name: profile.clone(),
+                size: io::size(&path)?,
+                path: path.to_string_lossy().to_string(),
+            });
+        }
+        json.targets.push(TargetJson {
+            name: tr.name.clone(),
+            real_path: tr.real_path.to_string_lossy().to_string(),
+            mode: tr.mode,
+            active: io::linked_profile(registry, tr)
+                .or_else(|| tr.state.as_ref().map(|s| s.profile.clone())),
+            profiles,
+        });
+    }
+    Ok(serde_json::to_string(&json)?)
+}
+
 fn format_targets(targets: &[&String]) -> String {
     let mut out = "".to_string();
     out.push_str("Registered targets:\n");
@@ -51,6 +114,7 @@ mod tests {
                 name: "tmux".to_string(),
                 real_path: std::path::PathBuf::from("/fake/tmux"),
                 profiles: vec!["work".to_string(), "clean".to_string()],
+                ..Default::default()
             },
         );
         registry.targets.insert(
@@ -59,12 +123,13 @@ mod tests {
                 name: "nvim".to_string(),
                 real_path: std::path::PathBuf::from("/fake/nvim"),
                 profiles: vec!["minimal".to_string()],
+                ..Default::default()
             },
         );
 
         let cmd = List { target: None };
 
-        let result = list(&cmd, &registry);
+        let result = list(&cmd, &registry, Format::Plain);
         assert!(result.is_ok());
     }
 
@@ -80,15 +145,61 @@ mod tests {
                 name: "tmux".to_string(),
                 real_path: std::path::PathBuf::from("/fake/tmux"),
                 profiles: vec!["work".to_string(), "clean".to_string()],
+                ..Default::default()
             },
         );
 
         let cmd = List { target: Some("tmux".to_string()) };
 
-        let result = list(&cmd, &registry);
+        let result = list(&cmd, &registry, Format::Plain);
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_format_json() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let wardrobe = dir.path().to_path_buf();
+        std::fs::create_dir_all(wardrobe.join("tmux"))?;
+        std::fs::write(wardrobe.join("tmux").join("work.conf"), "set -g mouse on")?;
+        std::fs::write(wardrobe.join("tmux").join("home.conf"), "")?;
+
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets: HashMap::new(),
+        };
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: std::path::PathBuf::from("/fake/tmux.conf"),
+            profiles: vec!["work".to_string(), "home".to_string()],
+            state: Some(crate::registry::TargetState {
+                profile: "work".to_string(),
+                activated_at: 0,
+                fingerprint: "".to_string(),
+                ..Default::default()
+            }),
+            file_name: Some("tmux.conf".to_string()),
+            ..Default::default()
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_json(&registry, &[tr])?).unwrap();
+        let target = &json["targets"][0];
+
+        assert_eq!(target["name"], "tmux");
+        assert_eq!(target["real_path"], "/fake/tmux.conf");
+        assert_eq!(target["mode"], "copy");
+        assert_eq!(target["active"], "work");
+        assert_eq!(target["profiles"][0]["name"], "home");
+        assert_eq!(target["profiles"][1]["name"], "work");
+        let profile_path = wardrobe.join("tmux").join("work.conf");
+        assert_eq!(
+            target["profiles"][1]["path"],
+            profile_path.to_string_lossy().as_ref()
+        );
+        assert_eq!(target["profiles"][1]["size"], 15);
+        Ok(())
+    }
+
     #[test]
     fn test_format_targets() {
         let mut targets_map: HashMap<String, ()> = std::collections::HashMap::new();
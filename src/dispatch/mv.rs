@@ -0,0 +1,208 @@
+use crate::dispatch::{cp, io, outfit};
+use crate::domain::Mv;
+use crate::errors::RobeError;
+use crate::registry::{Registry, TargetState};
+use std::fs;
+
+/// Rename a profile of a target.
+/// Requires force if a profile with the new name already exists.
+///
+/// Behaviour:
+/// rename `path/to/wardrobe/<target>/<profile>` to `path/to/wardrobe/<target>/<new_profile>[.<ext>]`
+/// a live config linking to the profile is re-linked to its new name
+/// the last activated profile is recorded under its new name
+/// hooks of the profile in `meta.toml` and its history move to its new name
+/// outfits wearing the profile wear it under its new name
+/// the live config itself is left untouched
+pub fn mv(cmd: &Mv, registry: &Registry) -> Result<(), RobeError> {
+    let target_registry = registry.target_registry(&cmd.target)?;
+    target_registry.assert_profile_exists(&cmd.profile)?;
+    if cmd.profile == cmd.new_profile {
+        return Err(RobeError::BadUsage(format!(
+            "{}/{} already has that name.",
+            &cmd.target, &cmd.profile
+        )));
+    }
+    cp::check_destination(registry, &target_registry, &cmd.new_profile, cmd.force)?;
+
+    let linked = io::linked_profile(registry, &target_registry);
+    let from = registry.profile_path(&target_registry, &cmd.profile);
+    let to = registry.profile_path(&target_registry, &cmd.new_profile);
+    // a rename replaces files, but not directories
+    if to.is_dir() {
+        io::delete_profile(registry, &target_registry, &cmd.new_profile)?;
+    }
+    fs::rename(&from, &to).map_err(RobeError::io(&format!("rename {} to", from.display()), &to))?;
+    let history = registry.history_path(&target_registry, &cmd.profile);
+    let new_history = registry.history_path(&target_registry, &cmd.new_profile);
+    if new_history.exists() {
+        fs::remove_dir_all(&new_history).map_err(RobeError::io("remove", &new_history))?;
+    }
+    if history.exists() {
+        fs::rename(&history, &new_history).map_err(RobeError::io(
+            &format!("rename {} to", history.display()),
+            &new_history,
+        ))?;
+    }
+
+    if linked.as_deref() == Some(cmd.profile.as_str()) {
+        io::link_into_place(&to, &target_registry.real_path)?;
+    }
+    if target_registry.profile_hooks.contains_key(&cmd.profile) {
+        let mut moved = target_registry.clone();
+        if let Some(hooks) = moved.profile_hooks.remove(&cmd.profile) {
+            moved.profile_hooks.insert(cmd.new_profile.clone(), hooks);
+        }
+        io::store_metadata(registry, &moved.metadata(), &cmd.target)?;
+    }
+    if let Some(state) = &target_registry.state
+        && state.profile == cmd.profile
+    {
+        let state = TargetState {
+            profile: cmd.new_profile.clone(),
+            ..state.clone()
+        };
+        io::store_state(registry, &state, &cmd.target)?;
+    }
+    outfit::rename_profile(registry, &cmd.target, &cmd.profile, &cmd.new_profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{Hooks, TargetMetadata, TargetRegistry};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn setup(dir: &Path, file_name: Option<&str>, state: Option<TargetState>) -> Registry {
+        let wardrobe = dir.join("wardrobe");
+        let tr = TargetRegistry {
+            name: "tmux".to_string(),
+            real_path: dir.join("tmux.conf"),
+            profiles: vec!["work".to_string(), "home".to_string()],
+            file_name: file_name.map(str::to_string),
+            state,
+            ..Default::default()
+        };
+        for profile in &tr.profiles {
+            let path = wardrobe.join("tmux").join(tr.profile_file_name(profile));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, profile).unwrap();
+        }
+        Registry {
+            base_path: wardrobe,
+            targets: HashMap::from([("tmux".to_string(), tr)]),
+        }
+    }
+
+    fn mv_cmd(profile: &str, new_profile: &str, force: bool) -> Mv {
+        Mv {
+            target: "tmux".to_string(),
+            profile: profile.to_string(),
+            new_profile: new_profile.to_string(),
+            force,
+        }
+    }
+
+    #[test]
+    fn test_mv_renames_profile() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path(), Some("tmux.conf"), None);
+        fs::write(dir.path().join("tmux.conf"), "live")?;
+        let pieces = vec![("tmux".to_string(), "work".to_string())];
+        outfit::save(&registry, "work", &pieces, false)?;
+
+        mv(&mv_cmd("work", "office", false), &registry)?;
+
+        let target_dir = registry.base_path.join("tmux");
+        assert!(!target_dir.join("work.conf").exists());
+        assert_eq!(fs::read_to_string(target_dir.join("office.conf"))?, "work");
+        assert_eq!(fs::read_to_string(dir.path().join("tmux.conf"))?, "live");
+        assert_eq!(outfit::load(&registry, "work")?.profiles["tmux"], "office");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mv_existing_requires_force() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path(), None, None);
+
+        let err = mv(&mv_cmd("work", "home", false), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::AlreadyExists(_)));
+
+        mv(&mv_cmd("work", "home", true), &registry)?;
+        let target_dir = registry.base_path.join("tmux");
+        assert!(!target_dir.join("work").exists());
+        assert_eq!(fs::read_to_string(target_dir.join("home"))?, "work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mv_renames_active_profile_in_state() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 42,
+            fingerprint: "abc".to_string(),
+            ..Default::default()
+        };
+        let registry = setup(dir.path(), None, Some(state));
+
+        mv(&mv_cmd("work", "office", false), &registry)?;
+
+        let state = fs::read_to_string(registry.base_path.join("tmux").join("state.toml"))?;
+        let state: TargetState = toml::from_str(&state)?;
+        assert_eq!(state.profile, "office");
+        assert_eq!(state.activated_at, 42);
+        assert_eq!(state.fingerprint, "abc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mv_refuses_reserved_name() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path(), None, None);
+
+        let err = mv(&mv_cmd("work", "meta.toml", false), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+        assert!(registry.base_path.join("tmux").join("work").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mv_relinks_linked_profile() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path(), Some("tmux.conf"), None);
+        let live = dir.path().join("tmux.conf");
+        let target_dir = registry.base_path.join("tmux");
+        std::os::unix::fs::symlink(target_dir.join("work.conf"), &live)?;
+
+        mv(&mv_cmd("work", "office", false), &registry)?;
+
+        assert_eq!(fs::read_link(&live)?, target_dir.join("office.conf"));
+        assert_eq!(fs::read_to_string(&live)?, "work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mv_moves_profile_hooks() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let mut registry = setup(dir.path(), None, None);
+        let tr = registry.targets.get_mut("tmux").unwrap();
+        let hooks = Hooks {
+            post_use: Some("true".to_string()),
+            ..Default::default()
+        };
+        tr.profile_hooks.insert("work".to_string(), hooks.clone());
+
+        mv(&mv_cmd("work", "office", false), &registry)?;
+
+        let meta = fs::read_to_string(registry.base_path.join("tmux").join("meta.toml"))?;
+        let meta: TargetMetadata = toml::from_str(&meta)?;
+        assert!(!meta.profiles.contains_key("work"));
+        assert_eq!(meta.profiles["office"].hooks, hooks);
+        Ok(())
+    }
+}
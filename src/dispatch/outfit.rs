@@ -0,0 +1,796 @@
+use crate::dispatch::{hooks, io, usecmd};
+use crate::domain::{Format, Outfit, OutfitAction, Use};
+use crate::errors::RobeError;
+use crate::registry::{
+    Event, OUTFITS_DIR, OutfitManifest, Registry, STATE_FILE, TargetRegistry, assert_name,
+};
+use crate::settings::Settings;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the live configs are kept while an outfit is worn, to roll back on failure.
+const WEARING_DIR: &str = ".wearing";
+
+/// Save, wear, list or remove outfits: profiles of several targets worn together.
+///
+/// Behaviour:
+/// save: write the profiles to `path/to/wardrobe/.outfits/<name>.toml`
+/// wear: activate each profile like `robe use`, in target order; nothing is switched if a
+/// profile is missing or a live config drifted, and if switching one fails, the targets
+/// switched already are put back along with the profiles `-s` saved into, and the backups
+/// and history taken are dropped; old backups and history are only pruned, and the
+/// `post-use` hooks only run, once every target is switched
+/// rm: delete `path/to/wardrobe/.outfits/<name>.toml`
+pub fn outfit(
+    cmd: &Outfit,
+    registry: &Registry,
+    settings: &Settings,
+    format: Format,
+) -> Result<(), RobeError> {
+    match &cmd.action {
+        OutfitAction::Save {
+            name,
+            profiles,
+            force,
+        } => save(registry, name, profiles, *force),
+        OutfitAction::Wear { name, force, save } => wear(registry, settings, name, *force, *save),
+        OutfitAction::List => list(registry, format),
+        OutfitAction::Rm(name) => rm(registry, name),
+    }
+}
+
+/// Names of the outfits of the wardrobe, sorted.
+pub fn names(registry: &Registry) -> Result<Vec<String>, RobeError> {
+    let dir = registry.base_path.join(OUTFITS_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir).map_err(RobeError::io("list", &dir))? {
+        let entry = entry.map_err(RobeError::io("list", &dir))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(name) = file_name.strip_suffix(".toml")
+            && !name.starts_with('.')
+        {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// The file of the outfit `name`, refusing names that would lead out of `.outfits`.
+fn outfit_path(registry: &Registry, name: &str) -> Result<PathBuf, RobeError> {
+    assert_name("outfit", name)?;
+    Ok(registry.outfit_path(name))
+}
+
+/// The outfit `name`, failing if it does not exist.
+pub fn load(registry: &Registry, name: &str) -> Result<OutfitManifest, RobeError> {
+    let path = outfit_path(registry, name)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(RobeError::NotFound(format!("Outfit {} not found.", name)));
+        }
+        Err(e) => return Err(RobeError::io("read", &path)(e)),
+    };
+    toml::from_str(&contents)
+        .map_err(|e| RobeError::config(&path.to_string_lossy(), &contents, e.span(), e.message()))
+}
+
+pub fn save(
+    registry: &Registry,
+    name: &str,
+    profiles: &[(String, String)],
+    force: bool,
+) -> Result<(), RobeError> {
+    let path = outfit_path(registry, name)?;
+    let mut manifest = OutfitManifest::default();
+    for (target, profile) in profiles {
+        registry
+            .target_registry(target)?
+            .assert_profile_exists(profile)?;
+        if manifest
+            .profiles
+            .insert(target.clone(), profile.clone())
+            .is_some()
+        {
+            return Err(RobeError::BadUsage(format!(
+                "Outfit {} lists {} more than once.",
+                name, target
+            )));
+        }
+    }
+
+    if path.exists() && !force {
+        return Err(RobeError::AlreadyExists(format!(
+            "Outfit {} already exists. Use `-f` to overwrite.",
+            name
+        )));
+    }
+    store(registry, name, &manifest)
+}
+
+fn store(registry: &Registry, name: &str, manifest: &OutfitManifest) -> Result<(), RobeError> {
+    let path = outfit_path(registry, name)?;
+    let dir = registry.base_path.join(OUTFITS_DIR);
+    fs::create_dir_all(&dir).map_err(RobeError::io("create", &dir))?;
+    fs::write(&path, toml::to_string_pretty(manifest)?).map_err(RobeError::io("write", &path))?;
+    Ok(())
+}
+
+/// The outfits wearing `target`, or only its `profile` if given, sorted.
+pub fn wearing(
+    registry: &Registry,
+    target: &str,
+    profile: Option<&str>,
+) -> Result<Vec<String>, RobeError> {
+    let mut wearing = Vec::new();
+    for name in names(registry)? {
+        let worn = load(registry, &name)?.profiles.remove(target);
+        if worn.is_some_and(|worn| profile.is_none_or(|profile| worn == profile)) {
+            wearing.push(name);
+        }
+    }
+    Ok(wearing)
+}
+
+/// Point the outfits wearing `target` at its new name.
+pub fn rename_target(registry: &Registry, target: &str, new_target: &str) -> Result<(), RobeError> {
+    for name in names(registry)? {
+        let mut manifest = load(registry, &name)?;
+        if let Some(profile) = manifest.profiles.remove(target) {
+            manifest.profiles.insert(new_target.to_string(), profile);
+            store(registry, &name, &manifest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Point the outfits wearing `target/profile` at the new name of the profile.
+pub fn rename_profile(
+    registry: &Registry,
+    target: &str,
+    profile: &str,
+    new_profile: &str,
+) -> Result<(), RobeError> {
+    for name in names(registry)? {
+        let mut manifest = load(registry, &name)?;
+        if let Some(worn) = manifest.profiles.get_mut(target)
+            && worn == profile
+        {
+            *worn = new_profile.to_string();
+            store(registry, &name, &manifest)?;
+        }
+    }
+    Ok(())
+}
+
+fn wear(
+    registry: &Registry,
+    settings: &Settings,
+    name: &str,
+    force: bool,
+    save: bool,
+) -> Result<(), RobeError> {
+    let manifest = load(registry, name)?;
+
+    // check every piece before switching any
+    let mut pieces = Vec::new();
+    for (target, profile) in &manifest.profiles {
+        let tr = registry
+            .target_registry(target)
+            .and_then(|tr| tr.assert_profile_exists(profile).map(|_| tr))
+            .map_err(|e| match e {
+                RobeError::NotFound(msg) => {
+                    RobeError::NotFound(format!("{} It is part of outfit {}.", msg, name))
+                }
+                other => other,
+            })?;
+        if !force
+            && !save
+            && let Some(active) = usecmd::drifted_profile(registry, &tr)?
+        {
+            return Err(usecmd::drift_error(&tr, &active));
+        }
+        pieces.push((tr, profile));
+    }
+
+    let scratch = registry.base_path.join(OUTFITS_DIR).join(WEARING_DIR);
+    if scratch.exists() {
+        io::remove_path(&scratch).map_err(RobeError::io("remove", &scratch))?;
+    }
+    // nothing is pruned before every target is switched, so that a failure can undo it all
+    let unpruned = Settings {
+        backups: if settings.backups == 0 { 0 } else { usize::MAX },
+        history: if settings.history == 0 { 0 } else { usize::MAX },
+        ..settings.clone()
+    };
+    let mut worn: Vec<Snapshot> = Vec::new();
+    for (tr, profile) in &pieces {
+        let cmd = Use {
+            target: tr.name.clone(),
+            profile: profile.to_string(),
+            force,
+            save,
+            apply: None,
+        };
+        let switched = Snapshot::take(registry, tr, &scratch, save).and_then(|snapshot| {
+            worn.push(snapshot);
+            usecmd::switch(&cmd, registry, &unpruned)
+        });
+        if let Err(e) = switched {
+            return Err(roll_back(registry, &worn, &scratch, e));
+        }
+    }
+    for snapshot in &worn {
+        snapshot.prune(registry, settings)?;
+    }
+    if scratch.exists() {
+        io::remove_path(&scratch).map_err(RobeError::io("remove", &scratch))?;
+    }
+    for (tr, profile) in &pieces {
+        hooks::run(registry, tr, profile, Event::PostUse)?;
+    }
+    Ok(())
+}
+
+/// Put back every target of `worn`, latest first, and return the error that stopped the outfit.
+fn roll_back(registry: &Registry, worn: &[Snapshot], scratch: &Path, e: RobeError) -> RobeError {
+    for snapshot in worn.iter().rev() {
+        if let Err(rollback) = snapshot.restore(registry) {
+            return RobeError::message(format!(
+                "{} Failed to roll back {}: {} Its previous contents are kept in {}.",
+                e.to_string().trim_start_matches("robe: "),
+                snapshot.target.name,
+                rollback.to_string().trim_start_matches("robe: "),
+                scratch.display()
+            ));
+        }
+    }
+    let _ = io::remove_path(scratch);
+    e
+}
+
+/// A live config as it was before an outfit switched it, with what switching it adds to
+/// the wardrobe.
+struct Snapshot {
+    target: TargetRegistry,
+    live: Live,
+    /// the most recent backup of the target before
+    last_backup: Option<u64>,
+    /// with `-s`, the drifted profile the live config is saved into
+    saved: Option<Saved>,
+}
+
+/// A profile as it was before `-s` saved the live config into it.
+struct Saved {
+    profile: String,
+    /// copied to this path, if it existed
+    copy: Option<PathBuf>,
+    /// the most recent version in its history before
+    last_version: Option<u64>,
+}
+
+enum Live {
+    Missing,
+    /// a symlink to this profile of the wardrobe
+    Linked(String),
+    /// copied to this path
+    Copied(PathBuf),
+}
+
+impl Snapshot {
+    fn take(
+        registry: &Registry,
+        target_registry: &TargetRegistry,
+        scratch: &Path,
+        save: bool,
+    ) -> Result<Self, RobeError> {
+        let real_path = &target_registry.real_path;
+        let live = if let Some(profile) = io::linked_profile(registry, target_registry) {
+            Live::Linked(profile)
+        } else if real_path.exists() {
+            let copy = scratch.join(&target_registry.name);
+            io::replace_file_or_dir(real_path, &copy, &io::CopyOptions::default())?;
+            Live::Copied(copy)
+        } else {
+            Live::Missing
+        };
+
+        let saved = match usecmd::drifted_profile(registry, target_registry)? {
+            Some(profile) if save => {
+                let path = registry.profile_path(target_registry, &profile);
+                let copy = if path.exists() {
+                    let copy = scratch.join(format!(".{}.profile", &target_registry.name));
+                    io::replace_file_or_dir(&path, &copy, &io::CopyOptions::default())?;
+                    Some(copy)
+                } else {
+                    None
+                };
+                let last_version = io::list_versions(registry, target_registry, &profile)?
+                    .last()
+                    .map(|v| v.created_at);
+                Some(Saved {
+                    profile,
+                    copy,
+                    last_version,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            target: target_registry.clone(),
+            live,
+            last_backup: io::list_backups(registry, &target_registry.name)?
+                .last()
+                .map(|b| b.created_at),
+            saved,
+        })
+    }
+
+    /// Drop the backups and history beyond those `settings` keeps, once the outfit is worn.
+    fn prune(&self, registry: &Registry, settings: &Settings) -> Result<(), RobeError> {
+        if settings.backups > 0 {
+            io::prune_backups(registry, &self.target.name, settings.backups)?;
+        }
+        if let Some(saved) = &self.saved
+            && settings.history > 0
+        {
+            io::prune_history(registry, &self.target, &saved.profile, settings.history)?;
+        }
+        Ok(())
+    }
+
+    fn restore(&self, registry: &Registry) -> Result<(), RobeError> {
+        let real_path = &self.target.real_path;
+        match &self.live {
+            Live::Missing => {
+                if real_path.symlink_metadata().is_ok() {
+                    io::remove_path(real_path).map_err(RobeError::io("remove", real_path))?;
+                }
+            }
+            Live::Linked(profile) => {
+                io::link_into_place(&registry.profile_path(&self.target, profile), real_path)?
+            }
+            Live::Copied(copy) => {
+                io::replace_file_or_dir(copy, real_path, &io::CopyOptions::default())?;
+            }
+        }
+
+        if let Some(saved) = &self.saved {
+            let path = registry.profile_path(&self.target, &saved.profile);
+            match &saved.copy {
+                Some(copy) => {
+                    io::replace_file_or_dir(copy, &path, &io::CopyOptions::default())?;
+                }
+                None if path.symlink_metadata().is_ok() => {
+                    io::remove_path(&path).map_err(RobeError::io("remove", &path))?;
+                }
+                None => {}
+            }
+            for version in io::list_versions(registry, &self.target, &saved.profile)? {
+                if Some(version.created_at) > saved.last_version {
+                    io::remove_path(&version.path)
+                        .map_err(RobeError::io("remove", &version.path))?;
+                }
+            }
+        }
+        for backup in io::list_backups(registry, &self.target.name)? {
+            if Some(backup.created_at) > self.last_backup {
+                io::delete_backup(&backup)?;
+            }
+        }
+
+        match &self.target.state {
+            Some(state) => io::store_state(registry, state, &self.target.name),
+            None => {
+                let state_path = registry.base_path.join(&self.target.name).join(STATE_FILE);
+                if state_path.exists() {
+                    fs::remove_file(&state_path).map_err(RobeError::io("remove", &state_path))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OutfitsJson {
+    outfits: Vec<OutfitJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct OutfitJson {
+    name: String,
+    profiles: Vec<PieceJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct PieceJson {
+    target: String,
+    profile: String,
+}
+
+fn list(registry: &Registry, format: Format) -> Result<(), RobeError> {
+    let mut outfits = Vec::new();
+    for name in names(registry)? {
+        let manifest = load(registry, &name)?;
+        outfits.push((name, manifest));
+    }
+
+    if format == Format::Json {
+        let json = OutfitsJson {
+            outfits: outfits
+                .into_iter()
+                .map(|(name, manifest)| OutfitJson {
+                    name,
+                    profiles: manifest
+                        .profiles
+                        .into_iter()
+                        .map(|(target, profile)| PieceJson { target, profile })
+                        .collect(),
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string(&json)?);
+        return Ok(());
+    }
+
+    println!("{}", format_outfits(&outfits));
+    Ok(())
+}
+
+fn format_outfits(outfits: &[(String, OutfitManifest)]) -> String {
+    let mut out = "Outfits:".to_string();
+    for (name, manifest) in outfits {
+        let pieces: Vec<String> = manifest
+            .profiles
+            .iter()
+            .map(|(target, profile)| format!("{}/{}", target, profile))
+            .collect();
+        out.push_str(&format!("\n  - {}: {}", name, pieces.join(", ")));
+    }
+    out
+}
+
+fn rm(registry: &Registry, name: &str) -> Result<(), RobeError> {
+    let path = outfit_path(registry, name)?;
+    if !path.is_file() {
+        return Err(RobeError::NotFound(format!("Outfit {} not found.", name)));
+    }
+    fs::remove_file(&path).map_err(RobeError::io("remove", &path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ignore::Ignore;
+    use crate::registry::{Hooks, Mode, TargetState};
+    use std::collections::{BTreeMap, HashMap};
+    use tempfile::tempdir;
+
+    /// `tmux` and `git` with the profiles `work` and `home`, wearing `home`.
+    fn setup(dir: &Path) -> Registry {
+        let wardrobe = dir.join("wardrobe");
+        let mut targets = HashMap::new();
+        for name in ["git", "tmux"] {
+            let real_path = dir.join(name);
+            fs::write(&real_path, format!("{} home", name)).unwrap();
+            let tr = TargetRegistry {
+                name: name.to_string(),
+                real_path: real_path.clone(),
+                profiles: vec!["work".to_string(), "home".to_string()],
+                state: Some(TargetState {
+                    profile: "home".to_string(),
+                    activated_at: 0,
+                    fingerprint: io::fingerprint(&real_path, &Ignore::default()).unwrap(),
+                    ..Default::default()
+                }),
+                mode: Mode::Copy,
+                ..Default::default()
+            };
+            for profile in &tr.profiles {
+                fs::create_dir_all(wardrobe.join(name)).unwrap();
+                fs::write(
+                    wardrobe.join(name).join(profile),
+                    format!("{} {}", name, profile),
+                )
+                .unwrap();
+            }
+            targets.insert(name.to_string(), tr);
+        }
+        Registry {
+            base_path: wardrobe,
+            targets,
+        }
+    }
+
+    fn pieces(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(t, p)| (t.to_string(), p.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+
+        let manifest = load(&registry, "work")?;
+        let expected = BTreeMap::from([
+            ("git".to_string(), "work".to_string()),
+            ("tmux".to_string(), "work".to_string()),
+        ]);
+        assert_eq!(manifest.profiles, expected);
+        assert_eq!(names(&registry)?, vec!["work"]);
+
+        let err = save(&registry, "work", &pieces(&[("tmux", "home")]), false).unwrap_err();
+        assert!(matches!(err, RobeError::AlreadyExists(_)));
+        save(&registry, "work", &pieces(&[("tmux", "home")]), true)?;
+        assert_eq!(load(&registry, "work")?.profiles.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_refuses_unknown_and_duplicate_pieces() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        let err = save(&registry, "work", &pieces(&[("tmux", "nope")]), false).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        let err = save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("tmux", "home")]),
+            false,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+        let err = save(&registry, ".work", &pieces(&[("tmux", "work")]), false).unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+        assert!(names(&registry)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_switches_every_target() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+
+        wear(&registry, &Settings::default(), "work", false, false)?;
+
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux work");
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git work");
+        assert!(
+            !registry
+                .base_path
+                .join(OUTFITS_DIR)
+                .join(WEARING_DIR)
+                .exists()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_checks_drift_before_switching() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+        fs::write(dir.path().join("tmux"), "edited")?;
+
+        let err = wear(&registry, &Settings::default(), "work", false, false).unwrap_err();
+        assert!(matches!(err, RobeError::Drift(_)));
+        // git comes first, but is left alone
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git home");
+
+        wear(&registry, &Settings::default(), "work", true, false)?;
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_rolls_back_on_failure() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+        // listed, so the outfit passes its checks, but gone by the time tmux is switched
+        fs::remove_file(registry.base_path.join("tmux").join("work"))?;
+
+        let err = wear(&registry, &Settings::default(), "work", false, false).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git home");
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux home");
+        let state = fs::read_to_string(registry.base_path.join("git").join(STATE_FILE))?;
+        let state: TargetState = toml::from_str(&state)?;
+        assert_eq!(state.profile, "home");
+        assert!(
+            !registry
+                .base_path
+                .join(OUTFITS_DIR)
+                .join(WEARING_DIR)
+                .exists()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_save_is_undone_on_failure() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+        let git = registry.target_registry("git")?;
+        let earlier = io::backup_live(&registry, &git, 5)?.unwrap();
+        fs::write(dir.path().join("git"), "git edited")?;
+        // git is saved and switched before tmux fails
+        fs::remove_file(registry.base_path.join("tmux").join("work"))?;
+        let settings = Settings {
+            backups: 1,
+            history: 1,
+            ..Default::default()
+        };
+
+        wear(&registry, &settings, "work", false, true).unwrap_err();
+
+        let home = registry.base_path.join("git").join("home");
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git edited");
+        assert_eq!(fs::read_to_string(&home)?, "git home");
+        assert!(io::list_versions(&registry, &git, "home")?.is_empty());
+        assert_eq!(io::list_backups(&registry, "git")?, vec![earlier.clone()]);
+
+        fs::write(registry.base_path.join("tmux").join("work"), "tmux work")?;
+        wear(&registry, &settings, "work", false, true)?;
+
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git work");
+        assert_eq!(fs::read_to_string(&home)?, "git edited");
+        assert_eq!(io::list_versions(&registry, &git, "home")?.len(), 1);
+        let backups = io::list_backups(&registry, "git")?;
+        assert_eq!(backups.len(), 1);
+        assert_ne!(backups[0], earlier);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_runs_post_use_hooks_once_every_target_is_switched() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let mut registry = setup(dir.path());
+        let marker = dir.path().join("marker");
+        registry.targets.get_mut("git").unwrap().hooks = Hooks {
+            post_use: Some(format!("echo $ROBE_PROFILE >> '{}'", marker.display())),
+            ..Default::default()
+        };
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+        // git is switched before tmux fails, and put back without its hook having run
+        fs::remove_file(registry.base_path.join("tmux").join("work"))?;
+
+        wear(&registry, &Settings::default(), "work", false, false).unwrap_err();
+        assert!(!marker.exists());
+
+        fs::write(registry.base_path.join("tmux").join("work"), "tmux work")?;
+        wear(&registry, &Settings::default(), "work", false, false)?;
+        assert_eq!(fs::read_to_string(&marker)?, "work\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wear_unknown_piece() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let mut registry = setup(dir.path());
+        save(&registry, "work", &pieces(&[("tmux", "work")]), false)?;
+        registry.targets.get_mut("tmux").unwrap().profiles = vec!["home".to_string()];
+
+        let err = wear(&registry, &Settings::default(), "work", false, false).unwrap_err();
+        assert!(format!("{}", err).contains("It is part of outfit work."));
+
+        let err = wear(&registry, &Settings::default(), "none", false, false).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_and_format() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+
+        let outfits = vec![("work".to_string(), load(&registry, "work")?)];
+        assert_eq!(
+            format_outfits(&outfits),
+            "Outfits:\n  - work: git/work, tmux/work"
+        );
+
+        rm(&registry, "work")?;
+        assert!(names(&registry)?.is_empty());
+        assert!(matches!(rm(&registry, "work"), Err(RobeError::NotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_renames_follow_into_outfits() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        save(
+            &registry,
+            "work",
+            &pieces(&[("tmux", "work"), ("git", "work")]),
+            false,
+        )?;
+        save(&registry, "home", &pieces(&[("tmux", "home")]), false)?;
+
+        assert_eq!(wearing(&registry, "tmux", None)?, vec!["home", "work"]);
+        assert_eq!(wearing(&registry, "tmux", Some("work"))?, vec!["work"]);
+        assert!(wearing(&registry, "tmux", Some("other"))?.is_empty());
+
+        rename_profile(&registry, "tmux", "work", "office")?;
+        rename_target(&registry, "tmux", "term")?;
+
+        let expected = BTreeMap::from([
+            ("git".to_string(), "work".to_string()),
+            ("term".to_string(), "office".to_string()),
+        ]);
+        assert_eq!(load(&registry, "work")?.profiles, expected);
+        assert_eq!(
+            load(&registry, "home")?.profiles,
+            BTreeMap::from([("term".to_string(), "home".to_string())])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_names_cannot_lead_out_of_the_outfits() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+        let meta = registry.base_path.join("tmux").join("meta.toml");
+        fs::write(&meta, "")?;
+
+        for name in ["../tmux/meta", "..", "a/b"] {
+            assert!(matches!(rm(&registry, name), Err(RobeError::BadUsage(_))));
+            assert!(matches!(load(&registry, name), Err(RobeError::BadUsage(_))));
+        }
+        assert!(meta.exists());
+        Ok(())
+    }
+}
@@ -1,28 +1,51 @@
 use crate::dispatch::io;
 use crate::domain::Register;
 use crate::errors::RobeError;
-use crate::registry::{Registry, TargetMetadata};
+use crate::ignore::Ignore;
+use crate::registry::{Mode, Registry, TargetMetadata, TargetRegistry, assert_name};
 
 /// Register a target with a profile.
 /// Fails if target already exists.
 ///
 /// Behaviour:
 /// store target metadata
-/// copy the current file/dir at `real_path` to `path/to/wardrobe/<target>/<profile>`
+/// copy the current file/dir at `real_path` to `path/to/wardrobe/<target>/<profile>[.<ext>]`,
+/// keeping symlinks inside it as symlinks unless `-L` is given,
+/// and leaving out the entries of a directory matched by its `.robeignore`
+/// in symlink mode, replace `real_path` with a symlink to the new profile
 pub fn register(cmd: &Register, registry: &Registry) -> Result<(), RobeError> {
+    assert_name("target", &cmd.target)?;
     if registry.get_target_registry(&cmd.target).is_some() {
-        return Err(RobeError::message(format!(
+        return Err(RobeError::AlreadyExists(format!(
             "target {} already exists.",
             &cmd.target
         )));
     }
 
-    let new_meta = TargetMetadata::create(&cmd.register_file_path)?;
+    let mut new_meta = TargetMetadata::create(&cmd.register_file_path)?;
+    new_meta.mode = cmd.mode;
+    let target_registry = TargetRegistry::new(&cmd.target, &new_meta, &[]);
+    target_registry.assert_profile_name(&cmd.profile)?;
+    let profile_path = registry.profile_path(&target_registry, &cmd.profile);
 
     io::store_metadata(registry, &new_meta, &cmd.target)?;
-    io::replace_file_or_dir(
+    let ignore = Ignore::for_target(&target_registry)?;
+    let opts = io::CopyOptions {
+        dereference: cmd.dereference,
+        ignore: Some(&ignore),
+        ..Default::default()
+    };
+    io::replace_file_or_dir(&cmd.register_file_path, &profile_path, &opts)?.print_warnings();
+    if cmd.mode == Mode::Symlink {
+        io::link_into_place(&profile_path, &cmd.register_file_path)?;
+    }
+    io::record_activation(
+        registry,
+        &cmd.target,
+        &cmd.profile,
         &cmd.register_file_path,
-        &registry.base_path.join(&cmd.target).join(&cmd.profile),
+        &ignore,
+        target_registry.apply,
     )?;
     Ok(())
 }
@@ -59,12 +82,13 @@ mod tests {
             target: "target".to_string(),
             profile: "profile".to_string(),
             register_file_path: source.clone(),
+            ..Default::default()
         };
 
         register(&cmd, &registry).unwrap();
 
-        // profile should exist
-        let profile = wardrobe.join("target").join("profile");
+        // profile should exist, with the extension of the registered file
+        let profile = wardrobe.join("target").join("profile.txt");
         assert!(profile.exists());
 
         let content = fs::read_to_string(profile).unwrap();
@@ -72,7 +96,48 @@ mod tests {
 
         // metadata should exist
         let meta = wardrobe.join("target").join("meta.toml");
-        assert!(meta.exists());
+        assert!(
+            fs::read_to_string(meta)
+                .unwrap()
+                .contains("file_name = \"config.txt\"")
+        );
+
+        // the registered profile is the one being worn
+        let state = fs::read_to_string(wardrobe.join("target").join("state.toml")).unwrap();
+        assert!(state.contains("profile = \"profile\""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_register_symlink_mode() {
+        let temp = tempdir().unwrap();
+        let wardrobe = temp.path().join("wardrobe");
+        fs::create_dir_all(&wardrobe).unwrap();
+
+        let source = temp.path().join("config.txt");
+        create_file(&source, "hello");
+
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets: HashMap::new(),
+        };
+
+        let cmd = Register {
+            target: "target".to_string(),
+            profile: "profile".to_string(),
+            register_file_path: source.clone(),
+            mode: Mode::Symlink,
+            dereference: false,
+        };
+
+        register(&cmd, &registry).unwrap();
+
+        let profile = wardrobe.join("target").join("profile.txt");
+        assert_eq!(fs::read_link(&source).unwrap(), profile);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "hello");
+
+        let meta = fs::read_to_string(wardrobe.join("target").join("meta.toml")).unwrap();
+        assert!(meta.contains("mode = \"symlink\""));
     }
 
     #[test]
@@ -88,6 +153,7 @@ mod tests {
                 name: "target".to_string(),
                 real_path: temp.path().to_path_buf(),
                 profiles: vec![],
+                ..Default::default()
             },
         );
 
@@ -100,10 +166,35 @@ mod tests {
             target: "target".to_string(),
             profile: "profile".to_string(),
             register_file_path: temp.path().join("file"),
+            ..Default::default()
         };
 
         let result = register(&cmd, &registry);
 
-        assert!(result.is_err());
+        assert!(matches!(result, Err(RobeError::AlreadyExists(_))));
+    }
+
+    #[test]
+    fn test_register_refuses_reserved_names() {
+        let temp = tempdir().unwrap();
+        let registry = Registry {
+            base_path: temp.path().join("wardrobe"),
+            ..Default::default()
+        };
+        let source = temp.path().join("starship.toml");
+        create_file(&source, "hello");
+
+        for (target, profile) in [(".outfits", "work"), ("starship", "meta")] {
+            let cmd = Register {
+                target: target.to_string(),
+                profile: profile.to_string(),
+                register_file_path: source.clone(),
+                ..Default::default()
+            };
+            let result = register(&cmd, &registry);
+
+            assert!(matches!(result, Err(RobeError::BadUsage(_))));
+        }
+        assert!(!temp.path().join("wardrobe").join("starship").exists());
     }
 }
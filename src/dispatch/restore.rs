@@ -0,0 +1,154 @@
+use crate::dispatch::io::{self, Version};
+use crate::domain::Restore;
+use crate::errors::RobeError;
+use crate::registry::Registry;
+use crate::settings::Settings;
+use crate::utils;
+
+/// Bring back an earlier version of a profile.
+///
+/// Behaviour:
+/// pick the `<n>`-th most recent version in `path/to/wardrobe/<target>/.history/<profile>`,
+/// or the most recent one taken during second `<timestamp>`
+/// keep the stored profile in its history first, unless `history` is 0
+/// copy the version over `path/to/wardrobe/<target>/<profile>[.<ext>]`
+/// the live config is left untouched; one linking to the profile shows the version at once
+pub fn restore(cmd: &Restore, registry: &Registry, settings: &Settings) -> Result<(), RobeError> {
+    let target_registry = registry.target_registry(&cmd.target)?;
+    target_registry.assert_profile_exists(&cmd.profile)?;
+
+    let versions = io::list_versions(registry, &target_registry, &cmd.profile)?;
+    let Some(version) = find_version(&versions, cmd.version) else {
+        return Err(RobeError::NotFound(format!(
+            "Version {}/{}@{} not found. See `robe history {}/{}`.",
+            &cmd.target, &cmd.profile, cmd.version, &cmd.target, &cmd.profile
+        )));
+    };
+
+    if settings.history > 0 {
+        io::save_version(registry, &target_registry, &cmd.profile)?;
+    }
+    let to = registry.profile_path(&target_registry, &cmd.profile);
+    io::replace_file_or_dir(&version.path, &to, &io::CopyOptions::default())?
+        .print_metadata_warnings();
+    // pruned only now, as the version restored may be the oldest
+    if settings.history > 0 {
+        io::prune_history(registry, &target_registry, &cmd.profile, settings.history)?;
+    }
+
+    println!(
+        "Restored {}/{} to its version of {}.",
+        &cmd.target,
+        &cmd.profile,
+        utils::format_timestamp(version.created_at / 1000)
+    );
+    Ok(())
+}
+
+/// The `n`-th most recent of `versions`, or else the most recent taken during second `n`.
+fn find_version(versions: &[Version], n: u64) -> Option<&Version> {
+    match usize::try_from(n) {
+        Ok(i) if (1..=versions.len()).contains(&i) => versions.get(versions.len() - i),
+        _ => versions.iter().rev().find(|v| v.created_at / 1000 == n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::TargetRegistry;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn setup(dir: &std::path::Path) -> Result<(Registry, TargetRegistry), RobeError> {
+        let wardrobe = dir.join("wardrobe");
+        fs::create_dir_all(wardrobe.join("nvim").join("work"))?;
+        let tr = TargetRegistry {
+            name: "nvim".to_string(),
+            real_path: dir.join("nvim"),
+            profiles: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let registry = Registry {
+            base_path: wardrobe,
+            targets: HashMap::from([("nvim".to_string(), tr.clone())]),
+        };
+        Ok((registry, tr))
+    }
+
+    fn restore_cmd(version: u64) -> Restore {
+        Restore {
+            target: "nvim".to_string(),
+            profile: "work".to_string(),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_find_version() {
+        let versions: Vec<Version> = [1_000, 2_000, 2_500, 3_000]
+            .into_iter()
+            .map(|created_at| Version {
+                path: PathBuf::from(created_at.to_string()),
+                created_at,
+            })
+            .collect();
+        assert_eq!(find_version(&versions, 1).unwrap().created_at, 3_000);
+        assert_eq!(find_version(&versions, 4).unwrap().created_at, 1_000);
+        assert_eq!(find_version(&versions, 2).unwrap().created_at, 2_500);
+        assert!(find_version(&versions, 5).is_none());
+        assert!(find_version(&[], 1).is_none());
+
+        let versions: Vec<Version> = [1_792_304_569_000, 1_792_304_570_123]
+            .into_iter()
+            .map(|created_at| Version {
+                path: PathBuf::from(created_at.to_string()),
+                created_at,
+            })
+            .collect();
+        assert_eq!(
+            find_version(&versions, 1_792_304_570).unwrap().created_at,
+            1_792_304_570_123
+        );
+        assert!(find_version(&versions, 1_792_304_571).is_none());
+    }
+
+    #[test]
+    fn test_restore_keeps_the_replaced_version() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (registry, tr) = setup(dir.path())?;
+        let profile = registry.profile_path(&tr, "work");
+        let settings = Settings {
+            history: 2,
+            ..Default::default()
+        };
+        for content in ["v1", "v2"] {
+            fs::write(profile.join("init.lua"), content)?;
+            io::keep_version(&registry, &tr, "work", settings.history)?;
+        }
+        fs::write(profile.join("init.lua"), "v3")?;
+
+        // the oldest version, which keeping v3 would otherwise prune
+        restore(&restore_cmd(2), &registry, &settings)?;
+
+        assert_eq!(fs::read_to_string(profile.join("init.lua"))?, "v1");
+        let versions = io::list_versions(&registry, &tr, "work")?;
+        assert_eq!(versions.len(), 2);
+        assert_eq!(fs::read_to_string(versions[0].path.join("init.lua"))?, "v2");
+        assert_eq!(fs::read_to_string(versions[1].path.join("init.lua"))?, "v3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_unknown_version_fails() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let (registry, _) = setup(dir.path())?;
+
+        let err = restore(&restore_cmd(1), &registry, &Settings::default()).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        assert!(format!("{}", err).contains("nvim/work@1 not found"));
+        Ok(())
+    }
+}
@@ -1,21 +1,62 @@
-use crate::dispatch::io;
+use crate::dispatch::{io, outfit};
 use crate::domain::Rm;
 use crate::errors::RobeError;
 use crate::registry::Registry;
 
+/// Remove a profile, or a target with all its profiles.
+/// Fails if the live config is a symlink to a profile that would be removed,
+/// or if an outfit wears a profile that would be removed.
 pub fn rm(cmd: &Rm, registry: &Registry) -> Result<(), RobeError> {
     let target_registry = registry.target_registry(&cmd.target)?;
+    let linked = io::linked_profile(registry, &target_registry);
 
     match &cmd.profile {
         Some(profile) => {
             target_registry.assert_profile_exists(profile)?;
+            if linked.as_deref() == Some(profile.as_str()) {
+                return Err(RobeError::Conflict(format!(
+                    "Profile {}/{} is linked at {}. Use another profile first.",
+                    &cmd.target,
+                    profile,
+                    target_registry.real_path.display()
+                )));
+            }
+            let outfits = outfit::wearing(registry, &cmd.target, Some(profile))?;
+            if !outfits.is_empty() {
+                return Err(RobeError::Conflict(format!(
+                    "Profile {}/{} is part of outfit {}. Save the outfit without it first.",
+                    &cmd.target,
+                    profile,
+                    outfits.join(", ")
+                )));
+            }
             io::delete_profile(registry, &target_registry, profile)
         }
-        None => io::delete_target(&cmd.target, registry),
+        None => {
+            if let Some(profile) = linked {
+                return Err(RobeError::Conflict(format!(
+                    "Target {} is in use: {} links to {}/{}.",
+                    &cmd.target,
+                    target_registry.real_path.display(),
+                    &cmd.target,
+                    profile
+                )));
+            }
+            let outfits = outfit::wearing(registry, &cmd.target, None)?;
+            if !outfits.is_empty() {
+                return Err(RobeError::Conflict(format!(
+                    "Target {} is part of outfit {}. Save the outfit without it first.",
+                    &cmd.target,
+                    outfits.join(", ")
+                )));
+            }
+            io::delete_target(&cmd.target, registry)
+        }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
     use super::*;
     use crate::domain::Rm;
@@ -46,6 +87,7 @@ mod tests {
             name: "tmux".to_string(),
             real_path: PathBuf::from("/fake/path"),
             profiles: vec!["work".to_string(), "home".to_string()],
+            ..Default::default()
         };
 
         let mut targets = HashMap::new();
@@ -106,6 +148,69 @@ mod tests {
         );
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_rm_refuses_linked_profile() {
+        let real_file_contents: String = "content".into();
+        let (temp, mut registry, _) = setup_registry(&real_file_contents);
+
+        let link = temp.path().join("tmux.conf");
+        std::os::unix::fs::symlink(registry.base_path.join("tmux").join("work"), &link).unwrap();
+        registry.targets.get_mut("tmux").unwrap().real_path = link;
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: Some("work".to_string()),
+        };
+        let err = rm(&cmd, &registry).unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+        assert!(format!("{}", err).contains("Profile tmux/work is linked at"));
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: None,
+        };
+        assert!(rm(&cmd, &registry).is_err());
+        assert!(registry.base_path.join("tmux").join("work").exists());
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: Some("home".to_string()),
+        };
+        rm(&cmd, &registry).unwrap();
+        assert!(!registry.base_path.join("tmux").join("home").exists());
+    }
+
+    #[test]
+    fn test_rm_refuses_profile_in_outfit() {
+        let real_file_contents: String = "content".into();
+        let (_temp, registry, _) = setup_registry(&real_file_contents);
+        let pieces = vec![("tmux".to_string(), "work".to_string())];
+        outfit::save(&registry, "work", &pieces, false).unwrap();
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: Some("work".to_string()),
+        };
+        let err = rm(&cmd, &registry).unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+        assert!(format!("{}", err).contains("Profile tmux/work is part of outfit work."));
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: None,
+        };
+        let err = rm(&cmd, &registry).unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+        assert!(registry.base_path.join("tmux").join("work").exists());
+
+        let cmd = Rm {
+            target: "tmux".to_string(),
+            profile: Some("home".to_string()),
+        };
+        rm(&cmd, &registry).unwrap();
+    }
+
     #[test]
     fn test_rm_missing_profile_fails() {
         let real_file_contents: String = "content".into();
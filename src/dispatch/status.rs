@@ -0,0 +1,424 @@
+use crate::dispatch::{diff, io};
+use crate::domain::{Format, Status};
+use crate::errors::RobeError;
+use crate::ignore::Ignore;
+use crate::registry::{Apply, Mode, Registry, TargetRegistry, TargetState};
+use crate::utils;
+use serde::Serialize;
+
+/// Show the active profile of one or all targets.
+///
+/// Behaviour:
+/// compare the live file/dir at `real_path` with the fingerprint recorded on the last activation
+/// in symlink mode, report the profile `real_path` links to
+/// after an overlay switch, only compare the files of the profile and report the others as unmanaged
+pub fn status(cmd: &Status, registry: &Registry, format: Format) -> Result<(), RobeError> {
+    let mut targets: Vec<TargetRegistry> = match &cmd.target {
+        Some(t) => vec![registry.target_registry(t)?],
+        None => registry.targets.values().cloned().collect(),
+    };
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if format == Format::Json {
+        let mut json = StatusJson {
+            targets: Vec::new(),
+        };
+        for tr in &targets {
+            let live = live_state(registry, tr)?;
+            json.targets
+                .push(status_json(tr, live, unmanaged(registry, tr)?));
+        }
+        println!("{}", serde_json::to_string(&json)?);
+        return Ok(());
+    }
+
+    let mut out = "".to_string();
+    for tr in &targets {
+        let live = live_state(registry, tr)?;
+        let unmanaged = unmanaged(registry, tr)?;
+        out.push_str(&format_status(
+            &tr.name,
+            tr.state.as_ref(),
+            live,
+            unmanaged.as_deref(),
+        ));
+    }
+    if targets.is_empty() {
+        out.push_str("No targets registered.\n");
+    }
+
+    print!("{}", out);
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum LiveState {
+    Unchanged,
+    Modified,
+    Missing,
+    Untracked,
+    Linked(String),
+    Unlinked,
+}
+
+fn live_state(registry: &Registry, tr: &TargetRegistry) -> Result<LiveState, RobeError> {
+    if let Some(profile) = io::linked_profile(registry, tr) {
+        return Ok(LiveState::Linked(profile));
+    }
+    let Some(state) = &tr.state else {
+        return Ok(LiveState::Untracked);
+    };
+    if !tr.real_path.exists() {
+        return Ok(LiveState::Missing);
+    }
+    if tr.mode == Mode::Symlink {
+        return Ok(LiveState::Unlinked);
+    }
+    if io::fingerprint(&tr.real_path, &Ignore::for_state(registry, tr, state)?)?
+        == state.fingerprint
+    {
+        Ok(LiveState::Unchanged)
+    } else {
+        Ok(LiveState::Modified)
+    }
+}
+
+/// Files of the live directory that the profile it was overlaid with does not manage,
+/// unless its profile was applied with the replace strategy.
+fn unmanaged(registry: &Registry, tr: &TargetRegistry) -> Result<Option<Vec<String>>, RobeError> {
+    let Some(state) = &tr.state else {
+        return Ok(None);
+    };
+    if state.apply != Apply::Overlay || io::linked_profile(registry, tr).is_some() {
+        return Ok(None);
+    }
+    let managed = diff::collect_files(&tr.real_path, &Ignore::for_state(registry, tr, state)?)?;
+    let files = diff::collect_files(&tr.real_path, &Ignore::for_target(tr)?)?;
+    Ok(Some(
+        files
+            .into_keys()
+            .filter(|rel| !managed.contains_key(rel))
+            .map(|rel| rel.to_string_lossy().to_string())
+            .collect(),
+    ))
+}
+
+impl LiveState {
+    /// Stable name of the state, as reported by `--json`.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Unchanged => "unchanged",
+            Self::Modified => "modified",
+            Self::Missing => "missing",
+            Self::Untracked => "untracked",
+            Self::Linked(_) => "linked",
+            Self::Unlinked => "unlinked",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StatusJson {
+    targets: Vec<TargetStatusJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetStatusJson {
+    name: String,
+    real_path: String,
+    /// the profile linked at `real_path`, or else the last activated one
+    profile: Option<String>,
+    state: &'static str,
+    /// seconds since the unix epoch
+    activated_at: Option<u64>,
+    /// `replace` or `overlay`, how the last activated profile was applied
+    apply: Option<&'static str>,
+    /// after an overlay switch, the files of `real_path` robe leaves alone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unmanaged: Option<Vec<String>>,
+}
+
+fn status_json(
+    tr: &TargetRegistry,
+    live: LiveState,
+    unmanaged: Option<Vec<String>>,
+) -> TargetStatusJson {
+    let profile = match &live {
+        LiveState::Linked(profile) => Some(profile.clone()),
+        _ => tr.state.as_ref().map(|s| s.profile.clone()),
+    };
+    TargetStatusJson {
+        name: tr.name.clone(),
+        real_path: tr.real_path.to_string_lossy().to_string(),
+        profile,
+        state: live.name(),
+        activated_at: tr.state.as_ref().map(|s| s.activated_at),
+        apply: tr.state.as_ref().map(|s| s.apply.name()),
+        unmanaged,
+    }
+}
+
+fn format_status(
+    target: &str,
+    state: Option<&TargetState>,
+    live: LiveState,
+    unmanaged: Option<&[String]>,
+) -> String {
+    let overlay = unmanaged
+        .map(|files| {
+            let n = files.len();
+            format!(
+                ", overlay with {} unmanaged file{}",
+                n,
+                if n == 1 { "" } else { "s" }
+            )
+        })
+        .unwrap_or_default();
+    let activated = state
+        .map(|s| format!(", activated {}", utils::format_timestamp(s.activated_at)))
+        .unwrap_or_default();
+    match (state, live) {
+        (_, LiveState::Linked(profile)) => {
+            format!("{}: {} (linked{})\n", target, profile, activated)
+        }
+        (None, _) => format!("{}: no active profile\n", target),
+        (Some(state), live) => {
+            let live = match live {
+                LiveState::Unchanged => "unchanged",
+                LiveState::Modified => "modified since activation",
+                LiveState::Missing => "live config missing",
+                LiveState::Unlinked => "no longer linked",
+                LiveState::Untracked | LiveState::Linked(_) => "untracked",
+            };
+            format!(
+                "{}: {} ({}{}{})\n",
+                target, state.profile, live, overlay, activated
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn target(real_path: std::path::PathBuf, state: Option<TargetState>) -> TargetRegistry {
+        TargetRegistry {
+            name: "tmux".to_string(),
+            real_path,
+            profiles: vec!["work".to_string()],
+            state,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_live_state() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let real = dir.path().join("tmux.conf");
+        fs::write(&real, "set -g mouse on")?;
+
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 0,
+            fingerprint: io::fingerprint(&real, &Ignore::default())?,
+            ..Default::default()
+        };
+
+        let registry = Registry::default();
+
+        assert_eq!(
+            live_state(&registry, &target(real.clone(), None))?,
+            LiveState::Untracked
+        );
+        assert_eq!(
+            live_state(&registry, &target(real.clone(), Some(state.clone())))?,
+            LiveState::Unchanged
+        );
+
+        fs::write(&real, "set -g mouse off")?;
+        assert_eq!(
+            live_state(&registry, &target(real.clone(), Some(state.clone())))?,
+            LiveState::Modified
+        );
+
+        fs::remove_file(&real)?;
+        assert_eq!(
+            live_state(&registry, &target(real, Some(state)))?,
+            LiveState::Missing
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_live_state_symlink_mode() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let wardrobe = dir.path().join("wardrobe");
+        fs::create_dir_all(wardrobe.join("tmux"))?;
+        fs::write(wardrobe.join("tmux").join("work"), "work")?;
+        let real = dir.path().join("tmux.conf");
+        std::os::unix::fs::symlink(wardrobe.join("tmux").join("work"), &real)?;
+
+        let registry = Registry {
+            base_path: wardrobe,
+            targets: HashMap::new(),
+        };
+        let mut tr = target(real.clone(), None);
+        tr.mode = Mode::Symlink;
+
+        assert_eq!(
+            live_state(&registry, &tr)?,
+            LiveState::Linked("work".to_string())
+        );
+
+        fs::remove_file(&real)?;
+        fs::write(&real, "work")?;
+        tr.state = Some(TargetState {
+            profile: "work".to_string(),
+            activated_at: 0,
+            fingerprint: io::fingerprint(&real, &Ignore::default())?,
+            ..Default::default()
+        });
+        assert_eq!(live_state(&registry, &tr)?, LiveState::Unlinked);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unmanaged_after_overlay() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let wardrobe = dir.path().join("wardrobe");
+        let real = dir.path().join("nvim");
+        fs::create_dir_all(wardrobe.join("nvim").join("work"))?;
+        fs::write(wardrobe.join("nvim").join("work").join("init.lua"), "work")?;
+        fs::create_dir_all(real.join("plugins"))?;
+        fs::write(real.join("init.lua"), "work")?;
+        fs::write(real.join("plugins").join("data.json"), "{}")?;
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            targets: HashMap::new(),
+        };
+        let managed = Ignore::default().applied(Apply::Overlay, &wardrobe.join("nvim/work"));
+        let mut state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 0,
+            fingerprint: io::fingerprint(&real, &managed)?,
+            apply: Apply::Overlay,
+        };
+        let mut tr = target(real.clone(), Some(state.clone()));
+        tr.name = "nvim".to_string();
+
+        assert_eq!(live_state(&registry, &tr)?, LiveState::Unchanged);
+        assert_eq!(
+            unmanaged(&registry, &tr)?,
+            Some(vec!["plugins/data.json".to_string()])
+        );
+
+        state.apply = Apply::Replace;
+        tr.state = Some(state);
+        assert_eq!(live_state(&registry, &tr)?, LiveState::Modified);
+        assert_eq!(unmanaged(&registry, &tr)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_status() {
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 0,
+            fingerprint: "".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_status("tmux", None, LiveState::Untracked, None),
+            "tmux: no active profile\n"
+        );
+        assert_eq!(
+            format_status("tmux", Some(&state), LiveState::Unchanged, None),
+            "tmux: work (unchanged, activated 1970-01-01 00:00:00 UTC)\n"
+        );
+        assert!(
+            format_status("tmux", Some(&state), LiveState::Modified, None)
+                .contains("modified since activation")
+        );
+        assert_eq!(
+            format_status("tmux", None, LiveState::Linked("home".to_string()), None),
+            "tmux: home (linked)\n"
+        );
+        let files = ["lazy-lock.json".to_string()];
+        assert_eq!(
+            format_status("tmux", Some(&state), LiveState::Unchanged, Some(&files)),
+            "tmux: work (unchanged, overlay with 1 unmanaged file, activated 1970-01-01 00:00:00 UTC)\n"
+        );
+    }
+
+    #[test]
+    fn test_status_json() {
+        let state = TargetState {
+            profile: "work".to_string(),
+            activated_at: 42,
+            fingerprint: "".to_string(),
+            ..Default::default()
+        };
+        let tr = target("/fake/tmux.conf".into(), Some(state));
+
+        let json = serde_json::to_value(status_json(&tr, LiveState::Modified, None)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "tmux",
+                "real_path": "/fake/tmux.conf",
+                "profile": "work",
+                "state": "modified",
+                "activated_at": 42,
+                "apply": "replace",
+            })
+        );
+
+        let json = serde_json::to_value(status_json(
+            &target("/fake/tmux.conf".into(), None),
+            LiveState::Linked("home".to_string()),
+            None,
+        ))
+        .unwrap();
+        assert_eq!(json["profile"], "home");
+        assert_eq!(json["state"], "linked");
+        assert!(json["activated_at"].is_null());
+    }
+
+    #[test]
+    fn test_status_missing_target_fails() {
+        let registry = Registry {
+            base_path: tempdir().unwrap().path().to_path_buf(),
+            targets: HashMap::new(),
+        };
+
+        let cmd = Status {
+            target: Some("ghost".to_string()),
+        };
+
+        assert!(status(&cmd, &registry, Format::Plain).is_err());
+    }
+
+    #[test]
+    fn test_status_all_targets() {
+        let dir = tempdir().unwrap();
+        let mut registry = Registry {
+            base_path: dir.path().to_path_buf(),
+            targets: HashMap::new(),
+        };
+        registry
+            .targets
+            .insert("tmux".to_string(), target(dir.path().join("missing"), None));
+
+        let cmd = Status { target: None };
+
+        assert!(status(&cmd, &registry, Format::Plain).is_ok());
+        assert!(status(&cmd, &registry, Format::Json).is_ok());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::dispatch::{io, outfit};
+use crate::domain::{Target, TargetAction};
+use crate::errors::RobeError;
+use crate::registry::{Registry, TargetRegistry, assert_name};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rename a target, or point it to another path.
+/// Live configs are never moved or copied.
+///
+/// Behaviour:
+/// rename: move `path/to/wardrobe/<target>` to `path/to/wardrobe/<new-name>`,
+/// re-linking a live config that links into it and renaming it in the outfits wearing it
+/// set-path: record `<path>` as the `real_path` of `<target>` in its `meta.toml`,
+/// renaming the stored profiles when the extension of the file changes
+pub fn target(cmd: &Target, registry: &Registry) -> Result<(), RobeError> {
+    let target_registry = registry.target_registry(&cmd.target)?;
+    match &cmd.action {
+        TargetAction::Rename(name) => rename(registry, &target_registry, name),
+        TargetAction::SetPath(path) => set_path(registry, &target_registry, path),
+    }
+}
+
+fn rename(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    name: &str,
+) -> Result<(), RobeError> {
+    assert_name("target", name)?;
+    if name == target_registry.name {
+        return Err(RobeError::BadUsage(format!(
+            "Target {} already has that name.",
+            name
+        )));
+    }
+    let to = registry.base_path.join(name);
+    if registry.get_target_registry(name).is_some() || to.exists() {
+        return Err(RobeError::AlreadyExists(format!(
+            "Target {} already exists.",
+            name
+        )));
+    }
+
+    let linked = io::linked_profile(registry, target_registry);
+    let from = registry.base_path.join(&target_registry.name);
+    fs::rename(&from, &to).map_err(RobeError::io(&format!("rename {} to", from.display()), &to))?;
+
+    if let Some(profile) = linked {
+        let renamed = TargetRegistry {
+            name: name.to_string(),
+            ..target_registry.clone()
+        };
+        io::link_into_place(
+            &registry.profile_path(&renamed, &profile),
+            &target_registry.real_path,
+        )?;
+    }
+    outfit::rename_target(registry, &target_registry.name, name)
+}
+
+fn set_path(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+    path: &Path,
+) -> Result<(), RobeError> {
+    let real_path = resolve(path)?;
+    let wardrobe = registry
+        .base_path
+        .canonicalize()
+        .map_err(RobeError::io("resolve", &registry.base_path))?;
+    if real_path.starts_with(&wardrobe) {
+        return Err(RobeError::BadUsage(format!(
+            "{} is inside the wardrobe.",
+            real_path.display()
+        )));
+    }
+    if let Some(other) = registry
+        .targets
+        .values()
+        .find(|tr| tr.name != target_registry.name && tr.real_path == real_path)
+    {
+        return Err(RobeError::Conflict(format!(
+            "{} is the path of target {} already.",
+            real_path.display(),
+            other.name
+        )));
+    }
+
+    let holds_dir = holds_dir(registry, target_registry);
+    if let Ok(meta) = fs::metadata(&real_path)
+        && meta.is_dir() != holds_dir
+    {
+        return Err(RobeError::Conflict(format!(
+            "{} is a {}, but the profiles of {} are {}.",
+            real_path.display(),
+            if meta.is_dir() { "directory" } else { "file" },
+            target_registry.name,
+            if holds_dir { "directories" } else { "files" }
+        )));
+    }
+
+    let moved = TargetRegistry {
+        real_path: real_path.clone(),
+        file_name: match real_path.file_name() {
+            Some(name) if !holds_dir => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        },
+        ..target_registry.clone()
+    };
+    // a live config moved along with its link into the wardrobe, found under the old names
+    let linked = io::linked_profile(
+        registry,
+        &TargetRegistry {
+            real_path: real_path.clone(),
+            ..target_registry.clone()
+        },
+    );
+
+    rename_profiles(registry, target_registry, &moved)?;
+    io::store_metadata(registry, &moved.metadata(), &target_registry.name)?;
+    if let Some(profile) = linked
+        && target_registry.extension() != moved.extension()
+    {
+        io::link_into_place(&registry.profile_path(&moved, &profile), &real_path)?;
+    }
+    Ok(())
+}
+
+/// `path` made absolute with its parent resolved, leaving a symlink at `path` itself as is.
+fn resolve(path: &Path) -> Result<PathBuf, RobeError> {
+    let absolute = std::path::absolute(path).map_err(RobeError::io("resolve", path))?;
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => Ok(parent
+            .canonicalize()
+            .map_err(RobeError::io("resolve", parent))?
+            .join(name)),
+        _ => Err(RobeError::BadUsage(format!(
+            "{} cannot be the path of a target.",
+            path.display()
+        ))),
+    }
+}
+
+/// Whether the profiles of a target are directories, judged by a stored profile if any.
+fn holds_dir(registry: &Registry, target_registry: &TargetRegistry) -> bool {
+    match target_registry.profiles.first() {
+        Some(profile) => registry.profile_path(target_registry, profile).is_dir(),
+        None => target_registry.file_name.is_none(),
+    }
+}
+
+/// Rename the stored profiles of `from` to the names they have under `to`, whose
+/// extension differs. Goes through temporary names, so no profile is renamed onto
+/// one that is still to be renamed.
+fn rename_profiles(
+    registry: &Registry,
+    from: &TargetRegistry,
+    to: &TargetRegistry,
+) -> Result<(), RobeError> {
+    if from.extension() == to.extension() {
+        return Ok(());
+    }
+    let target_dir = registry.base_path.join(&from.name);
+    let mut staged = Vec::new();
+    for (i, profile) in from.profiles.iter().enumerate() {
+        let old = registry.profile_path(from, profile);
+        let tmp = target_dir.join(format!(".{}.robe-rename", i));
+        fs::rename(&old, &tmp)
+            .map_err(RobeError::io(&format!("rename {} to", old.display()), &tmp))?;
+        staged.push((tmp, registry.profile_path(to, profile)));
+    }
+    for (tmp, new) in staged {
+        fs::rename(&tmp, &new)
+            .map_err(RobeError::io(&format!("rename {} to", tmp.display()), &new))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{META_FILE, Mode, TargetMetadata};
+    use crate::settings::Settings;
+    use crate::utils;
+    use tempfile::tempdir;
+
+    /// Registers `live` as `tmux` with the profiles `work` and `home`.
+    fn setup(dir: &Path, live: &Path, mode: Mode) -> Registry {
+        let wardrobe = dir.join("wardrobe");
+        let mut meta = TargetMetadata::create(live).unwrap();
+        meta.mode = mode;
+        let tr = TargetRegistry::new("tmux", &meta, &[]);
+        let registry = Registry {
+            base_path: wardrobe.clone(),
+            ..Default::default()
+        };
+        io::store_metadata(&registry, &meta, "tmux").unwrap();
+        for profile in ["work", "home"] {
+            io::replace_file_or_dir(
+                live,
+                &registry.profile_path(&tr, profile),
+                &io::CopyOptions::default(),
+            )
+            .unwrap();
+        }
+        reload(&wardrobe)
+    }
+
+    fn reload(wardrobe: &Path) -> Registry {
+        utils::get_registry(&Settings {
+            wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn cmd(target: &str, action: TargetAction) -> Target {
+        Target {
+            target: target.to_string(),
+            action,
+        }
+    }
+
+    #[test]
+    fn test_rename() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Copy);
+        let pieces = vec![("tmux".to_string(), "work".to_string())];
+        outfit::save(&registry, "work", &pieces, false)?;
+
+        target(
+            &cmd("tmux", TargetAction::Rename("tmux3".into())),
+            &registry,
+        )?;
+
+        let registry = reload(&registry.base_path);
+        assert!(registry.get_target_registry("tmux").is_none());
+        let tr = registry.target_registry("tmux3")?;
+        assert_eq!(tr.real_path, live.canonicalize()?);
+        assert_eq!(tr.profiles.len(), 2);
+        let outfit = outfit::load(&registry, "work")?;
+        assert_eq!(outfit.profiles.keys().collect::<Vec<_>>(), vec!["tmux3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_refuses_existing_and_invalid_names() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Copy);
+        fs::create_dir_all(registry.base_path.join("git"))?;
+
+        let err = target(&cmd("tmux", TargetAction::Rename("git".into())), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::AlreadyExists(_)));
+        let err = target(&cmd("tmux", TargetAction::Rename(".x".into())), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+        let err = target(&cmd("nope", TargetAction::Rename("x".into())), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::NotFound(_)));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rename_relinks_live_config() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Symlink);
+        let tr = registry.target_registry("tmux")?;
+        io::link_into_place(&registry.profile_path(&tr, "work"), &live)?;
+
+        target(
+            &cmd("tmux", TargetAction::Rename("tmux3".into())),
+            &registry,
+        )?;
+
+        let registry = reload(&registry.base_path);
+        let tr = registry.target_registry("tmux3")?;
+        assert_eq!(io::linked_profile(&registry, &tr).as_deref(), Some("work"));
+        assert_eq!(fs::read_to_string(&live)?, "live");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_path() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Copy);
+
+        fs::create_dir_all(dir.path().join("config").join("tmux"))?;
+        let moved = dir.path().join("config").join("tmux").join("tmux.conf");
+        fs::rename(&live, &moved)?;
+        target(
+            &cmd("tmux", TargetAction::SetPath(moved.clone())),
+            &registry,
+        )?;
+
+        let tr = reload(&registry.base_path).target_registry("tmux")?;
+        assert_eq!(tr.real_path, moved.canonicalize()?);
+        assert_eq!(tr.file_name.as_deref(), Some("tmux.conf"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_path_renames_profiles_for_new_extension() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join(".tmux");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Copy);
+        let target_dir = registry.base_path.join("tmux");
+        assert!(target_dir.join("work").exists());
+
+        let moved = dir.path().join("tmux.conf");
+        target(&cmd("tmux", TargetAction::SetPath(moved)), &registry)?;
+
+        assert!(!target_dir.join("work").exists());
+        assert_eq!(fs::read_to_string(target_dir.join("work.conf"))?, "live");
+        let tr = reload(&registry.base_path).target_registry("tmux")?;
+        let mut profiles = tr.profiles.clone();
+        profiles.sort();
+        assert_eq!(profiles, vec!["home", "work"]);
+        assert!(fs::read_to_string(target_dir.join(META_FILE))?.contains("tmux.conf"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_path_relinks_moved_link() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join(".zshrc");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Symlink);
+        let tr = registry.target_registry("tmux")?;
+        io::link_into_place(&registry.profile_path(&tr, "work"), &live)?;
+
+        let moved = dir.path().join("zshrc.sh");
+        fs::rename(&live, &moved)?;
+        target(
+            &cmd("tmux", TargetAction::SetPath(moved.clone())),
+            &registry,
+        )?;
+
+        let registry = reload(&registry.base_path);
+        let tr = registry.target_registry("tmux")?;
+        assert_eq!(
+            fs::read_link(&moved)?,
+            registry.base_path.join("tmux").join("work.sh")
+        );
+        assert_eq!(io::linked_profile(&registry, &tr).as_deref(), Some("work"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_path_refuses_other_kind() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "live")?;
+        let registry = setup(dir.path(), &live, Mode::Copy);
+        let meta = fs::read_to_string(registry.base_path.join("tmux").join(META_FILE))?;
+
+        let other = dir.path().join("nvim");
+        fs::create_dir_all(&other)?;
+        let err = target(&cmd("tmux", TargetAction::SetPath(other)), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::Conflict(_)));
+
+        let inside = registry.base_path.join("x");
+        let err = target(&cmd("tmux", TargetAction::SetPath(inside)), &registry).unwrap_err();
+        assert!(matches!(err, RobeError::BadUsage(_)));
+
+        let missing_parent = dir.path().join("missing").join("tmux.conf");
+        let err = target(
+            &cmd("tmux", TargetAction::SetPath(missing_parent)),
+            &registry,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RobeError::Io { .. }));
+
+        assert_eq!(
+            fs::read_to_string(registry.base_path.join("tmux").join(META_FILE))?,
+            meta
+        );
+        Ok(())
+    }
+}
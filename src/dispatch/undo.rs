@@ -0,0 +1,128 @@
+use crate::dispatch::io::{self, Backup};
+use crate::domain::Undo;
+use crate::errors::RobeError;
+use crate::registry::{Registry, TargetRegistry};
+use crate::utils;
+
+/// Restore the most recent backup of a target, or of any target if none is given.
+///
+/// Behaviour:
+/// copy `path/to/wardrobe/<target>/.backups/<latest>` back to the file/dir at `real_path`
+/// restore the active profile recorded with the backup
+/// delete the backup
+pub fn undo(cmd: &Undo, registry: &Registry) -> Result<(), RobeError> {
+    let targets: Vec<TargetRegistry> = match &cmd.target {
+        Some(t) => vec![registry.target_registry(t)?],
+        None => registry.targets.values().cloned().collect(),
+    };
+
+    let mut latest: Option<(TargetRegistry, Backup)> = None;
+    for tr in targets {
+        if let Some(backup) = io::list_backups(registry, &tr.name)?.pop()
+            && latest
+                .as_ref()
+                .is_none_or(|(_, l)| backup.created_at > l.created_at)
+        {
+            latest = Some((tr, backup));
+        }
+    }
+
+    let Some((tr, backup)) = latest else {
+        return Err(RobeError::NotFound(match &cmd.target {
+            Some(t) => format!("No backups to restore for {}.", t),
+            None => "No backups to restore.".to_string(),
+        }));
+    };
+
+    io::restore_backup(registry, &tr, &backup)?;
+    println!(
+        "Restored {} to its state before {}.",
+        tr.name,
+        utils::format_timestamp(backup.created_at / 1000)
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Use;
+    use crate::settings::Settings;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn setup(dir: &std::path::Path) -> Registry {
+        let wardrobe = dir.join("wardrobe");
+        let mut targets = HashMap::new();
+        for name in ["tmux", "git"] {
+            fs::create_dir_all(wardrobe.join(name)).unwrap();
+            fs::write(wardrobe.join(name).join("work"), format!("{} work", name)).unwrap();
+            fs::write(dir.join(name), format!("{} original", name)).unwrap();
+            targets.insert(
+                name.to_string(),
+                TargetRegistry {
+                    name: name.to_string(),
+                    real_path: dir.join(name),
+                    profiles: vec!["work".to_string()],
+                    ..Default::default()
+                },
+            );
+        }
+        Registry {
+            base_path: wardrobe,
+            targets,
+        }
+    }
+
+    fn wear(registry: &Registry, target: &str) {
+        let cmd = Use {
+            target: target.to_string(),
+            profile: "work".to_string(),
+            ..Default::default()
+        };
+        crate::dispatch::usecmd::usecmd(&cmd, registry, &Settings::default()).unwrap();
+    }
+
+    #[test]
+    fn test_undo_target() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        wear(&registry, "tmux");
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux work");
+
+        undo(&Undo { target: Some("tmux".to_string()) }, &registry)?;
+
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux original");
+        assert!(!registry.base_path.join("tmux").join("state.toml").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_picks_most_recent_target() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let registry = setup(dir.path());
+
+        wear(&registry, "tmux");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        wear(&registry, "git");
+
+        undo(&Undo { target: None }, &registry)?;
+
+        assert_eq!(fs::read_to_string(dir.path().join("git"))?, "git original");
+        assert_eq!(fs::read_to_string(dir.path().join("tmux"))?, "tmux work");
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_without_backups_fails() {
+        let dir = tempdir().unwrap();
+        let registry = setup(dir.path());
+
+        let err = undo(&Undo { target: Some("tmux".to_string()) }, &registry).unwrap_err();
+        assert!(format!("{}", err).contains("No backups to restore for tmux"));
+        assert!(undo(&Undo { target: None }, &registry).is_err());
+    }
+}
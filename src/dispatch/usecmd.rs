@@ -1,28 +1,209 @@
-use crate::dispatch::io;
+use crate::dispatch::{hooks, io};
 use crate::domain::Use;
 use crate::errors::RobeError;
-use crate::registry::Registry;
+use crate::ignore::Ignore;
+use crate::registry::{Apply, Event, Mode, Registry, TargetRegistry, TargetState};
+use crate::settings::Settings;
+use std::path::Path;
 
-pub fn usecmd(cmd: &Use, registry: &Registry) -> Result<(), RobeError> {
+/// Activate a profile of a target.
+/// Fails if the live config changed since the last activation, unless forced.
+///
+/// Behaviour:
+/// run the `pre-use` hooks of the target and of `<profile>`, failing if one fails
+/// with `-s`, save the live config into the last activated profile first,
+/// keeping the stored profile under `path/to/wardrobe/<target>/.history/<profile>`
+/// stage a copy of `path/to/wardrobe/<target>/<profile>` next to `real_path` and run the
+/// `validate` command of the target on it, leaving the live config untouched if it fails
+/// back up the live config under `path/to/wardrobe/<target>/.backups`, unless it links into the wardrobe
+/// swap the staged copy into `real_path`, or into a live directory entry by entry, leaving
+/// in place the entries its ignore patterns match, and with the `overlay` strategy
+/// (`apply` in `meta.toml`, or `-a`) those `<profile>` lacks
+/// or in symlink mode validate the profile itself and replace `real_path` with a symlink to it
+/// record `<profile>` as the active profile of `<target>`, and the strategy it was applied with
+/// run the `post-use` hooks, warning about those that fail
+pub fn usecmd(cmd: &Use, registry: &Registry, settings: &Settings) -> Result<(), RobeError> {
+    switch(cmd, registry, settings)?;
     let target_registry = registry.target_registry(&cmd.target)?;
+    hooks::run(registry, &target_registry, &cmd.profile, Event::PostUse)
+}
+
+/// Everything `usecmd` does but the `post-use` hooks, which an outfit only runs once every
+/// target is switched.
+pub fn switch(cmd: &Use, registry: &Registry, settings: &Settings) -> Result<(), RobeError> {
+    let mut target_registry = registry.target_registry(&cmd.target)?;
     target_registry.assert_profile_exists(&cmd.profile)?;
-    let target = target_registry.real_path;
-    let from = registry.base_path.clone().join(&cmd.target).join(&cmd.profile);
 
-    io::replace_file_or_dir(&from, &target)?;
-    Ok(())
+    let drifted = drifted_profile(registry, &target_registry)?;
+    if let Some(active) = &drifted
+        && !cmd.save
+        && !cmd.force
+    {
+        return Err(drift_error(&target_registry, active));
+    }
+    hooks::run(registry, &target_registry, &cmd.profile, Event::PreUse)?;
+
+    if let Some(active) = drifted
+        && cmd.save
+        && let Some(state) = &target_registry.state
+    {
+        // only what the active profile manages goes back into it
+        let ignore = Ignore::for_state(registry, &target_registry, state)?;
+        let opts = io::CopyOptions {
+            ignore: Some(&ignore),
+            ..Default::default()
+        };
+        let to = registry.profile_path(&target_registry, &active);
+        io::keep_version(registry, &target_registry, &active, settings.history)?;
+        io::replace_file_or_dir(&target_registry.real_path, &to, &opts)?.print_warnings();
+
+        // the live config matches the active profile again, as its backup is to record
+        let state = TargetState {
+            fingerprint: io::fingerprint(&target_registry.real_path, &ignore)?,
+            ..state.clone()
+        };
+        io::store_state(registry, &state, &cmd.target)?;
+        target_registry.state = Some(state);
+    }
+
+    let linked = io::linked_profile(registry, &target_registry).is_some();
+    // the live config is only backed up once the new one passed validation
+    let prepare = |staged: &Path| {
+        hooks::validate(&target_registry, staged)?;
+        if !linked {
+            io::backup_live(registry, &target_registry, settings.backups)?;
+        }
+        Ok(())
+    };
+
+    let from = registry.profile_path(&target_registry, &cmd.profile);
+    let target = target_registry.real_path.clone();
+    // only a directory copied over another one can be overlaid
+    let apply = match cmd.apply.unwrap_or(target_registry.apply) {
+        Apply::Overlay if target_registry.mode == Mode::Copy && from.is_dir() => Apply::Overlay,
+        _ => Apply::Replace,
+    };
+    let ignore = Ignore::for_target(&target_registry)?.applied(apply, &from);
+    let opts = io::CopyOptions {
+        ignore: Some(&ignore),
+        keep_ignored: true,
+        ..Default::default()
+    };
+
+    match target_registry.mode {
+        Mode::Copy => {
+            io::replace_file_or_dir_checked(&from, &target, &opts, prepare)?
+                .print_metadata_warnings();
+        }
+        Mode::Symlink => {
+            prepare(&from)?;
+            io::link_into_place(&from, &target)?
+        }
+    }
+    io::record_activation(registry, &cmd.target, &cmd.profile, &target, &ignore, apply)
+}
+
+/// Refusal to switch `target_registry`, whose live config changed since `active` was activated.
+pub fn drift_error(target_registry: &TargetRegistry, active: &str) -> RobeError {
+    RobeError::Drift(format!(
+        "{} has changed since {}/{} was activated. Use `-s` to save the changes into {}/{} first or `-f` to discard them.",
+        target_registry.real_path.display(),
+        &target_registry.name,
+        active,
+        &target_registry.name,
+        active
+    ))
+}
+
+/// The last activated profile, if the live config no longer matches it.
+/// A live config linking into the wardrobe never drifts: its edits are already in a profile.
+pub fn drifted_profile(
+    registry: &Registry,
+    target_registry: &TargetRegistry,
+) -> Result<Option<String>, RobeError> {
+    if io::linked_profile(registry, target_registry).is_some() {
+        return Ok(None);
+    }
+    match &target_registry.state {
+        Some(state) if target_registry.real_path.exists() => {
+            let ignore = Ignore::for_state(registry, target_registry, state)?;
+            if io::fingerprint(&target_registry.real_path, &ignore)? == state.fingerprint {
+                Ok(None)
+            } else {
+                Ok(Some(state.profile.clone()))
+            }
+        }
+        _ => Ok(None),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::domain::Use;
-    use crate::registry::{Registry, TargetRegistry};
     use crate::errors::RobeError;
+    use crate::registry::{Registry, TargetRegistry, TargetState};
     use std::collections::HashMap;
     use std::fs;
     use tempfile::tempdir;
 
+    fn setup(
+        wardrobe: &std::path::Path,
+        real_path: &std::path::Path,
+        state: Option<TargetState>,
+    ) -> Registry {
+        setup_with_mode(wardrobe, real_path, state, Mode::Copy)
+    }
+
+    fn setup_with_mode(
+        wardrobe: &std::path::Path,
+        real_path: &std::path::Path,
+        state: Option<TargetState>,
+        mode: Mode,
+    ) -> Registry {
+        let target_dir = wardrobe.join("tmux");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(target_dir.join("work"), "work content").unwrap();
+        fs::write(target_dir.join("home"), "home content").unwrap();
+
+        let mut targets = HashMap::new();
+        targets.insert(
+            "tmux".to_string(),
+            TargetRegistry {
+                name: "tmux".to_string(),
+                real_path: real_path.to_path_buf(),
+                profiles: vec!["work".to_string(), "home".to_string()],
+                state,
+                mode,
+                ..Default::default()
+            },
+        );
+
+        Registry {
+            base_path: wardrobe.to_path_buf(),
+            targets,
+        }
+    }
+
+    fn use_cmd(profile: &str, force: bool, save: bool) -> Use {
+        Use {
+            target: "tmux".to_string(),
+            profile: profile.to_string(),
+            force,
+            save,
+            ..Default::default()
+        }
+    }
+
+    fn active_state(real_path: &std::path::Path) -> TargetState {
+        TargetState {
+            profile: "work".to_string(),
+            activated_at: 0,
+            fingerprint: io::fingerprint(real_path, &Ignore::default()).unwrap(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_usecmd_replaces_file() -> Result<(), RobeError> {
         let tmp_wardrobe = tempdir().unwrap();
@@ -46,6 +227,7 @@ mod tests {
                 name: target_name.to_string(),
                 real_path: target_real_path.clone(),
                 profiles: vec![profile_name.to_string()],
+                ..Default::default()
             },
         );
 
@@ -57,13 +239,367 @@ mod tests {
         let cmd = Use {
             target: target_name.to_string(),
             profile: profile_name.to_string(),
+            ..Default::default()
         };
 
-        usecmd(&cmd, &registry)?;
+        usecmd(&cmd, &registry, &Settings::default())?;
 
         let content = fs::read_to_string(&target_real_path)?;
         assert_eq!(content, "profile content");
 
+        let backups = io::list_backups(&registry, target_name)?;
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0].path)?, "original content");
+
+        let state = fs::read_to_string(base_path.join(target_name).join("state.toml"))?;
+        let state: crate::registry::TargetState = toml::from_str(&state)?;
+        assert_eq!(state.profile, profile_name);
+        assert_eq!(state.fingerprint, io::fingerprint(&target_real_path, &Ignore::default())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_unchanged_live_config_switches() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+
+        let registry = setup(&tmp.path().join("wardrobe"), &real, Some(active_state(&real)));
+
+        usecmd(&use_cmd("home", false, false), &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_to_string(&real)?, "home content");
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_drift_requires_force() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+
+        let registry = setup(&tmp.path().join("wardrobe"), &real, Some(active_state(&real)));
+        fs::write(&real, "edited content")?;
+
+        let err = usecmd(&use_cmd("home", false, false), &registry, &Settings::default()).unwrap_err();
+        assert!(matches!(err, RobeError::Drift(_)));
+        assert!(format!("{}", err).contains("has changed since tmux/work was activated"));
+        assert_eq!(fs::read_to_string(&real)?, "edited content");
+
+        usecmd(&use_cmd("home", true, false), &registry, &Settings::default())?;
+        assert_eq!(fs::read_to_string(&real)?, "home content");
+        assert_eq!(
+            fs::read_to_string(registry.base_path.join("tmux").join("work"))?,
+            "work content"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_drift_save_captures_into_active_profile() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+
+        let registry = setup(&tmp.path().join("wardrobe"), &real, Some(active_state(&real)));
+        fs::write(&real, "edited content")?;
+
+        usecmd(&use_cmd("home", false, true), &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_to_string(&real)?, "home content");
+        assert_eq!(
+            fs::read_to_string(registry.base_path.join("tmux").join("work"))?,
+            "edited content"
+        );
+        // undoing the switch brings back a live config that matches its state
+        let backup = io::list_backups(&registry, "tmux")?.pop().unwrap();
+        assert_eq!(
+            backup.state()?.unwrap().fingerprint,
+            io::fingerprint(&backup.path, &Ignore::default())?
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usecmd_symlink_mode() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+
+        let wardrobe = tmp.path().join("wardrobe");
+        let registry = setup_with_mode(&wardrobe, &real, Some(active_state(&real)), Mode::Symlink);
+
+        usecmd(&use_cmd("home", false, false), &registry, &Settings::default())?;
+        assert_eq!(fs::read_link(&real)?, wardrobe.join("tmux").join("home"));
+
+        // edits through the link land in the profile and do not count as drift
+        fs::write(&real, "edited home")?;
+        usecmd(&use_cmd("work", false, false), &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_link(&real)?, wardrobe.join("tmux").join("work"));
+        assert_eq!(
+            fs::read_to_string(wardrobe.join("tmux").join("home"))?,
+            "edited home"
+        );
+        // the original file was backed up once; switching between links takes no backups
+        assert_eq!(io::list_backups(&registry, "tmux")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_without_state_does_not_check_drift() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "unknown content")?;
+
+        let registry = setup(&tmp.path().join("wardrobe"), &real, None);
+
+        usecmd(&use_cmd("home", false, false), &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_to_string(&real)?, "home content");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usecmd_failing_pre_use_hook_aborts() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+        let mut registry = setup(&tmp.path().join("wardrobe"), &real, None);
+        let tr = registry.targets.get_mut("tmux").unwrap();
+        tr.hooks.pre_use = Some("test \"$ROBE_PROFILE\" != home".to_string());
+
+        let err = usecmd(&use_cmd("home", false, false), &registry, &Settings::default())
+            .unwrap_err();
+        assert!(matches!(err, RobeError::Hook(_)));
+        assert_eq!(fs::read_to_string(&real)?, "work content");
+        assert!(io::list_backups(&registry, "tmux")?.is_empty());
+
+        usecmd(&use_cmd("work", false, false), &registry, &Settings::default())?;
+        assert_eq!(fs::read_to_string(&real)?, "work content");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usecmd_invalid_profile_leaves_live_config() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let real = tmp.path().join("tmux.conf");
+        fs::write(&real, "work content")?;
+        let mut registry = setup(&tmp.path().join("wardrobe"), &real, None);
+        let tr = registry.targets.get_mut("tmux").unwrap();
+        tr.validate = Some("grep -q work {path} || { echo no work here; false; }".to_string());
+
+        let err = usecmd(&use_cmd("home", false, false), &registry, &Settings::default())
+            .unwrap_err();
+        assert!(matches!(err, RobeError::Invalid(_)));
+        assert!(format!("{}", err).ends_with("no work here"));
+        assert_eq!(fs::read_dir(tmp.path())?.count(), 2);
+        assert_eq!(fs::read_to_string(&real)?, "work content");
+        assert!(io::list_backups(&registry, "tmux")?.is_empty());
+
+        fs::write(&real, "home content")?;
+        usecmd(&use_cmd("work", false, false), &registry, &Settings::default())?;
+        assert_eq!(fs::read_to_string(&real)?, "work content");
+        assert_eq!(io::list_backups(&registry, "tmux")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_leaves_ignored_live_entries() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let wardrobe = tmp.path().join("wardrobe");
+        let live = tmp.path().join("nvim");
+        for profile in ["work", "home"] {
+            fs::create_dir_all(wardrobe.join("nvim").join(profile))?;
+            fs::write(wardrobe.join("nvim").join(profile).join("init.lua"), profile)?;
+        }
+        fs::create_dir_all(live.join(".git"))?;
+        fs::write(live.join(".robeignore"), ".git/\n*.swp\n")?;
+        fs::write(live.join("init.lua"), "work")?;
+        fs::write(live.join(".git").join("HEAD"), "main")?;
+        let tr = TargetRegistry {
+            name: "nvim".to_string(),
+            real_path: live.clone(),
+            profiles: vec!["work".to_string(), "home".to_string()],
+            ..Default::default()
+        };
+        let registry = Registry {
+            base_path: wardrobe,
+            targets: HashMap::from([("nvim".to_string(), tr)]),
+        };
+
+        let cmd = Use {
+            target: "nvim".to_string(),
+            profile: "home".to_string(),
+            ..Default::default()
+        };
+        usecmd(&cmd, &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "home");
+        assert_eq!(fs::read_to_string(live.join(".git").join("HEAD"))?, "main");
+        assert!(live.join(".robeignore").exists());
+
+        // changes to ignored entries are no drift
+        fs::write(live.join(".git").join("HEAD"), "feature")?;
+        fs::write(live.join(".init.lua.swp"), "swap")?;
+        let state = fs::read_to_string(registry.base_path.join("nvim").join("state.toml"))?;
+        let mut tr = registry.target_registry("nvim")?;
+        tr.state = Some(toml::from_str(&state)?);
+        assert_eq!(drifted_profile(&registry, &tr)?, None);
+        Ok(())
+    }
+
+    fn overlay_registry(tmp: &std::path::Path, apply: Apply) -> Result<Registry, RobeError> {
+        let wardrobe = tmp.join("wardrobe");
+        let live = tmp.join("nvim");
+        for profile in ["work", "home"] {
+            let dir = wardrobe.join("nvim").join(profile);
+            fs::create_dir_all(dir.join("lua"))?;
+            fs::write(dir.join("init.lua"), profile)?;
+        }
+        fs::write(wardrobe.join("nvim/work/lua/keys.lua"), "work keys")?;
+        fs::create_dir_all(live.join("lua"))?;
+        fs::create_dir_all(live.join("plugins"))?;
+        fs::write(live.join("init.lua"), "mine")?;
+        fs::write(live.join("lua").join("keys.lua"), "my keys")?;
+        fs::write(live.join("plugins").join("data.json"), "{}")?;
+        let tr = TargetRegistry {
+            name: "nvim".to_string(),
+            real_path: live,
+            profiles: vec!["work".to_string(), "home".to_string()],
+            apply,
+            ..Default::default()
+        };
+        Ok(Registry {
+            base_path: wardrobe,
+            targets: HashMap::from([("nvim".to_string(), tr)]),
+        })
+    }
+
+    fn reloaded(registry: &Registry) -> Result<TargetRegistry, RobeError> {
+        let state = fs::read_to_string(registry.base_path.join("nvim").join("state.toml"))?;
+        let mut tr = registry.target_registry("nvim")?;
+        tr.state = Some(toml::from_str(&state)?);
+        Ok(tr)
+    }
+
+    #[test]
+    fn test_usecmd_overlay_leaves_unmanaged_live_files() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let registry = overlay_registry(tmp.path(), Apply::Overlay)?;
+        let live = tmp.path().join("nvim");
+
+        let cmd = Use {
+            target: "nvim".to_string(),
+            profile: "work".to_string(),
+            ..Default::default()
+        };
+        usecmd(&cmd, &registry, &Settings::default())?;
+
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "work");
+        assert_eq!(fs::read_to_string(live.join("lua/keys.lua"))?, "work keys");
+        assert_eq!(fs::read_to_string(live.join("plugins/data.json"))?, "{}");
+        let tr = reloaded(&registry)?;
+        assert_eq!(tr.state.as_ref().unwrap().apply, Apply::Overlay);
+
+        // only the files of the profile are tracked
+        fs::write(live.join("plugins").join("data.json"), "{\"a\": 1}")?;
+        fs::write(live.join("lazy-lock.json"), "{}")?;
+        assert_eq!(drifted_profile(&registry, &tr)?, None);
+        fs::write(live.join("lua").join("keys.lua"), "edited")?;
+        assert_eq!(drifted_profile(&registry, &tr)?, Some("work".to_string()));
+
+        // -s saves the managed files only, and home does not remove work's
+        let registry = Registry {
+            targets: HashMap::from([("nvim".to_string(), tr)]),
+            ..registry
+        };
+        let cmd = Use {
+            target: "nvim".to_string(),
+            profile: "home".to_string(),
+            save: true,
+            ..Default::default()
+        };
+        usecmd(&cmd, &registry, &Settings::default())?;
+
+        let work = registry.base_path.join("nvim").join("work");
+        assert_eq!(fs::read_to_string(work.join("lua/keys.lua"))?, "edited");
+        assert!(!work.join("plugins").exists());
+        assert!(!work.join("lazy-lock.json").exists());
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "home");
+        assert_eq!(fs::read_to_string(live.join("lua/keys.lua"))?, "edited");
+        assert!(live.join("lazy-lock.json").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usecmd_overlay_moves_only_the_profile_entries() -> Result<(), RobeError> {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let tmp = tempdir()?;
+        let registry = overlay_registry(tmp.path(), Apply::Overlay)?;
+        let live = tmp.path().join("nvim");
+        // a fifo blocks whoever opens it, so nothing may copy the unmanaged entries
+        let fifo = live.join("plugins").join("server");
+        assert!(
+            std::process::Command::new("mkfifo")
+                .arg(&fifo)
+                .status()?
+                .success()
+        );
+        let plugins = fs::metadata(live.join("plugins"))?.ino();
+        let keys = fs::metadata(live.join("lua").join("keys.lua"))?.ino();
+
+        let cmd = Use {
+            target: "nvim".to_string(),
+            profile: "home".to_string(),
+            ..Default::default()
+        };
+        // the switch alone, as a backup copies the whole live dir
+        let settings = Settings {
+            backups: 0,
+            ..Default::default()
+        };
+        usecmd(&cmd, &registry, &settings)?;
+
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "home");
+        assert_eq!(fs::metadata(live.join("plugins"))?.ino(), plugins);
+        assert_eq!(fs::metadata(live.join("lua").join("keys.lua"))?.ino(), keys);
+        assert!(fs::symlink_metadata(&fifo)?.file_type().is_fifo());
+        Ok(())
+    }
+
+    #[test]
+    fn test_usecmd_apply_flag_overrides_target() -> Result<(), RobeError> {
+        let tmp = tempdir()?;
+        let live = tmp.path().join("nvim");
+
+        let registry = overlay_registry(tmp.path(), Apply::Overlay)?;
+        let cmd = Use {
+            target: "nvim".to_string(),
+            profile: "home".to_string(),
+            apply: Some(Apply::Replace),
+            ..Default::default()
+        };
+        usecmd(&cmd, &registry, &Settings::default())?;
+        assert!(!live.join("plugins").exists());
+        assert!(!live.join("lua").join("keys.lua").exists());
+        assert_eq!(reloaded(&registry)?.state.unwrap().apply, Apply::Replace);
+
+        fs::remove_dir_all(tmp.path())?;
+        let registry = overlay_registry(tmp.path(), Apply::Replace)?;
+        let cmd = Use {
+            apply: Some(Apply::Overlay),
+            ..cmd
+        };
+        usecmd(&cmd, &registry, &Settings::default())?;
+        assert_eq!(fs::read_to_string(live.join("init.lua"))?, "home");
+        assert!(live.join("plugins").join("data.json").exists());
         Ok(())
     }
 }
@@ -1,42 +1,144 @@
-use crate::domain::View;
+use crate::dispatch::io;
+use crate::domain::{Format, View};
 use crate::errors::RobeError;
+use crate::ignore::Ignore;
 use crate::registry::Registry;
+use serde::Serialize;
 use std::fs;
 use std::path::Path;
 
-pub fn view(cmd: &View, registry: &Registry) -> Result<(), RobeError> {
+pub fn view(cmd: &View, registry: &Registry, format: Format) -> Result<(), RobeError> {
     let target_registry = registry.target_registry(&cmd.target)?;
+    let linked = match &cmd.profile {
+        None => io::linked_profile(registry, &target_registry),
+        Some(_) => None,
+    };
+
+    let ignore = Ignore::for_target(&target_registry)?;
 
     let fp = match &cmd.profile {
         Some(profile) => {
             target_registry.assert_profile_exists(profile)?;
-            registry
-                .base_path
-                .to_path_buf()
-                .join(&cmd.target)
-                .join(profile)
+            registry.profile_path(&target_registry, profile)
         }
         None => target_registry.real_path,
     };
 
-    let formatted = if fp.is_dir() {
-        format_dir(&fp, cmd.raw)?
+    if format == Format::Json {
+        let json = format_json(&cmd.target, cmd.profile.as_deref(), linked, &fp, &ignore)?;
+        println!("{}", serde_json::to_string(&json)?);
+        return Ok(());
+    }
+
+    let mut formatted = if fp.is_dir() {
+        format_dir(&fp, cmd.raw, &ignore)?
     } else {
         format_file(&fp, cmd.raw)?
     };
+    if let Some(profile) = linked
+        && !cmd.raw
+    {
+        formatted.insert_str(0, &format!("Linked to: {}/{}\n", &cmd.target, profile));
+    }
 
     println!("{}", formatted);
 
     Ok(())
 }
 
+#[derive(Debug, Serialize)]
+struct ViewJson {
+    target: String,
+    /// `null` for the live config
+    profile: Option<String>,
+    /// the profile the live config links to
+    linked: Option<String>,
+    path: String,
+    kind: &'static str,
+    size: u64,
+    /// `Some(None)`, serialized as `null`, for a file that is not UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contents: Option<Option<String>>,
+    /// `utf-8`, or `binary` when the contents are `null`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<Vec<EntryJson>>,
+}
+
+#[derive(Debug, Serialize)]
+struct EntryJson {
+    name: String,
+    kind: &'static str,
+    size: u64,
+}
+
+/// A file with its contents, or a directory with its entries sorted by name,
+/// but for those `ignore` leaves alone.
+fn format_json(
+    target: &str,
+    profile: Option<&str>,
+    linked: Option<String>,
+    fp: &Path,
+    ignore: &Ignore,
+) -> Result<ViewJson, RobeError> {
+    let mut json = ViewJson {
+        target: target.to_string(),
+        profile: profile.map(str::to_string),
+        linked,
+        path: fp.to_string_lossy().to_string(),
+        kind: "file",
+        size: io::size(fp)?,
+        contents: None,
+        encoding: None,
+        entries: None,
+    };
+    if !fp.is_dir() {
+        let contents = fs::read(fp).map_err(RobeError::io("read", fp))?;
+        let text = String::from_utf8(contents).ok();
+        json.encoding = Some(if text.is_some() { "utf-8" } else { "binary" });
+        json.contents = Some(text);
+        return Ok(json);
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(fp).map_err(RobeError::io("list", fp))? {
+        let entry = entry.map_err(RobeError::io("list", fp))?;
+        let path = entry.path();
+        let meta = entry
+            .metadata()
+            .map_err(RobeError::io("read the metadata of", &path))?;
+        if ignore.is_ignored(Path::new(&entry.file_name()), meta.is_dir()) {
+            continue;
+        }
+        let (kind, size) = if meta.is_symlink() {
+            ("symlink", meta.len())
+        } else if meta.is_dir() {
+            let rel = Path::new(&entry.file_name()).to_path_buf();
+            ("directory", io::size_within(fp, &rel, ignore)?)
+        } else {
+            ("file", meta.len())
+        };
+        entries.push(EntryJson {
+            name: entry.file_name().to_string_lossy().to_string(),
+            kind,
+            size,
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    json.size = entries.iter().map(|e| e.size).sum();
+    json.kind = "directory";
+    json.entries = Some(entries);
+    Ok(json)
+}
+
 fn format_file(fp: &Path, raw: bool) -> Result<String, RobeError> {
     let mut out = "".to_string();
     if !raw {
         out.push_str(&format!("File: {}\n", fp.display()));
         out.push_str("------------------------------\n\n");
     }
-    out.push_str(&fs::read_to_string(fp)?.to_string());
+    out.push_str(&fs::read_to_string(fp).map_err(RobeError::io("read", fp))?);
     if !raw {
         out.push_str("\n------------------------------\n");
         out.push_str(&format!("Path: {}", fp.display()));
@@ -44,22 +146,25 @@ fn format_file(fp: &Path, raw: bool) -> Result<String, RobeError> {
     Ok(out)
 }
 
-fn format_dir(fp: &Path, raw: bool) -> Result<String, RobeError> {
+fn format_dir(fp: &Path, raw: bool, ignore: &Ignore) -> Result<String, RobeError> {
     let mut out = "".to_string();
     if !raw {
         out.push_str(&format!("Directory: {}\n\n", fp.display()));
     }
-    out.push_str(&format_dir_raw(fp)?);
+    out.push_str(&format_dir_raw(fp, ignore)?);
     if !raw {
         out.push_str(&format!("\nPath: {}", fp.display()));
     }
     Ok(out)
 }
 
-fn format_dir_raw(fp: &Path) -> Result<String, RobeError> {
+fn format_dir_raw(fp: &Path, ignore: &Ignore) -> Result<String, RobeError> {
     let mut out = "".to_string();
 
-    let entries: Vec<_> = fs::read_dir(fp)?.collect::<Result<Vec<_>, _>>()?;
+    let mut entries: Vec<_> = fs::read_dir(fp)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(RobeError::io("list", fp))?;
+    entries.retain(|e| !ignore.is_ignored(Path::new(&e.file_name()), e.path().is_dir()));
     let mut dir_entries: Vec<_> = entries.iter().filter(|p| p.path().is_dir()).collect();
     dir_entries.sort_by_key(|e| e.file_name());
     let mut other_entries: Vec<_> = entries.iter().filter(|p| !p.path().is_dir()).collect();
@@ -121,7 +226,7 @@ mod tests {
         fs::write(base.join("z.txt"), "z").unwrap();
         fs::write(base.join("a.txt"), "a").unwrap();
 
-        let output = format_dir_raw(base).unwrap();
+        let output = format_dir_raw(base, &Ignore::default()).unwrap();
 
         let expected = "\
 a_dir/
@@ -141,7 +246,7 @@ z.txt
         fs::create_dir(base.join("folder")).unwrap();
         fs::write(base.join("file.txt"), "x").unwrap();
 
-        let output = format_dir(base, false).unwrap();
+        let output = format_dir(base, false, &Ignore::default()).unwrap();
 
         assert!(output.contains("Directory:"));
         assert!(output.contains("folder/"));
@@ -156,11 +261,122 @@ z.txt
 
         fs::create_dir(base.join("folder")).unwrap();
 
-        let output = format_dir(base, true).unwrap();
+        let output = format_dir(base, true, &Ignore::default()).unwrap();
 
         assert_eq!(output, "folder/\n");
     }
 
+    #[test]
+    fn test_format_json_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let file = dir.path().join("tmux.conf");
+        fs::write(&file, "set -g mouse on")?;
+
+        let json = serde_json::to_value(format_json(
+            "tmux",
+            None,
+            Some("work".to_string()),
+            &file,
+            &Ignore::default(),
+        )?)
+        .unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "target": "tmux",
+                "profile": null,
+                "linked": "work",
+                "path": file.to_string_lossy(),
+                "kind": "file",
+                "size": 15,
+                "contents": "set -g mouse on",
+                "encoding": "utf-8",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_json_binary_file() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let file = dir.path().join("font.bin");
+        fs::write(&file, [0xff, 0xfe, 0x00])?;
+
+        let json = serde_json::to_value(format_json(
+            "fonts",
+            Some("work"),
+            None,
+            &file,
+            &Ignore::default(),
+        )?)
+        .unwrap();
+
+        assert_eq!(json["contents"], serde_json::Value::Null);
+        assert_eq!(json["encoding"], "binary");
+        assert_eq!(json["size"], 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_json_dir() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        fs::create_dir(dir.path().join("lua"))?;
+        fs::write(dir.path().join("lua").join("a.lua"), "abc")?;
+        fs::write(dir.path().join("init.lua"), "print('hi')")?;
+
+        let json = serde_json::to_value(format_json(
+            "nvim",
+            Some("minimal"),
+            None,
+            dir.path(),
+            &Ignore::default(),
+        )?)
+        .unwrap();
+
+        assert_eq!(json["kind"], "directory");
+        assert_eq!(json["size"], 14);
+        assert!(json.get("contents").is_none());
+        assert!(json.get("encoding").is_none());
+        assert_eq!(
+            json["entries"],
+            serde_json::json!([
+                { "name": "init.lua", "kind": "file", "size": 11 },
+                { "name": "lua", "kind": "directory", "size": 3 },
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_json_dir_sizes_leave_out_ignored() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        fs::create_dir(dir.path().join("lua"))?;
+        fs::write(dir.path().join("lua").join("a.lua"), "abc")?;
+        fs::write(dir.path().join("lua").join("a.lua.swp"), "swap")?;
+        fs::write(dir.path().join("init.lua"), "print('hi')")?;
+        fs::write(dir.path().join("lazy-lock.json"), "{}")?;
+
+        let json = serde_json::to_value(format_json(
+            "nvim",
+            None,
+            None,
+            dir.path(),
+            &Ignore::new(["lazy-lock.json", "*.swp"]),
+        )?)
+        .unwrap();
+
+        assert_eq!(json["size"], 14);
+        assert_eq!(
+            json["entries"],
+            serde_json::json!([
+                { "name": "init.lua", "kind": "file", "size": 11 },
+                { "name": "lua", "kind": "directory", "size": 3 },
+            ])
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_view_file_profile() {
         use std::fs;
@@ -185,6 +401,7 @@ z.txt
                 name: "tmux".to_string(),
                 real_path: profile_file.clone(),
                 profiles: vec!["work".to_string()],
+                ..Default::default()
             },
         );
 
@@ -194,7 +411,7 @@ z.txt
             raw: false,
         };
 
-        let result = view(&cmd, &registry);
+        let result = view(&cmd, &registry, Format::Plain);
 
         assert!(result.is_ok());
     }
@@ -226,6 +443,7 @@ z.txt
                 name: "nvim".to_string(),
                 real_path: profile_dir.clone(),
                 profiles: vec!["minimal".to_string()],
+                ..Default::default()
             },
         );
 
@@ -235,7 +453,7 @@ z.txt
             raw: false,
         };
 
-        let result = view(&cmd, &registry);
+        let result = view(&cmd, &registry, Format::Plain);
 
         assert!(result.is_ok());
     }
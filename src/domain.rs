@@ -1,9 +1,130 @@
 use crate::errors::RobeError;
+use crate::registry::{Apply, Mode};
 use std::path::PathBuf;
 
+/// Options given before the command, e.g. `robe --config <file> list`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlobalOptions {
+    pub config: Option<PathBuf>,
+    pub format: Format,
+}
+
+/// How `list`, `view`, `status` and errors are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Plain,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub options: GlobalOptions,
+    pub command: Command,
+}
+
+fn global_bu() -> RobeError {
+    RobeError::BadUsage(
+        "Usage: robe [--config <file>] [--json | --format <json|plain>] <command> [args]"
+            .to_string(),
+    )
+}
+
+pub fn parse_cli(args: &[String]) -> Result<Invocation, RobeError> {
+    let (options, i) = parse_options(args)?;
+    Ok(Invocation {
+        options,
+        command: parse_cmd(&args[i..])?,
+    })
+}
+
+/// The global options at the start of `args`, and the index of the command after them.
+pub fn parse_options(args: &[String]) -> Result<(GlobalOptions, usize), RobeError> {
+    let mut options = GlobalOptions::default();
+    let mut i = 0;
+
+    while let Some(arg) = args.get(i) {
+        if arg == "--json" {
+            options.format = Format::Json;
+            i += 1;
+            continue;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) if name == "--config" || name == "--format" => (name, Some(value)),
+            _ if arg == "--config" || arg == "--format" => {
+                i += 1;
+                (arg.as_str(), args.get(i).map(String::as_str))
+            }
+            _ => break,
+        };
+        match (name, value) {
+            ("--config", Some(f)) if !f.is_empty() => options.config = Some(PathBuf::from(f)),
+            ("--format", Some("json")) => options.format = Format::Json,
+            ("--format", Some("plain")) => options.format = Format::Plain,
+            _ => return Err(global_bu()),
+        }
+        i += 1;
+    }
+
+    Ok((options, i))
+}
+
+/// Every command with its flags, as offered by `robe completions`.
+pub const COMMANDS: &[(&str, &[&str])] = &[
+    (
+        "add",
+        &[
+            "-r",
+            "--register",
+            "-m",
+            "--mode",
+            "-f",
+            "--force",
+            "-L",
+            "--dereference",
+        ],
+    ),
+    ("use", &["-f", "--force", "-s", "--save", "-a", "--apply"]),
+    ("view", &["--raw"]),
+    ("edit", &[]),
+    ("list", &[]),
+    ("ls", &[]),
+    ("rm", &[]),
+    ("mv", &["-f", "--force"]),
+    ("cp", &["-f", "--force"]),
+    ("target", &[]),
+    ("outfit", &["-f", "--force", "-s", "--save"]),
+    ("status", &[]),
+    ("diff", &["--stat"]),
+    ("undo", &[]),
+    ("history", &[]),
+    ("restore", &[]),
+    ("config", &[]),
+    ("completions", &[]),
+    ("help", &[]),
+];
+
+/// Flags accepted before, or instead of, a command.
+pub const GLOBAL_FLAGS: &[&str] = &[
+    "--config",
+    "--json",
+    "--format",
+    "-h",
+    "--help",
+    "-v",
+    "--version",
+];
+
 pub fn parse_cmd(args: &[String]) -> Result<Command, RobeError> {
+    // the words being completed may contain anything, `-h` included
+    if args.first().is_some_and(|a| a == "__complete") {
+        return Ok(Command::Complete(Complete {
+            words: args[1..].to_vec(),
+        }));
+    }
     if args.contains(&"-h".to_string()) || args.contains(&"--help".to_string()) {
-        return Ok(Command::Help(args.join(" ")));
+        let topic = args.first().and_then(|a| command_name(a));
+        return Ok(Command::Help(topic.map(str::to_string)));
     }
     if args.contains(&"-v".to_string()) || args.contains(&"--version".to_string()) {
         return Ok(Command::Version);
@@ -26,14 +147,85 @@ fn parse_internal(cmd: &str, args: &[String]) -> Result<Command, RobeError> {
         "list" => List::parse(args, "list"),
         "ls" => List::parse(args, "ls"),
         "rm" => Rm::parse(args),
+        "mv" => Mv::parse(args),
+        "cp" => Cp::parse(args),
+        "target" => Target::parse(args),
+        "outfit" => Outfit::parse(args),
         "view" => View::parse(args),
-        other => Err(RobeError::BadUsage(format!(
-            "Command not recognized: {}",
-            other
-        ))),
+        "status" => Status::parse(args),
+        "diff" => Diff::parse(args),
+        "undo" => Undo::parse(args),
+        "history" => History::parse(args),
+        "restore" => Restore::parse(args),
+        "config" => Config::parse(args),
+        "completions" => Completions::parse(args),
+        "help" => match args {
+            [] => Ok(Command::Help(None)),
+            [topic] => Ok(Command::Help(Some(
+                command_name(topic)
+                    .ok_or_else(|| not_recognized(topic))?
+                    .to_string(),
+            ))),
+            _ => Err(RobeError::BadUsage(
+                "Usage: robe help [<command>]".to_string(),
+            )),
+        },
+        other => Err(not_recognized(other)),
     }
 }
 
+/// The name a command is documented under, `list` for `ls`.
+fn command_name(cmd: &str) -> Option<&'static str> {
+    match cmd {
+        "ls" => Some("list"),
+        _ => COMMANDS
+            .iter()
+            .map(|(name, _)| *name)
+            .find(|name| *name == cmd),
+    }
+}
+
+fn not_recognized(cmd: &str) -> RobeError {
+    match closest_command(cmd) {
+        Some(name) => RobeError::BadUsage(format!(
+            "Command not recognized: {}. Did you mean `{}`?",
+            cmd, name
+        )),
+        None => RobeError::BadUsage(format!("Command not recognized: {}", cmd)),
+    }
+}
+
+/// The command `cmd` is most likely a typo of, if any is close enough.
+fn closest_command(cmd: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|(name, _)| (edit_distance(cmd, name), *name))
+        .filter(|(distance, name)| *distance <= 2 && *distance < name.len())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// Levenshtein distance, counting a swap of two neighbouring characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows i-2, i-1 and i of the distance table
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut prev, row);
+    }
+    prev[b.len()]
+}
+
 fn split_target_and_profile<F>(joined: &str, bad_usage: F) -> Result<(String, String), RobeError>
 where
     F: Fn() -> RobeError,
@@ -49,7 +241,8 @@ where
 
 #[derive(Debug, Clone)]
 pub enum Command {
-    Help(String),
+    /// Help on a command, or on robe as a whole.
+    Help(Option<String>),
     Version,
     Add(Add),
     Register(Register),
@@ -58,6 +251,18 @@ pub enum Command {
     View(View),
     List(List),
     Rm(Rm),
+    Mv(Mv),
+    Cp(Cp),
+    Target(Target),
+    Outfit(Outfit),
+    Status(Status),
+    Diff(Diff),
+    Undo(Undo),
+    History(History),
+    Restore(Restore),
+    Config(Config),
+    Completions(Completions),
+    Complete(Complete),
 }
 
 #[derive(Debug, Clone, Default)]
@@ -65,11 +270,12 @@ pub struct Add {
     pub target: String,
     pub profile: String,
     pub force: bool,
+    pub dereference: bool,
 }
 
 impl Add {
     fn bu() -> RobeError {
-        RobeError::BadUsage("Usage: robe add <target>/<profile> [-r file] [-f]".to_string())
+        RobeError::BadUsage("Usage: robe add <target>/<profile> [-r file] [-f] [-L]".to_string())
     }
 
     pub fn parse(args: &[String]) -> Result<Command, RobeError> {
@@ -84,6 +290,7 @@ impl Add {
         while let Some(arg) = args.get(i) {
             match arg.as_str() {
                 "-f" | "--force" => cmd.force = true,
+                "-L" | "--dereference" => cmd.dereference = true,
                 t if !seen_target => {
                     let (target, profile) = split_target_and_profile(t, Self::bu)?;
                     cmd.target = target;
@@ -108,11 +315,15 @@ pub struct Register {
     pub target: String,
     pub profile: String,
     pub register_file_path: PathBuf,
+    pub mode: Mode,
+    pub dereference: bool,
 }
 
 impl Register {
     fn bu() -> RobeError {
-        RobeError::BadUsage("Usage: robe add <target>/<profile> [-r <path>]".to_string())
+        RobeError::BadUsage(
+            "Usage: robe add <target>/<profile> [-r <path>] [-m <copy|symlink>] [-L]".to_string(),
+        )
     }
 
     pub fn parse(args: &[String]) -> Result<Command, RobeError> {
@@ -130,6 +341,14 @@ impl Register {
                         return Err(Self::bu());
                     }
                 }
+                "-m" | "--mode" => {
+                    i += 1;
+                    match args.get(i).and_then(|m| Mode::parse(m)) {
+                        Some(mode) => cmd.mode = mode,
+                        None => return Err(Self::bu()),
+                    }
+                }
+                "-L" | "--dereference" => cmd.dereference = true,
                 t if !seen_target => {
                     let (target, profile) = split_target_and_profile(t, Self::bu)?;
                     cmd.target = target;
@@ -227,19 +446,45 @@ impl View {
 pub struct Use {
     pub target: String,
     pub profile: String,
+    pub force: bool,
+    pub save: bool,
+    /// overrides the `apply` strategy of the target
+    pub apply: Option<Apply>,
 }
 
 impl Use {
     fn bu() -> RobeError {
-        RobeError::BadUsage("Usage: robe use <target>/<profile>".to_string())
+        RobeError::BadUsage(
+            "Usage: robe use <target>/<profile> [-f] [-s] [-a <replace|overlay>]".to_string(),
+        )
     }
     pub fn parse(args: &[String]) -> Result<Command, RobeError> {
-        if args.is_empty() || args.len() != 1 {
-            Err(Self::bu())
+        let mut cmd = Self::default();
+        let mut seen_target = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-f" | "--force" => cmd.force = true,
+                "-s" | "--save" => cmd.save = true,
+                "-a" | "--apply" => match args.next().and_then(|a| Apply::parse(a)) {
+                    Some(apply) => cmd.apply = Some(apply),
+                    None => return Err(Self::bu()),
+                },
+                t if !seen_target => {
+                    let (target, profile) = split_target_and_profile(t, Self::bu)?;
+                    cmd.target = target;
+                    cmd.profile = profile;
+                    seen_target = true;
+                }
+                _ => return Err(Self::bu()),
+            }
+        }
+
+        if seen_target {
+            Ok(Command::Use(cmd))
         } else {
-            let first = args[0].clone();
-            let (target, profile) = split_target_and_profile(&first, Self::bu)?;
-            Ok(Command::Use(Self { target, profile }))
+            Err(Self::bu())
         }
     }
 }
@@ -291,6 +536,403 @@ impl Rm {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct Mv {
+    pub target: String,
+    pub profile: String,
+    pub new_profile: String,
+    pub force: bool,
+}
+
+impl Mv {
+    fn bu() -> RobeError {
+        RobeError::BadUsage(
+            "Usage: robe mv <target>/<profile> <target>/<new-profile> [-f]".to_string(),
+        )
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let cp = Cp::parse_with(args, Self::bu)?;
+        if cp.from_target != cp.target {
+            return Err(RobeError::BadUsage(format!(
+                "mv renames a profile within {}. Use `robe cp` to copy it to {}.",
+                cp.from_target, cp.target
+            )));
+        }
+        Ok(Command::Mv(Self {
+            target: cp.target,
+            profile: cp.from_profile,
+            new_profile: cp.profile,
+            force: cp.force,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cp {
+    pub from_target: String,
+    pub from_profile: String,
+    pub target: String,
+    pub profile: String,
+    pub force: bool,
+}
+
+impl Cp {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe cp <target>/<profile> <target>/<profile> [-f]".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        Ok(Command::Cp(Self::parse_with(args, Self::bu)?))
+    }
+
+    /// Two `<target>/<profile>` arguments and `-f`, in any order.
+    fn parse_with<F>(args: &[String], bad_usage: F) -> Result<Self, RobeError>
+    where
+        F: Fn() -> RobeError,
+    {
+        let mut cmd = Self::default();
+        let mut seen = 0;
+        for arg in args {
+            match arg.as_str() {
+                "-f" | "--force" => cmd.force = true,
+                tp if seen == 0 => {
+                    (cmd.from_target, cmd.from_profile) = split_target_and_profile(tp, &bad_usage)?;
+                    seen += 1;
+                }
+                tp if seen == 1 => {
+                    (cmd.target, cmd.profile) = split_target_and_profile(tp, &bad_usage)?;
+                    seen += 1;
+                }
+                _ => return Err(bad_usage()),
+            }
+        }
+        if seen == 2 { Ok(cmd) } else { Err(bad_usage()) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetAction {
+    Rename(String),
+    SetPath(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub target: String,
+    pub action: TargetAction,
+}
+
+impl Target {
+    fn bu() -> RobeError {
+        RobeError::BadUsage(
+            "Usage: robe target <rename <target> <new-name> | set-path <target> <path>>"
+                .to_string(),
+        )
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let (target, action) = match args {
+            [action, target, name] if action == "rename" => {
+                (target, TargetAction::Rename(name.clone()))
+            }
+            [action, target, path] if action == "set-path" && !path.is_empty() => {
+                (target, TargetAction::SetPath(PathBuf::from(path)))
+            }
+            _ => return Err(Self::bu()),
+        };
+        Ok(Command::Target(Self {
+            target: target.clone(),
+            action,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutfitAction {
+    /// Record `profiles` as `(target, profile)` pairs, overwriting an outfit with `force`.
+    Save {
+        name: String,
+        profiles: Vec<(String, String)>,
+        force: bool,
+    },
+    /// Activate every profile of an outfit, with the `force` and `save` of `robe use`.
+    Wear {
+        name: String,
+        force: bool,
+        save: bool,
+    },
+    List,
+    Rm(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Outfit {
+    pub action: OutfitAction,
+}
+
+impl Outfit {
+    fn bu() -> RobeError {
+        RobeError::BadUsage(
+            "Usage: robe outfit <save <name> <target>/<profile>... [-f] | wear <name> [-f] [-s] | list | rm <name>>"
+                .to_string(),
+        )
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let flags: Vec<&str> = args
+            .iter()
+            .map(String::as_str)
+            .filter(|a| a.starts_with('-'))
+            .collect();
+        let words: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+        let has = |short: &str, long: &str| flags.contains(&short) || flags.contains(&long);
+        let allowed: &[&str] = match words.first().map(|w| w.as_str()) {
+            Some("save") => &["-f", "--force"],
+            Some("wear") => &["-f", "--force", "-s", "--save"],
+            _ => &[],
+        };
+        if flags.iter().any(|f| !allowed.contains(f)) {
+            return Err(Self::bu());
+        }
+
+        let action = match words.as_slice() {
+            [action, name, profiles @ ..] if *action == "save" && !profiles.is_empty() => {
+                OutfitAction::Save {
+                    name: name.to_string(),
+                    profiles: profiles
+                        .iter()
+                        .map(|tp| split_target_and_profile(tp, Self::bu))
+                        .collect::<Result<_, _>>()?,
+                    force: has("-f", "--force"),
+                }
+            }
+            [action, name] if *action == "wear" => OutfitAction::Wear {
+                name: name.to_string(),
+                force: has("-f", "--force"),
+                save: has("-s", "--save"),
+            },
+            [action] if *action == "list" => OutfitAction::List,
+            [action, name] if *action == "rm" => OutfitAction::Rm(name.to_string()),
+            _ => return Err(Self::bu()),
+        };
+        Ok(Command::Outfit(Self { action }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub target: Option<String>,
+}
+
+impl Status {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe status [<target>]".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        if args.len() > 1 {
+            Err(Self::bu())
+        } else {
+            let target = args.first().cloned();
+            Ok(Command::Status(Self { target }))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Undo {
+    pub target: Option<String>,
+}
+
+impl Undo {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe undo [<target>]".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        if args.len() > 1 {
+            Err(Self::bu())
+        } else {
+            let target = args.first().cloned();
+            Ok(Command::Undo(Self { target }))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    pub target: String,
+    pub profile: String,
+}
+
+impl History {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe history <target>/<profile>".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        match args {
+            [joined] => {
+                let (target, profile) = split_target_and_profile(joined, Self::bu)?;
+                Ok(Command::History(Self { target, profile }))
+            }
+            _ => Err(Self::bu()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Restore {
+    pub target: String,
+    pub profile: String,
+    /// position among the earlier versions, 1 being the most recent, or the time one was
+    /// taken at, in seconds since the unix epoch
+    pub version: u64,
+}
+
+impl Restore {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe restore <target>/<profile>@<n|timestamp>".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let [joined] = args else {
+            return Err(Self::bu());
+        };
+        let (joined, version) = joined.rsplit_once('@').ok_or_else(Self::bu)?;
+        let (target, profile) = split_target_and_profile(joined, Self::bu)?;
+        match version.parse::<u64>() {
+            Ok(version) if version > 0 => Ok(Command::Restore(Self {
+                target,
+                profile,
+                version,
+            })),
+            _ => Err(Self::bu()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completions {
+    pub shell: Shell,
+}
+
+impl Completions {
+    fn bu() -> RobeError {
+        RobeError::BadUsage("Usage: robe completions <bash|zsh|fish>".to_string())
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let shell = match args {
+            [shell] if shell == "bash" => Shell::Bash,
+            [shell] if shell == "zsh" => Shell::Zsh,
+            [shell] if shell == "fish" => Shell::Fish,
+            _ => return Err(Self::bu()),
+        };
+        Ok(Command::Completions(Self { shell }))
+    }
+}
+
+/// `robe __complete <words>`, called by the completion scripts with the words
+/// after `robe`, the last one being the word under the cursor.
+#[derive(Debug, Clone, Default)]
+pub struct Complete {
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigAction {
+    Check,
+    List,
+    Get(String),
+    Set(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub action: ConfigAction,
+}
+
+impl Config {
+    fn bu() -> RobeError {
+        RobeError::BadUsage(
+            "Usage: robe config <check | list | get <key> | set <key> <value>>".to_string(),
+        )
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let action = match args {
+            [action] if action == "check" => ConfigAction::Check,
+            [action] if action == "list" => ConfigAction::List,
+            [action, key] if action == "get" => ConfigAction::Get(key.clone()),
+            [action, key, value] if action == "set" => {
+                ConfigAction::Set(key.clone(), value.clone())
+            }
+            _ => return Err(Self::bu()),
+        };
+        Ok(Command::Config(Self { action }))
+    }
+}
+
+/// One side of a diff: a stored profile, or the live config when `profile` is `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffSide {
+    pub target: String,
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diff {
+    pub from: DiffSide,
+    pub to: DiffSide,
+    pub stat: bool,
+}
+
+impl Diff {
+    fn bu() -> RobeError {
+        RobeError::BadUsage(
+            "Usage: robe diff <target>/<profile> [<target>[/<profile>]] [--stat]".to_string(),
+        )
+    }
+    pub fn parse(args: &[String]) -> Result<Command, RobeError> {
+        let mut stat = false;
+        let mut sides: Vec<DiffSide> = Vec::new();
+
+        for arg in args {
+            match arg.as_str() {
+                "--stat" => stat = true,
+                tp if sides.len() < 2 => {
+                    let side = if tp.contains('/') {
+                        let (t, p) = split_target_and_profile(tp, Self::bu)?;
+                        DiffSide {
+                            target: t,
+                            profile: Some(p),
+                        }
+                    } else {
+                        DiffSide {
+                            target: tp.to_string(),
+                            profile: None,
+                        }
+                    };
+                    sides.push(side);
+                }
+                _ => return Err(Self::bu()),
+            }
+        }
+
+        let mut sides = sides.into_iter();
+        match (sides.next(), sides.next()) {
+            (Some(from), None) if from.profile.is_some() => {
+                let to = DiffSide {
+                    target: from.target.clone(),
+                    profile: None,
+                };
+                Ok(Command::Diff(Self { from, to, stat }))
+            }
+            (Some(from), Some(to)) => Ok(Command::Diff(Self { from, to, stat })),
+            _ => Err(Self::bu()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -299,6 +941,70 @@ mod tests {
         parse_cmd(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
     }
 
+    // ---------- GLOBAL OPTIONS ----------
+
+    fn parse_cli_vec(args: &[&str]) -> Result<Invocation, RobeError> {
+        parse_cli(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_cli_without_options() {
+        let inv = parse_cli_vec(&["list"]).unwrap();
+        assert_eq!(inv.options, GlobalOptions::default());
+        assert!(matches!(inv.command, Command::List(_)));
+    }
+
+    #[test]
+    fn test_parse_cli_config() {
+        let inv = parse_cli_vec(&["--config", "/tmp/c.toml", "use", "tmux/work"]).unwrap();
+        assert_eq!(inv.options.config, Some(PathBuf::from("/tmp/c.toml")));
+        assert!(matches!(inv.command, Command::Use(_)));
+
+        let inv = parse_cli_vec(&["--config=/tmp/c.toml", "-h"]).unwrap();
+        assert_eq!(inv.options.config, Some(PathBuf::from("/tmp/c.toml")));
+        assert!(matches!(inv.command, Command::Help(_)));
+    }
+
+    #[test]
+    fn test_parse_cli_format() {
+        for args in [
+            vec!["--json", "status"],
+            vec!["--format", "json", "status"],
+            vec!["--format=plain", "--json", "status"],
+        ] {
+            let inv = parse_cli_vec(&args).unwrap();
+            assert_eq!(inv.options.format, Format::Json);
+            assert!(matches!(inv.command, Command::Status(_)));
+        }
+
+        let inv =
+            parse_cli_vec(&["--json", "--format=plain", "--config=/tmp/c.toml", "list"]).unwrap();
+        assert_eq!(inv.options.format, Format::Plain);
+        assert_eq!(inv.options.config, Some(PathBuf::from("/tmp/c.toml")));
+    }
+
+    #[test]
+    fn test_parse_cli_bad_usage() {
+        for args in [vec!["--config"], vec!["--config="]] {
+            match parse_cli_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("--config <file>")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+        for args in [vec!["--format"], vec!["--format=yaml", "list"]] {
+            match parse_cli_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("--format <json|plain>")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+        // global options go before the command
+        assert!(parse_cli_vec(&["list", "--config", "/tmp/c.toml"]).is_err());
+        match parse_cli_vec(&["--config", "/tmp/c.toml"]).unwrap_err() {
+            RobeError::BadUsage(msg) => assert!(msg.contains("No command provided")),
+            _ => panic!("Expected BadUsage"),
+        }
+    }
+
     // ---------- ADD ----------
 
     #[test]
@@ -308,11 +1014,24 @@ mod tests {
                 assert_eq!(a.target, "target");
                 assert_eq!(a.profile, "profile");
                 assert!(!a.force);
+                assert!(!a.dereference);
             }
             _ => panic!("Expected Add"),
         }
     }
 
+    #[test]
+    fn test_add_dereference() {
+        match parse_vec(&["add", "target/profile", "-L"]).unwrap() {
+            Command::Add(a) => assert!(a.dereference),
+            _ => panic!("Expected Add"),
+        }
+        match parse_vec(&["add", "--dereference", "target/profile", "-r", "file"]).unwrap() {
+            Command::Register(a) => assert!(a.dereference),
+            _ => panic!("Expected Register"),
+        }
+    }
+
     #[test]
     fn test_add_force() {
         match parse_vec(&["add", "target/profile", "-f"]).unwrap() {
@@ -368,9 +1087,33 @@ mod tests {
                 assert_eq!(a.target, "target");
                 assert_eq!(a.profile, "profile");
                 assert_eq!(a.register_file_path, PathBuf::from("file"));
+                assert_eq!(a.mode, Mode::Copy);
+            }
+            _ => panic!("Expected Register"),
+        }
+    }
+
+    #[test]
+    fn test_register_mode() {
+        match parse_vec(&["add", "target/profile", "-r", "file", "-m", "symlink"]).unwrap() {
+            Command::Register(a) => {
+                assert_eq!(a.register_file_path, PathBuf::from("file"));
+                assert_eq!(a.mode, Mode::Symlink);
             }
             _ => panic!("Expected Register"),
         }
+        match parse_vec(&["add", "--mode", "copy", "target/profile", "-r", "file"]).unwrap() {
+            Command::Register(a) => assert_eq!(a.mode, Mode::Copy),
+            _ => panic!("Expected Register"),
+        }
+        for mode in [vec!["-m"], vec!["-m", "hardlink"]] {
+            let mut args = vec!["add", "target/profile", "-r", "file"];
+            args.extend(mode);
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("-m <copy|symlink>")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
     }
 
     #[test]
@@ -412,6 +1155,8 @@ mod tests {
             Command::Use(u) => {
                 assert_eq!(u.target, "target");
                 assert_eq!(u.profile, "profile");
+                assert!(!u.force);
+                assert!(!u.save);
             }
             _ => panic!("Expected Use"),
         }
@@ -421,6 +1166,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_use_flags() {
+        match parse_vec(&["use", "-f", "target/profile"]).unwrap() {
+            Command::Use(u) => {
+                assert_eq!(u.target, "target");
+                assert!(u.force);
+                assert!(!u.save);
+            }
+            _ => panic!("Expected Use"),
+        }
+        match parse_vec(&["use", "target/profile", "--save"]).unwrap() {
+            Command::Use(u) => {
+                assert!(!u.force);
+                assert!(u.save);
+                assert_eq!(u.apply, None);
+            }
+            _ => panic!("Expected Use"),
+        }
+        match parse_vec(&["use", "-a", "overlay", "target/profile"]).unwrap() {
+            Command::Use(u) => assert_eq!(u.apply, Some(Apply::Overlay)),
+            _ => panic!("Expected Use"),
+        }
+        match parse_vec(&["use", "target/profile", "--apply", "replace"]).unwrap() {
+            Command::Use(u) => assert_eq!(u.apply, Some(Apply::Replace)),
+            _ => panic!("Expected Use"),
+        }
+        for apply in [vec!["-a"], vec!["--apply", "merge"]] {
+            let mut args = vec!["use", "target/profile"];
+            args.extend(apply);
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("-a <replace|overlay>")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
     #[test]
     fn test_use_bad_usage() {
         match parse_vec(&["use"]).unwrap_err() {
@@ -435,6 +1216,12 @@ mod tests {
             }
             _ => panic!("Expected BadUsage"),
         }
+        match parse_vec(&["use", "-f"]).unwrap_err() {
+            RobeError::BadUsage(msg) => {
+                assert!(msg.contains("robe use <target>/<profile>"));
+            }
+            _ => panic!("Expected BadUsage"),
+        }
     }
 
     // ---------- EDIT ----------
@@ -641,20 +1428,434 @@ mod tests {
         }
     }
 
+    // ---------- MV / CP ----------
+
+    #[test]
+    fn test_mv() {
+        match parse_vec(&["mv", "tmux/work", "tmux/office", "-f"]).unwrap() {
+            Command::Mv(m) => {
+                assert_eq!(m.target, "tmux");
+                assert_eq!(m.profile, "work");
+                assert_eq!(m.new_profile, "office");
+                assert!(m.force);
+            }
+            _ => panic!("Expected Mv"),
+        }
+    }
+
+    #[test]
+    fn test_mv_bad_usage() {
+        assert!(parse_vec(&["mv", "tmux/work"]).is_err());
+        assert!(parse_vec(&["mv", "tmux", "tmux/office"]).is_err());
+        assert!(parse_vec(&["mv", "tmux/work", "tmux/a", "tmux/b"]).is_err());
+        match parse_vec(&["mv", "tmux/work", "git/work"]).unwrap_err() {
+            RobeError::BadUsage(msg) => assert!(msg.contains("robe cp")),
+            _ => panic!("Expected BadUsage"),
+        }
+    }
+
+    #[test]
+    fn test_cp() {
+        match parse_vec(&["cp", "--force", "tmux/work", "tmux-old/work"]).unwrap() {
+            Command::Cp(c) => {
+                assert_eq!(c.from_target, "tmux");
+                assert_eq!(c.from_profile, "work");
+                assert_eq!(c.target, "tmux-old");
+                assert_eq!(c.profile, "work");
+                assert!(c.force);
+            }
+            _ => panic!("Expected Cp"),
+        }
+        match parse_vec(&["cp"]).unwrap_err() {
+            RobeError::BadUsage(msg) => assert!(msg.contains("robe cp <target>/<profile>")),
+            _ => panic!("Expected BadUsage"),
+        }
+    }
+
+    // ---------- TARGET ----------
+
+    #[test]
+    fn test_target_rename() {
+        match parse_vec(&["target", "rename", "tmux", "tmux3"]).unwrap() {
+            Command::Target(t) => {
+                assert_eq!(t.target, "tmux");
+                assert_eq!(t.action, TargetAction::Rename("tmux3".to_string()));
+            }
+            _ => panic!("Expected Target"),
+        }
+    }
+
+    #[test]
+    fn test_target_set_path() {
+        match parse_vec(&["target", "set-path", "tmux", "/tmp/tmux.conf"]).unwrap() {
+            Command::Target(t) => {
+                assert_eq!(t.target, "tmux");
+                assert_eq!(
+                    t.action,
+                    TargetAction::SetPath(PathBuf::from("/tmp/tmux.conf"))
+                );
+            }
+            _ => panic!("Expected Target"),
+        }
+    }
+
+    #[test]
+    fn test_target_bad_usage() {
+        for args in [
+            vec!["target"],
+            vec!["target", "rename", "tmux"],
+            vec!["target", "move", "tmux", "x"],
+            vec!["target", "set-path", "tmux", ""],
+        ] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("robe target <rename")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    // ---------- OUTFIT ----------
+
+    fn parse_outfit(args: &[&str]) -> Result<OutfitAction, RobeError> {
+        match parse_vec(args)? {
+            Command::Outfit(o) => Ok(o.action),
+            _ => panic!("Expected Outfit"),
+        }
+    }
+
+    #[test]
+    fn test_outfit_save() {
+        assert_eq!(
+            parse_outfit(&["outfit", "save", "work", "tmux/work", "nvim/full", "-f"]).unwrap(),
+            OutfitAction::Save {
+                name: "work".to_string(),
+                profiles: vec![
+                    ("tmux".to_string(), "work".to_string()),
+                    ("nvim".to_string(), "full".to_string()),
+                ],
+                force: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_outfit_wear_list_rm() {
+        assert_eq!(
+            parse_outfit(&["outfit", "wear", "work", "-s"]).unwrap(),
+            OutfitAction::Wear {
+                name: "work".to_string(),
+                force: false,
+                save: true,
+            }
+        );
+        assert_eq!(
+            parse_outfit(&["outfit", "list"]).unwrap(),
+            OutfitAction::List
+        );
+        assert_eq!(
+            parse_outfit(&["outfit", "rm", "work"]).unwrap(),
+            OutfitAction::Rm("work".to_string())
+        );
+    }
+
+    #[test]
+    fn test_outfit_bad_usage() {
+        for args in [
+            vec!["outfit"],
+            vec!["outfit", "save", "work"],
+            vec!["outfit", "save", "work", "tmux"],
+            vec!["outfit", "save", "work", "tmux/work", "-s"],
+            vec!["outfit", "wear"],
+            vec!["outfit", "list", "-f"],
+            vec!["outfit", "rm", "a", "b"],
+        ] {
+            match parse_outfit(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("robe outfit <save")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    // ---------- STATUS ----------
+
+    #[test]
+    fn test_status() {
+        match parse_vec(&["status"]).unwrap() {
+            Command::Status(s) => {
+                assert!(s.target.is_none());
+            }
+            _ => panic!("Expected Status"),
+        }
+        match parse_vec(&["status", "target"]).unwrap() {
+            Command::Status(s) => {
+                assert_eq!(s.target.unwrap(), "target");
+            }
+            _ => panic!("Expected Status"),
+        }
+    }
+
+    #[test]
+    fn test_status_bad_usage() {
+        match parse_vec(&["status", "a", "b"]).unwrap_err() {
+            RobeError::BadUsage(msg) => {
+                assert!(msg.contains("robe status [<target>]"));
+            }
+            _ => panic!("Expected BadUsage"),
+        }
+    }
+
+    // ---------- UNDO ----------
+
+    #[test]
+    fn test_undo() {
+        match parse_vec(&["undo"]).unwrap() {
+            Command::Undo(u) => assert!(u.target.is_none()),
+            _ => panic!("Expected Undo"),
+        }
+        match parse_vec(&["undo", "tmux"]).unwrap() {
+            Command::Undo(u) => assert_eq!(u.target.unwrap(), "tmux"),
+            _ => panic!("Expected Undo"),
+        }
+        match parse_vec(&["undo", "a", "b"]).unwrap_err() {
+            RobeError::BadUsage(msg) => assert!(msg.contains("robe undo [<target>]")),
+            _ => panic!("Expected BadUsage"),
+        }
+    }
+
+    // ---------- HISTORY ----------
+
+    #[test]
+    fn test_history() {
+        match parse_vec(&["history", "tmux/work"]).unwrap() {
+            Command::History(h) => {
+                assert_eq!(h.target, "tmux");
+                assert_eq!(h.profile, "work");
+            }
+            _ => panic!("Expected History"),
+        }
+        for args in [
+            vec!["history"],
+            vec!["history", "tmux"],
+            vec!["history", "a/b", "c"],
+        ] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => {
+                    assert!(msg.contains("robe history <target>/<profile>"))
+                }
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore() {
+        match parse_vec(&["restore", "tmux/work@2"]).unwrap() {
+            Command::Restore(r) => {
+                assert_eq!(r.target, "tmux");
+                assert_eq!(r.profile, "work");
+                assert_eq!(r.version, 2);
+            }
+            _ => panic!("Expected Restore"),
+        }
+        match parse_vec(&["restore", "tmux/work@1792304569"]).unwrap() {
+            Command::Restore(r) => assert_eq!(r.version, 1792304569),
+            _ => panic!("Expected Restore"),
+        }
+        for args in [
+            vec!["restore"],
+            vec!["restore", "tmux/work"],
+            vec!["restore", "tmux/work@"],
+            vec!["restore", "tmux/work@0"],
+            vec!["restore", "tmux/work@last"],
+            vec!["restore", "tmux@1"],
+            vec!["restore", "tmux/work@1", "x"],
+        ] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => {
+                    assert!(msg.contains("robe restore <target>/<profile>@"))
+                }
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    // ---------- CONFIG ----------
+
+    #[test]
+    fn test_config_check() {
+        match parse_vec(&["config", "check"]).unwrap() {
+            Command::Config(c) => assert_eq!(c.action, ConfigAction::Check),
+            _ => panic!("Expected Config"),
+        }
+        for args in [
+            vec!["config"],
+            vec!["config", "fix"],
+            vec!["config", "check", "x"],
+        ] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("robe config <check")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_get_set_list() {
+        let action = |args: &[&str]| match parse_vec(args).unwrap() {
+            Command::Config(c) => c.action,
+            _ => panic!("Expected Config"),
+        };
+        assert_eq!(action(&["config", "list"]), ConfigAction::List);
+        assert_eq!(
+            action(&["config", "get", "backups"]),
+            ConfigAction::Get("backups".to_string())
+        );
+        assert_eq!(
+            action(&["config", "set", "backups", "3"]),
+            ConfigAction::Set("backups".to_string(), "3".to_string())
+        );
+        for args in [
+            vec!["config", "get"],
+            vec!["config", "set", "backups"],
+            vec!["config", "list", "backups"],
+        ] {
+            assert!(matches!(parse_vec(&args), Err(RobeError::BadUsage(_))));
+        }
+    }
+
+    // ---------- COMPLETIONS ----------
+
+    #[test]
+    fn test_completions() {
+        match parse_vec(&["completions", "zsh"]).unwrap() {
+            Command::Completions(c) => assert_eq!(c.shell, Shell::Zsh),
+            _ => panic!("Expected Completions"),
+        }
+        for args in [vec!["completions"], vec!["completions", "tcsh"]] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => assert!(msg.contains("<bash|zsh|fish>")),
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_complete_keeps_every_word() {
+        match parse_vec(&["__complete", "add", "-h", ""]).unwrap() {
+            Command::Complete(c) => assert_eq!(c.words, vec!["add", "-h", ""]),
+            _ => panic!("Expected Complete"),
+        }
+    }
+
+    #[test]
+    fn test_completed_commands_are_recognized() {
+        for (name, _) in COMMANDS {
+            if let Err(RobeError::BadUsage(msg)) = parse_internal(name, &[]) {
+                assert!(!msg.contains("not recognized"), "{}", name);
+            }
+        }
+    }
+
+    // ---------- DIFF ----------
+
+    fn side(target: &str, profile: Option<&str>) -> DiffSide {
+        DiffSide {
+            target: target.to_string(),
+            profile: profile.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_diff_two_profiles() {
+        match parse_vec(&["diff", "tmux/work", "tmux/minimal"]).unwrap() {
+            Command::Diff(d) => {
+                assert_eq!(d.from, side("tmux", Some("work")));
+                assert_eq!(d.to, side("tmux", Some("minimal")));
+                assert!(!d.stat);
+            }
+            _ => panic!("Expected Diff"),
+        }
+    }
+
+    #[test]
+    fn test_diff_against_live() {
+        match parse_vec(&["diff", "tmux/work", "--stat"]).unwrap() {
+            Command::Diff(d) => {
+                assert_eq!(d.from, side("tmux", Some("work")));
+                assert_eq!(d.to, side("tmux", None));
+                assert!(d.stat);
+            }
+            _ => panic!("Expected Diff"),
+        }
+        match parse_vec(&["diff", "tmux", "tmux/work"]).unwrap() {
+            Command::Diff(d) => {
+                assert_eq!(d.from, side("tmux", None));
+                assert_eq!(d.to, side("tmux", Some("work")));
+            }
+            _ => panic!("Expected Diff"),
+        }
+    }
+
+    #[test]
+    fn test_diff_bad_usage() {
+        for args in [
+            vec!["diff"],
+            vec!["diff", "tmux"],
+            vec!["diff", "--stat"],
+            vec!["diff", "a/b", "c/d", "e/f"],
+        ] {
+            match parse_vec(&args).unwrap_err() {
+                RobeError::BadUsage(msg) => {
+                    assert!(msg.contains("robe diff <target>/<profile>"));
+                }
+                _ => panic!("Expected BadUsage"),
+            }
+        }
+    }
+
     // ---------- HELP / VERSION ----------
 
     #[test]
     fn test_help() {
-        match parse_vec(&["add", "-h"]).unwrap() {
-            Command::Help(t) if t == "add -h" => (),
-            _ => panic!("Expected Help"),
-        }
-        match parse_vec(&["add", "--help"]).unwrap() {
-            Command::Help(t) if t == "add --help" => (),
+        let topic = |args: &[&str]| match parse_vec(args).unwrap() {
+            Command::Help(topic) => topic,
             _ => panic!("Expected Help"),
+        };
+        assert_eq!(topic(&["add", "-h"]), Some("add".to_string()));
+        assert_eq!(
+            topic(&["use", "tmux/work", "--help"]),
+            Some("use".to_string())
+        );
+        assert_eq!(topic(&["ls", "-h"]), Some("list".to_string()));
+        assert_eq!(topic(&["help", "diff"]), Some("diff".to_string()));
+        assert_eq!(topic(&["help"]), None);
+        assert_eq!(topic(&["-h"]), None);
+        assert_eq!(topic(&["unknown", "-h"]), None);
+
+        match parse_vec(&["help", "stauts"]).unwrap_err() {
+            RobeError::BadUsage(msg) => assert!(msg.contains("Did you mean `status`?")),
+            _ => panic!("Expected BadUsage"),
         }
     }
 
+    #[test]
+    fn test_closest_command() {
+        assert_eq!(closest_command("stauts"), Some("status"));
+        assert_eq!(closest_command("lst"), Some("list"));
+        assert_eq!(closest_command("confg"), Some("config"));
+        assert_eq!(closest_command("us"), Some("use"));
+        assert_eq!(closest_command("wardrobe"), None);
+        assert_eq!(closest_command("x"), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "use"), 3);
+        assert_eq!(edit_distance("use", "use"), 0);
+        assert_eq!(edit_distance("sue", "use"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
     #[test]
     fn test_version() {
         match parse_vec(&["-v"]).unwrap() {
@@ -674,6 +1875,7 @@ mod tests {
         match parse_vec(&["unrecognized"]).unwrap_err() {
             RobeError::BadUsage(msg) => {
                 assert!(msg.contains("Command not recognized"));
+                assert!(!msg.contains("Did you mean"));
             }
             _ => panic!("Expected BadUsage"),
         }
@@ -1,27 +1,191 @@
+use std::path::{Path, PathBuf};
+
 #[derive(Debug)]
 pub enum RobeError {
     Internal(String),
     BadUsage(String),
+    /// A target, profile or backup that does not exist.
+    NotFound(String),
+    /// A target or profile that exists already.
+    AlreadyExists(String),
+    /// The files on disk do not allow the operation, e.g. a profile that is still linked.
+    Conflict(String),
+    /// The live config changed since its profile was activated.
+    Drift(String),
+    /// A `pre-` hook that failed, aborting the command.
+    Hook(String),
+    /// A config the `validate` command of its target rejected, with the output of the command.
+    Invalid(String),
+    /// A file operation that failed on `path`.
+    Io {
+        operation: String,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A config file that cannot be used, with the line and column of the problem if known.
+    Config {
+        file: String,
+        location: Option<(usize, usize)>,
+        message: String,
+    },
 }
 
 impl RobeError {
     pub fn message(msg: String) -> Self {
         Self::Internal(msg)
     }
+
+    /// Wraps an IO error with the `operation` that failed on `path`,
+    /// e.g. `fs::read(p).map_err(RobeError::io("read", p))`.
+    pub fn io(operation: &str, path: &Path) -> impl FnOnce(std::io::Error) -> Self {
+        let operation = operation.to_string();
+        let path = path.to_path_buf();
+        move |source| Self::Io {
+            operation,
+            path,
+            source,
+        }
+    }
+
+    /// Stable name of the kind of error, as reported by `--json`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Internal(_) => "internal",
+            Self::BadUsage(_) => "bad_usage",
+            Self::NotFound(_) => "not_found",
+            Self::AlreadyExists(_) => "already_exists",
+            Self::Conflict(_) => "conflict",
+            Self::Drift(_) => "drift",
+            Self::Hook(_) => "hook",
+            Self::Invalid(_) => "invalid",
+            Self::Config { .. } => "config",
+            Self::Io { .. } => "io",
+        }
+    }
+
+    /// The error as a JSON document, with the path and location of IO and config errors.
+    pub fn to_json(&self) -> String {
+        let message = match self {
+            Self::BadUsage(msg) => msg.clone(),
+            Self::Config { message, .. } => message.clone(),
+            other => other.to_string().trim_start_matches("robe: ").to_string(),
+        };
+        let mut error = serde_json::json!({
+            "kind": self.kind(),
+            "code": self.exit_code(),
+            "message": message,
+        });
+        match self {
+            Self::Io {
+                operation, path, ..
+            } => {
+                error["operation"] = operation.as_str().into();
+                error["path"] = path.to_string_lossy().into();
+            }
+            Self::Config { file, location, .. } => {
+                error["file"] = file.as_str().into();
+                if let Some((line, column)) = location {
+                    error["line"] = (*line).into();
+                    error["column"] = (*column).into();
+                }
+            }
+            _ => {}
+        }
+        serde_json::json!({ "error": error }).to_string()
+    }
+
+    /// Exit status of robe when failing with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Internal(_) => 1,
+            Self::BadUsage(_) => 2,
+            Self::NotFound(_) => 3,
+            Self::AlreadyExists(_) => 4,
+            Self::Conflict(_) => 5,
+            Self::Drift(_) => 6,
+            Self::Config { .. } => 7,
+            Self::Io { .. } => 8,
+            Self::Hook(_) => 9,
+            Self::Invalid(_) => 10,
+        }
+    }
+
+    /// A TOML error in the config `file`, located in its `contents` by the byte `span`.
+    pub fn config(
+        file: &str,
+        contents: &str,
+        span: Option<std::ops::Range<usize>>,
+        message: &str,
+    ) -> Self {
+        Self::Config {
+            file: file.to_string(),
+            location: span.map(|span| line_and_column(contents, span.start)),
+            message: message.trim_end().to_string(),
+        }
+    }
+}
+
+/// 1-based line and column of the byte `offset` in `s`.
+fn line_and_column(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line, before[line_start..].chars().count() + 1)
 }
 
 impl std::fmt::Display for RobeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Internal(err) => f.write_fmt(format_args!("robe: {}", err)),
+            Self::Internal(err)
+            | Self::NotFound(err)
+            | Self::AlreadyExists(err)
+            | Self::Conflict(err)
+            | Self::Drift(err)
+            | Self::Hook(err)
+            | Self::Invalid(err) => f.write_fmt(format_args!("robe: {}", err)),
             Self::BadUsage(err) => f.write_fmt(format_args!(
                 "robe: Wrong usage. {}\nUse `robe -h` for help.",
                 err
             )),
+            Self::Config {
+                file,
+                location: Some((line, column)),
+                message,
+            } => f.write_fmt(format_args!(
+                "robe: Invalid config {}:{}:{}: {}",
+                file, line, column, message
+            )),
+            Self::Config {
+                file,
+                location: None,
+                message,
+            } => f.write_fmt(format_args!("robe: Invalid config {}: {}", file, message)),
+            Self::Io {
+                operation,
+                path,
+                source,
+            } => f.write_fmt(format_args!(
+                "robe: Cannot {} {}: {}",
+                operation,
+                path.display(),
+                source
+            )),
         }
     }
 }
 
+impl std::error::Error for RobeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Lets tests use `?` on file operations; robe itself reports every IO error with
+/// its path and operation, see `RobeError::io`.
+#[cfg(test)]
 impl From<std::io::Error> for RobeError {
     fn from(value: std::io::Error) -> Self {
         Self::Internal(format!("IO error: {}", value))
@@ -34,6 +198,12 @@ impl From<toml::de::Error> for RobeError {
     }
 }
 
+impl From<serde_json::Error> for RobeError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Internal(format!("Serialization error: {}", value))
+    }
+}
+
 impl From<toml::ser::Error> for RobeError {
     fn from(value: toml::ser::Error) -> Self {
         Self::Internal(format!("Serialization error: {}", value))
@@ -41,6 +211,7 @@ impl From<toml::ser::Error> for RobeError {
 }
 
 #[cfg(test)]
+#[allow(clippy::io_other_error)]
 mod tests {
     use super::*;
 
@@ -83,6 +254,94 @@ mod tests {
         assert!(msg.contains("disk exploded"));
     }
 
+    #[test]
+    fn test_display_io() {
+        let err = RobeError::io("read", Path::new("/tmp/tmux.conf"))(std::io::Error::from(
+            std::io::ErrorKind::PermissionDenied,
+        ));
+
+        assert_eq!(
+            format!("{}", err),
+            "robe: Cannot read /tmp/tmux.conf: permission denied"
+        );
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            RobeError::Internal("".to_string()),
+            RobeError::BadUsage("".to_string()),
+            RobeError::NotFound("".to_string()),
+            RobeError::AlreadyExists("".to_string()),
+            RobeError::Conflict("".to_string()),
+            RobeError::Drift("".to_string()),
+            RobeError::Config {
+                file: "".to_string(),
+                location: None,
+                message: "".to_string(),
+            },
+            RobeError::io("read", Path::new(""))(std::io::Error::other("")),
+            RobeError::Hook("".to_string()),
+            RobeError::Invalid("".to_string()),
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(RobeError::exit_code).collect();
+        codes.dedup();
+
+        assert_eq!(codes, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let err = RobeError::NotFound("Target tmux not found.".to_string());
+        assert_eq!(
+            err.to_json(),
+            r#"{"error":{"code":3,"kind":"not_found","message":"Target tmux not found."}}"#
+        );
+
+        let err = RobeError::io("read", Path::new("/tmp/tmux.conf"))(std::io::Error::from(
+            std::io::ErrorKind::PermissionDenied,
+        ));
+        let json: serde_json::Value = serde_json::from_str(&err.to_json()).unwrap();
+        assert_eq!(json["error"]["kind"], "io");
+        assert_eq!(json["error"]["operation"], "read");
+        assert_eq!(json["error"]["path"], "/tmp/tmux.conf");
+        assert_eq!(
+            json["error"]["message"],
+            "Cannot read /tmp/tmux.conf: permission denied"
+        );
+    }
+
+    #[test]
+    fn test_config_error_location() {
+        let contents = "backups = 2\nwardrobe = /tmp\n";
+        let err = toml::from_str::<toml::Table>(contents).unwrap_err();
+
+        let robe_err = RobeError::config("config.toml", contents, err.span(), err.message());
+        let msg = format!("{}", robe_err);
+
+        assert!(msg.starts_with("robe: Invalid config config.toml:2:12: "));
+    }
+
+    #[test]
+    fn test_display_config_without_location() {
+        let err = RobeError::Config {
+            file: "config.toml".to_string(),
+            location: None,
+            message: "not found".to_string(),
+        };
+
+        assert_eq!(
+            format!("{}", err),
+            "robe: Invalid config config.toml: not found"
+        );
+    }
+
+    #[test]
+    fn test_line_and_column() {
+        assert_eq!(line_and_column("abc", 0), (1, 1));
+        assert_eq!(line_and_column("a\nbé c", 6), (2, 4));
+    }
+
     #[test]
     fn test_from_toml_deser_error() {
         let result: Result<u32, _> = toml::from_str("not_a_number");
@@ -1,21 +1,44 @@
+use crate::settings::Source;
+
 pub const HELP: &str = r#"robe — switch between named dotfile configurations
 
 Usage:
-  robe add <target>/<profile> [-r <path>] [-f]
-  robe use <target>/<profile>
+  robe [--config <file>] [--json | --format <json|plain>] <command> [args]
+  robe add <target>/<profile> [-r <path>] [-m <copy|symlink>] [-f] [-L]
+  robe use <target>/<profile> [-f] [-s] [-a <replace|overlay>]
   robe view <target>[/profile]
   robe edit <target>[/profile]
   robe list [target]
   robe ls [target]
   robe rm <target>/<profile>
   robe rm <target>
+  robe mv <target>/<profile> <target>/<new-profile> [-f]
+  robe cp <target>/<profile> <target>/<profile> [-f]
+  robe target <rename <target> <new-name> | set-path <target> <path>>
+  robe outfit <save <name> <target>/<profile>... [-f] | wear <name> [-f] [-s] | list | rm <name>>
+  robe status [target]
+  robe diff <target>/<profile> [<target>[/profile]] [--stat]
+  robe undo [target]
+  robe history <target>/<profile>
+  robe restore <target>/<profile>@<n|timestamp>
+  robe config <check | list | get <key> | set <key> <value>>
+  robe completions <bash|zsh|fish>
+  robe help [command]
 
 Commands:
   add       save current config as a profile
             -r, --register <path>  register file or directory to manage
+            -m, --mode <mode>      with -r: `copy` (default) copies profiles into place,
+                                   `symlink` links the path to the active profile
             -f, --force            overwrite existing profile or registration
+            -L, --dereference      copy what symlinks inside a directory point to
+                                   instead of the links themselves
 
   use       activate a profile
+            -f, --force            discard live changes made since the last switch
+            -s, --save             save live changes into the active profile first
+            -a, --apply <strategy> for a directory: `replace` (default) makes it match
+                                   the profile, `overlay` only writes the profile's files
 
   view      print config contents
             file → prints contents
@@ -28,15 +51,535 @@ Commands:
 
   rm        remove a stored profile or all profiles of a target
 
+  mv        rename a profile, leaving the live config as it is
+            -f, --force            overwrite an existing profile
+
+  cp        copy a profile, to the same target or another one
+            holding the same kind of config (file or directory)
+            -f, --force            overwrite an existing profile
+
+  target    change a registered target
+            rename <target> <new-name>
+                                   rename the target and its wardrobe directory
+            set-path <target> <path>
+                                   manage the config at <path> from now on
+
+  outfit    switch several targets at once
+            save <name> <target>/<profile>...
+                                   record the profiles as an outfit, -f to overwrite
+            wear <name>            activate every profile of the outfit, or none
+                                   -f and -s as for `use`
+            list                   show the outfits and their profiles
+            rm <name>              delete an outfit
+
+  status    show the active profile of each target
+            and whether the live config changed since activation
+            or which profile it links to
+
+  diff      compare two profiles, or a profile against the live config
+            file → unified diff
+            dir  → added/removed/changed files and their diffs
+            --stat                 summarize insertions and deletions
+
+  undo      restore the live config saved before the last `use`
+            of a target, or of the most recently switched target
+
+  history   list the earlier versions of a profile, kept when
+            `add -f` or `use -s` overwrote it
+
+  restore   bring back an earlier version of a profile,
+            by its number in `history` or its timestamp
+
+  config    manage the settings of the config file
+            check                  validate the config file and the wardrobe
+            list                   show every setting and where it comes from
+            get <key>              show one setting and where it comes from
+            set <key> <value>      write a setting, creating the config file if needed
+
+  completions
+            print a completion script for bash, zsh or fish, e.g.
+            `source <(robe completions bash)`; targets and profiles
+            are completed from the current wardrobe
+
+  help      show help on robe, or the flags, examples and exit
+            status of one command, same as `robe <command> -h`
+
 Options:
-  -h, --help       show help
+  -h, --help       show help, or help on the command it follows
   -v, --version    show version
+  --config <file>  read config from <file>, before the command
+  --json           print list, view, status, history, outfit list and errors
+                   as JSON, before the command
+  --format <fmt>   `json` or `plain` (default), before the command
+
+Environment:
+  ROBE_CONFIG      config file to read, unless --config is given
+  ROBE_WARDROBE    wardrobe to use, over the one in the config file
+
+Exit status:
+  0  success
+  1  internal error
+  2  wrong usage
+  3  target, profile, backup or version not found
+  4  target or profile already exists
+  5  conflicts with the files on disk, e.g. removing a linked profile
+  6  live config changed since activation, see `use -s` and `use -f`
+  7  invalid config file or meta.toml
+  8  file operation failed; the message names the path
+  9  a pre-use or pre-add hook failed
+  10 the validate command of the target rejected the config
 "#;
 
-pub fn help_with_storage_and_config(storage_file: &str, config_file: &str) -> String {
+/// Detailed help of each command, shown by `robe help <command>` and `robe <command> -h`.
+const COMMAND_HELP: &[(&str, &str)] = &[
+    (
+        "add",
+        r#"Usage: robe add <target>/<profile> [-r <path>] [-m <copy|symlink>] [-f] [-L]
+
+Save the live config of <target> as <profile>. With -r, register the file or
+directory at <path> as a new target first. The saved profile becomes the
+active profile. The pre-add and post-add hooks of meta.toml run around it,
+and its validate command must accept the live config. Entries of a directory
+matched by the ignore patterns of meta.toml or .robeignore are left out.
+A profile overwritten with -f is kept first, see `robe history`.
+
+Flags:
+  -r, --register <path>  register the file or directory to manage
+  -m, --mode <mode>      with -r: `copy` (default) copies profiles into place,
+                         `symlink` links the path to the active profile
+  -f, --force            overwrite an existing profile or registration
+  -L, --dereference      copy what symlinks inside a directory point to
+                         instead of the links themselves
+
+Examples:
+  robe add tmux/work -r ~/.tmux.conf
+  robe add nvim/minimal -r ~/.config/nvim -m symlink
+  robe add tmux/work -f
+
+Exit status:
+  0  success
+  2  wrong usage, e.g. an unknown mode
+  3  target not found
+  4  profile or registration exists already, see -f
+  8  file operation failed
+  9  a pre-add hook failed
+  10 the live config failed validation, nothing was saved
+"#,
+    ),
+    (
+        "use",
+        r#"Usage: robe use <target>/<profile> [-f] [-s] [-a <replace|overlay>]
+
+Activate <profile> of <target>. The live config is backed up first, see
+`robe undo`. Fails if the live config changed since the last activation.
+The pre-use and post-use hooks of meta.toml run around the switch. With a
+validate command in meta.toml, a profile it rejects is not switched to.
+Ignored entries of a live directory are left as they are.
+
+A directory is replaced by the profile, removing the files it lacks. With
+the overlay strategy, set as `apply = "overlay"` in meta.toml or given with
+-a, only the files of the profile are written and the others are left
+alone, unmanaged: they are neither saved by -s nor count as live changes.
+
+Flags:
+  -f, --force            discard live changes made since the last switch
+  -s, --save             save live changes into the last activated profile first,
+                         keeping its earlier version, see `robe history`
+  -a, --apply <strategy> `replace` or `overlay`, instead of the target's strategy
+
+Examples:
+  robe use tmux/home
+  robe use tmux/work -s
+  robe use nvim/work -a overlay
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  6  live config changed since activation, see -s and -f
+  8  file operation failed
+  9  a pre-use hook failed
+  10 the profile failed validation, the live config is untouched
+"#,
+    ),
+    (
+        "view",
+        r#"Usage: robe view <target>[/<profile>] [--raw]
+
+Print a profile, or the live config when no profile is given. A file is
+printed as is, a directory as the list of its entries, but for the ignored
+ones.
+
+Flags:
+  --raw                  skip headers and formatting, for piping
+
+Examples:
+  robe view tmux/work
+  robe view nvim --raw
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "edit",
+        r#"Usage: robe edit <target>[/<profile>]
+
+Open a profile, or the live config when no profile is given, in $EDITOR
+(defaults to vi). A live config linking into the wardrobe opens the profile
+it links to. The edited config is then checked with the validate command of
+the target, if it has one.
+
+Examples:
+  robe edit tmux/work
+  EDITOR=nano robe edit tmux
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  8  the editor could not be run
+  10 the edited config failed validation; the edits are kept
+"#,
+    ),
+    (
+        "list",
+        r#"Usage: robe list [target]
+       robe ls [target]
+
+List the targets of the wardrobe, or the profiles of <target>.
+
+Examples:
+  robe list
+  robe --json ls tmux
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target not found
+"#,
+    ),
+    (
+        "rm",
+        r#"Usage: robe rm <target>[/<profile>]
+
+Delete a stored profile, or every profile of <target> along with its
+registration. The live config is left in place.
+
+Examples:
+  robe rm tmux/home
+  robe rm tmux
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  5  the live config links to the profile
+  8  file operation failed
+"#,
+    ),
+    (
+        "mv",
+        r#"Usage: robe mv <target>/<profile> <target>/<new-profile> [-f]
+
+Rename a profile inside the wardrobe. The live config is left as it is; if it
+links to the profile, it is re-linked to the new name. The last activated
+profile is remembered under its new name.
+
+Flags:
+  -f, --force            overwrite an existing profile
+
+Examples:
+  robe mv tmux/work tmux/office
+  robe mv tmux/new tmux/work -f
+
+Exit status:
+  0  success
+  2  wrong usage, e.g. another target or an invalid profile name
+  3  target or profile not found
+  4  new profile exists already, see -f
+  5  the live config links to the profile being overwritten
+  8  file operation failed
+"#,
+    ),
+    (
+        "cp",
+        r#"Usage: robe cp <target>/<profile> <target>/<profile> [-f]
+
+Copy a profile inside the wardrobe, to the same target or to another target
+holding the same kind of config, a file or a directory. Live configs and
+active profiles are left as they are.
+
+Flags:
+  -f, --force            overwrite an existing profile
+
+Examples:
+  robe cp tmux/work tmux/work-backup
+  robe cp nvim/minimal nvim-nightly/minimal
+
+Exit status:
+  0  success
+  2  wrong usage, e.g. copying a profile onto itself
+  3  target or profile not found
+  4  profile exists already, see -f
+  5  targets hold different kinds of config, or the live config links
+     to the profile being overwritten
+  8  file operation failed
+"#,
+    ),
+    (
+        "target",
+        r#"Usage: robe target <rename <target> <new-name> | set-path <target> <path>>
+
+Change a registered target without touching its live config.
+
+Actions:
+  rename <target> <new-name>
+                         rename the target; a live config linking into the
+                         wardrobe is re-linked
+  set-path <target> <path>
+                         manage the config at <path> instead, e.g. after moving
+                         it; <path> must be of the same kind as the profiles,
+                         a file or a directory, if it exists. File profiles are
+                         renamed when the extension changes
+
+Examples:
+  robe target rename tmux tmux3
+  mv ~/.tmux.conf ~/.config/tmux/tmux.conf
+  robe target set-path tmux ~/.config/tmux/tmux.conf
+
+Exit status:
+  0  success
+  2  wrong usage, e.g. an invalid name or a path inside the wardrobe
+  3  target not found
+  4  a target with the new name exists already
+  5  <path> is of another kind than the profiles, or managed by another target
+  8  file operation failed
+"#,
+    ),
+    (
+        "outfit",
+        r#"Usage: robe outfit save <name> <target>/<profile>... [-f]
+       robe outfit wear <name> [-f] [-s]
+       robe outfit list
+       robe outfit rm <name>
+
+Outfits are profiles of several targets worn together, stored in the
+wardrobe as `.outfits/<name>.toml`.
+
+Actions:
+  save <name> <target>/<profile>...
+                         record one profile per target as the outfit <name>
+  wear <name>            activate every profile of the outfit like `robe use`.
+                         Nothing is switched if a profile is missing or a live
+                         config changed; if switching one target fails, the
+                         targets switched already are put back
+  list                   show the outfits and their profiles
+  rm <name>              delete an outfit; its profiles are kept
+
+Flags:
+  -f, --force            save: overwrite an existing outfit
+                         wear: discard live changes made since the last switch
+  -s, --save             wear: save live changes into the active profiles first
+
+Examples:
+  robe outfit save work tmux/work nvim/full git/work
+  robe outfit wear work
+  robe --json outfit list
+
+Exit status:
+  0  success
+  2  wrong usage, e.g. a target listed twice
+  3  outfit, target or profile not found
+  4  outfit exists already, see -f
+  6  a live config changed since activation, see -s and -f
+  7  invalid outfit file
+  8  file operation failed
+  9  a pre-use hook failed
+  10 a profile failed validation
+"#,
+    ),
+    (
+        "status",
+        r#"Usage: robe status [target]
+
+Show the active profile of each target, or of <target>, and whether the live
+config changed since activation. In symlink mode, show the profile the live
+config links to. After an overlay switch, only the files of the profile are
+compared and the number of unmanaged files is shown; --json lists them.
+
+Examples:
+  robe status
+  robe --json status tmux
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "diff",
+        r#"Usage: robe diff <target>/<profile> [<target>[/<profile>]] [--stat]
+
+Compare two profiles. A side without a profile, or a missing second side, is
+the live config. Files are shown as a unified diff, directories as the added,
+removed and changed files followed by their diffs. Ignored entries are left
+out. Files only in a live directory overlaid with a profile that lacks them
+are listed as unmanaged.
+
+Flags:
+  --stat                 summarize insertions and deletions per file
+
+Examples:
+  robe diff tmux/work tmux/home
+  robe diff tmux/work --stat
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "undo",
+        r#"Usage: robe undo [target]
+
+Restore the live config saved before the last `robe use` of <target>, or of
+the most recently switched target. The backup is removed once restored.
+
+Examples:
+  robe undo
+  robe undo tmux
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or backup not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "history",
+        r#"Usage: robe history <target>/<profile>
+
+List the earlier versions of <profile>, the most recent first, with when
+each was taken, its size and its hash. A version is kept whenever
+`robe add -f` or `robe use -s` overwrites the profile, and only the most
+recent ones are, as many as the `history` setting says (10 by default).
+
+Examples:
+  robe history tmux/work
+  robe --json history nvim/work
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target or profile not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "restore",
+        r#"Usage: robe restore <target>/<profile>@<n|timestamp>
+
+Replace <profile> with one of its earlier versions: the <n>-th most recent,
+as numbered by `robe history`, or the one taken at <timestamp>, in seconds
+since the unix epoch. The version replaced is kept in the history in turn.
+The live config is left as it is; `robe use` it to apply the profile.
+
+Examples:
+  robe restore tmux/work@1
+  robe restore tmux/work@1792304570
+
+Exit status:
+  0  success
+  2  wrong usage
+  3  target, profile or version not found
+  8  file operation failed
+"#,
+    ),
+    (
+        "config",
+        r#"Usage: robe config <check | list | get <key> | set <key> <value>>
+
+Manage the settings of the config file. Keys are `wardrobe`, `backups` and
+`history`.
+
+Actions:
+  check                  validate the config file and the wardrobe
+  list                   show every setting and where it comes from
+  get <key>              show one setting and where it comes from
+  set <key> <value>      write a setting, creating the config file if needed
+
+Examples:
+  robe config list
+  robe config set backups 10
+  robe --config ./robe.toml config check
+
+Exit status:
+  0  success
+  2  wrong usage, an unknown key or an invalid value
+  7  invalid config file
+  8  file operation failed
+"#,
+    ),
+    (
+        "completions",
+        r#"Usage: robe completions <bash|zsh|fish>
+
+Print a completion script. Targets and profiles are completed from the
+current wardrobe.
+
+Examples:
+  source <(robe completions bash)
+  robe completions zsh > "${fpath[1]}/_robe"
+  robe completions fish > ~/.config/fish/completions/robe.fish
+
+Exit status:
+  0  success
+  2  wrong usage
+"#,
+    ),
+    (
+        "help",
+        r#"Usage: robe help [command]
+
+Show help on robe, or on <command>. `robe <command> -h` shows the same.
+
+Examples:
+  robe help
+  robe help use
+
+Exit status:
+  0  success
+  2  unknown command
+"#,
+    ),
+];
+
+/// Detailed help of `command`, if it is one.
+pub fn command_help(command: &str) -> Option<&'static str> {
+    COMMAND_HELP
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, help)| *help)
+}
+
+pub fn help_with_storage_and_config(
+    storage_file: &str,
+    storage_source: Source,
+    config_file: &str,
+    config_source: Source,
+) -> String {
     format!(
-        "{}\nStorage:\n{}\n\nConfig:\n{}",
-        HELP, storage_file, config_file
+        "{}\nStorage:\n{} ({})\n\nConfig:\n{} ({})",
+        HELP, storage_file, storage_source, config_file, config_source
     )
 }
 
@@ -53,11 +596,40 @@ mod tests {
         let s_file = "storage/file/path";
         let c_file = "config/file/path";
 
-        let result = help_with_storage_and_config(s_file, c_file);
+        let result = help_with_storage_and_config(
+            s_file,
+            Source::Env("ROBE_WARDROBE"),
+            c_file,
+            Source::Default,
+        );
 
         assert!(result.contains(HELP));
-        assert!(result.contains(c_file));
-        assert!(result.contains(s_file));
+        assert!(result.contains(&format!("{} (default)", c_file)));
+        assert!(result.contains(&format!("{} (from $ROBE_WARDROBE)", s_file)));
+    }
+
+    #[test]
+    fn test_every_command_has_help() {
+        for (name, _) in crate::domain::COMMANDS {
+            if *name == "ls" {
+                continue;
+            }
+            let help = command_help(name).unwrap_or_else(|| panic!("no help for {}", name));
+            assert!(help.starts_with(&format!("Usage: robe {}", name)));
+            assert!(help.contains("Exit status:"));
+        }
+        assert!(command_help("ls").is_none());
+    }
+
+    #[test]
+    fn test_command_help_lists_flags() {
+        for (name, flags) in crate::domain::COMMANDS {
+            if let Some(help) = command_help(name) {
+                for flag in *flags {
+                    assert!(help.contains(flag), "{} help misses {}", name, flag);
+                }
+            }
+        }
     }
 
     #[test]
@@ -0,0 +1,288 @@
+use crate::errors::RobeError;
+use crate::registry::{Apply, Mode, Registry, TargetRegistry, TargetState};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File in a live directory listing more patterns, one per line.
+pub const IGNORE_FILE: &str = ".robeignore";
+
+/// Gitignore-style patterns selecting the entries of a directory target robe leaves alone:
+/// they are not captured into profiles, not replaced or removed by a switch, not part of the
+/// fingerprint and not shown by `view` or `diff`.
+///
+/// Supported: `#` comments, `!` to include again, a trailing `/` for directories only,
+/// a `/` at the start or in the middle to match from the root instead of at any depth,
+/// `*`, `?`, `[a-z]`, `[!a]` and `**`. The last pattern matching an entry wins, and
+/// nothing inside an ignored directory can be included again.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+    /// with the overlay strategy, the profile whose entries are the only ones robe manages
+    within: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Ignore {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            rules: patterns.into_iter().filter_map(Rule::parse).collect(),
+            within: None,
+        }
+    }
+
+    /// With the overlay strategy, also leave alone every entry the profile at `profile`
+    /// does not have, as switching to it that way does.
+    pub fn applied(self, apply: Apply, profile: &Path) -> Self {
+        match apply {
+            Apply::Overlay => Self {
+                within: Some(profile.to_path_buf()),
+                ..self
+            },
+            Apply::Replace => self,
+        }
+    }
+
+    /// What the fingerprint recorded in `state` covers.
+    pub fn for_state(
+        registry: &Registry,
+        target_registry: &TargetRegistry,
+        state: &TargetState,
+    ) -> Result<Self, RobeError> {
+        let profile = registry.profile_path(target_registry, &state.profile);
+        Ok(Self::for_target(target_registry)?.applied(state.apply, &profile))
+    }
+
+    /// The `ignore` patterns of `meta.toml`, then those of `.robeignore` in the live directory,
+    /// which itself stays there rather than going into profiles.
+    /// A target in symlink mode ignores nothing, as its live config is the profile itself.
+    pub fn for_target(target_registry: &TargetRegistry) -> Result<Self, RobeError> {
+        if target_registry.mode == Mode::Symlink {
+            return Ok(Self::default());
+        }
+        let own = format!("/{}", IGNORE_FILE);
+        let patterns = target_registry.ignore.iter().map(String::as_str);
+        let mut ignore = Self::new(std::iter::once(own.as_str()).chain(patterns));
+        if target_registry.real_path.is_dir() {
+            let file = target_registry.real_path.join(IGNORE_FILE);
+            match fs::read_to_string(&file) {
+                Ok(text) => ignore.rules.extend(text.lines().filter_map(Rule::parse)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(RobeError::io("read", &file)(e)),
+            }
+        }
+        Ok(ignore)
+    }
+
+    /// Whether the entry at `rel`, relative to the root of the target, is left alone.
+    pub fn is_ignored(&self, rel: &Path, is_dir: bool) -> bool {
+        if let Some(profile) = &self.within
+            && profile.join(rel).symlink_metadata().is_err()
+        {
+            return true;
+        }
+        if self.rules.is_empty() {
+            return false;
+        }
+        let names: Vec<String> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        // an ignored directory hides everything below it, whatever later patterns say
+        (1..=names.len()).any(|i| {
+            let path: Vec<char> = names[..i].join("/").chars().collect();
+            let name: Vec<char> = names[i - 1].chars().collect();
+            self.ignores(&path, &name, i < names.len() || is_dir)
+        })
+    }
+
+    fn ignores(&self, path: &[char], name: &[char], is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let subject = if rule.anchored { path } else { name };
+            if glob(&rule.pattern, subject) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+}
+
+/// Whether `s` matches the glob `p`, where only `**` matches across `/`.
+fn glob(p: &[char], s: &[char]) -> bool {
+    match p {
+        [] => s.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // any number of whole directories, none included
+            glob(rest, s) || (0..s.len()).any(|i| s[i] == '/' && glob(rest, &s[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=s.len()).any(|i| glob(rest, &s[i..])),
+        ['*', rest @ ..] => {
+            let end = s.iter().position(|c| *c == '/').unwrap_or(s.len());
+            (0..=end).any(|i| glob(rest, &s[i..]))
+        }
+        ['?', rest @ ..] => matches!(s, [c, ..] if *c != '/') && glob(rest, &s[1..]),
+        ['[', ..] => match (s, class(p)) {
+            ([c, tail @ ..], Some((len, set))) => *c != '/' && set(*c) && glob(&p[len..], tail),
+            (['[', tail @ ..], None) => glob(&p[1..], tail),
+            _ => false,
+        },
+        ['\\', c, rest @ ..] => s.first() == Some(c) && glob(rest, &s[1..]),
+        [c, rest @ ..] => s.first() == Some(c) && glob(rest, &s[1..]),
+    }
+}
+
+/// The character class `[...]` opening `p`: its length and which characters it matches.
+/// `None` if it is not closed, making the `[` a plain character.
+fn class(p: &[char]) -> Option<(usize, impl Fn(char) -> bool + '_)> {
+    let negated = matches!(p.get(1), Some('!' | '^'));
+    let start = if negated { 2 } else { 1 };
+    // a `]` right after the opening is part of the class
+    let end = start + 1 + p.get(start + 1..)?.iter().position(|c| *c == ']')?;
+    let items = &p[start..end];
+    let set = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < items.len() {
+            if items.get(i + 1) == Some(&'-') && i + 2 < items.len() {
+                found |= (items[i]..=items[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= items[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((end + 1, set))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(patterns: &[&str], rel: &str, is_dir: bool) -> bool {
+        Ignore::new(patterns.iter().copied()).is_ignored(Path::new(rel), is_dir)
+    }
+
+    #[test]
+    fn test_patterns_without_slash_match_at_any_depth() {
+        assert!(ignored(&["*.swp"], ".init.lua.swp", false));
+        assert!(ignored(&["*.swp"], "lua/.plugins.lua.swp", false));
+        assert!(ignored(&["lazy-lock.json"], "lazy-lock.json", false));
+        assert!(!ignored(&["lazy-lock.json"], "lazy-lock.json.bak", false));
+        assert!(ignored(&["cache"], "a/cache/b", false));
+    }
+
+    #[test]
+    fn test_anchored_patterns_match_from_the_root() {
+        assert!(ignored(&["/init.lua"], "init.lua", false));
+        assert!(!ignored(&["/init.lua"], "lua/init.lua", false));
+        assert!(ignored(&["lua/*.lua"], "lua/init.lua", false));
+        assert!(!ignored(&["lua/*.lua"], "lua/plugins/init.lua", false));
+        assert!(ignored(&["**/cache"], "cache", true));
+        assert!(ignored(&["**/cache"], "a/b/cache", true));
+        assert!(ignored(&["a/**/b"], "a/b", false));
+        assert!(ignored(&["a/**/b"], "a/x/y/b", false));
+        assert!(ignored(&["a/**"], "a/x/y", false));
+        assert!(!ignored(&["a/**"], "a", true));
+    }
+
+    #[test]
+    fn test_dir_only_patterns() {
+        assert!(ignored(&[".git/"], ".git", true));
+        assert!(ignored(&[".git/"], ".git/config", false));
+        assert!(!ignored(&[".git/"], ".git", false));
+    }
+
+    #[test]
+    fn test_negation_and_order() {
+        let patterns = ["*.json", "!settings.json"];
+        assert!(ignored(&patterns, "lazy-lock.json", false));
+        assert!(!ignored(&patterns, "settings.json", false));
+        assert!(ignored(
+            &["!settings.json", "*.json"],
+            "settings.json",
+            false
+        ));
+        // nothing comes back from an ignored directory
+        assert!(ignored(&["cache/", "!cache/keep"], "cache/keep", false));
+    }
+
+    #[test]
+    fn test_wildcards_and_classes() {
+        assert!(ignored(&["?.txt"], "a.txt", false));
+        assert!(!ignored(&["?.txt"], "ab.txt", false));
+        assert!(ignored(&["*.sw[op]"], "x.swo", false));
+        assert!(!ignored(&["*.sw[op]"], "x.swx", false));
+        assert!(ignored(&["[!a-c]*"], "doc", false));
+        assert!(!ignored(&["[!a-c]*"], "bin", false));
+        assert!(ignored(&["[]]"], "]", false));
+        assert!(ignored(&["a[b"], "a[b", false));
+        assert!(ignored(&["\\!important"], "!important", false));
+        assert!(ignored(&["\\#notes"], "#notes", false));
+    }
+
+    #[test]
+    fn test_overlay_leaves_alone_what_the_profile_lacks() -> Result<(), RobeError> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("lua"))?;
+        fs::write(dir.path().join("lua").join("keys.lua"), "")?;
+        let ignore = Ignore::new(["*.swp"]).applied(Apply::Overlay, dir.path());
+        assert!(!ignore.is_ignored(Path::new("lua"), true));
+        assert!(!ignore.is_ignored(Path::new("lua/keys.lua"), false));
+        assert!(ignore.is_ignored(Path::new("lua/plugins.lua"), false));
+        assert!(ignore.is_ignored(Path::new("plugins"), true));
+        assert!(
+            !Ignore::default()
+                .applied(Apply::Replace, dir.path())
+                .is_ignored(Path::new("plugins"), true)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let ignore = Ignore::new("# caches\n\n/cache/\n".lines());
+        assert!(ignore.is_ignored(Path::new("cache"), true));
+        assert!(!ignore.is_ignored(Path::new("# caches"), false));
+        assert!(Ignore::new(["", "# only a comment"]).rules.is_empty());
+    }
+}
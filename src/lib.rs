@@ -0,0 +1,31 @@
+//! Wear your configs: save and switch between named dotfile configurations.
+//!
+//! The [`Wardrobe`] handle does what the `robe` command line does:
+//!
+//! ```no_run
+//! use robe::{Settings, Wardrobe};
+//!
+//! let mut wardrobe = Wardrobe::open(&Settings::load()?)?;
+//! for target in wardrobe.targets() {
+//!     println!("{}: {:?}", target.name, target.active);
+//! }
+//! wardrobe.use_profile("tmux", "work", false)?;
+//! # Ok::<(), robe::RobeError>(())
+//! ```
+
+#[doc(hidden)]
+pub mod cli;
+mod dispatch;
+mod domain;
+mod errors;
+mod help;
+mod ignore;
+mod registry;
+mod settings;
+mod utils;
+mod wardrobe;
+
+pub use errors::RobeError;
+pub use registry::Mode;
+pub use settings::Settings;
+pub use wardrobe::{Contents, Target, Wardrobe};
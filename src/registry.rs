@@ -1,9 +1,30 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 use crate::errors::RobeError;
 
+pub const META_FILE: &str = "meta.toml";
+pub const STATE_FILE: &str = "state.toml";
+pub const BACKUP_DIR: &str = ".backups";
+pub const HISTORY_DIR: &str = ".history";
+/// Entries of a target directory that are not profiles.
+pub const RESERVED: &[&str] = &[META_FILE, STATE_FILE, BACKUP_DIR, HISTORY_DIR];
+/// Directory of the wardrobe holding the outfits, next to the targets.
+pub const OUTFITS_DIR: &str = ".outfits";
+
+/// Fails if `name` cannot name a target or outfit, `what`, in the wardrobe.
+pub fn assert_name(what: &str, name: &str) -> Result<(), RobeError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        Err(RobeError::BadUsage(format!(
+            "{} is not a valid {} name.",
+            name, what
+        )))
+    } else {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Registry {
     pub base_path: PathBuf,
@@ -15,20 +36,94 @@ pub struct TargetRegistry {
     pub name: String,
     pub real_path: PathBuf,
     pub profiles: Vec<String>,
+    pub state: Option<TargetState>,
+    pub mode: Mode,
+    pub apply: Apply,
+    pub file_name: Option<String>,
+    pub validate: Option<String>,
+    pub ignore: Vec<String>,
+    pub hooks: Hooks,
+    /// hooks of single profiles, by profile
+    pub profile_hooks: BTreeMap<String, Hooks>,
 }
 
 impl TargetRegistry {
     pub fn new(name: &str, meta: &TargetMetadata, profiles: &[PathBuf]) -> Self {
-        let mut prof: Vec<String> = Vec::new();
+        let mut target_registry = Self {
+            name: name.to_string(),
+            real_path: PathBuf::from(meta.real_path.clone()),
+            profiles: Vec::new(),
+            state: None,
+            mode: meta.mode,
+            apply: meta.apply,
+            file_name: meta.file_name.clone(),
+            validate: meta.validate.clone(),
+            ignore: meta.ignore.clone(),
+            hooks: meta.hooks.clone(),
+            profile_hooks: meta
+                .profiles
+                .iter()
+                .map(|(profile, pm)| (profile.clone(), pm.hooks.clone()))
+                .collect(),
+        };
+        let suffix = target_registry.extension().map(|ext| format!(".{}", ext));
         for path in profiles {
             if let Some(name) = path.file_name().map(|f| f.to_string_lossy().to_string()) {
-                prof.push(name);
+                let profile = match &suffix {
+                    Some(suffix) => name.strip_suffix(suffix.as_str()).unwrap_or(&name),
+                    None => &name,
+                };
+                target_registry.profiles.push(profile.to_string());
             }
         }
-        Self {
-            name: name.to_string(),
-            real_path: PathBuf::from(meta.real_path.clone()),
-            profiles: prof,
+        target_registry
+    }
+
+    /// The `meta.toml` of the target.
+    pub fn metadata(&self) -> TargetMetadata {
+        TargetMetadata {
+            real_path: self.real_path.to_string_lossy().to_string(),
+            mode: self.mode,
+            apply: self.apply,
+            file_name: self.file_name.clone(),
+            validate: self.validate.clone(),
+            ignore: self.ignore.clone(),
+            hooks: self.hooks.clone(),
+            profiles: self
+                .profile_hooks
+                .iter()
+                .map(|(profile, hooks)| {
+                    let pm = ProfileMetadata {
+                        hooks: hooks.clone(),
+                    };
+                    (profile.clone(), pm)
+                })
+                .collect(),
+        }
+    }
+
+    /// Hooks to run for `event` when handling `profile`: the target's, then the profile's.
+    pub fn hooks_for(&self, profile: &str, event: Event) -> Vec<&str> {
+        [Some(&self.hooks), self.profile_hooks.get(profile)]
+            .into_iter()
+            .flatten()
+            .filter_map(|hooks| hooks.get(event))
+            .collect()
+    }
+
+    /// Extension of the registered file, kept on its stored profiles.
+    pub fn extension(&self) -> Option<String> {
+        let file_name = Path::new(self.file_name.as_deref()?);
+        file_name
+            .extension()
+            .map(|e| e.to_string_lossy().to_string())
+    }
+
+    /// Name `profile` is stored under: `<profile>.<ext>` for files with an extension.
+    pub fn profile_file_name(&self, profile: &str) -> String {
+        match self.extension() {
+            Some(ext) => format!("{}.{}", profile, ext),
+            None => profile.to_string(),
         }
     }
 
@@ -36,28 +131,208 @@ impl TargetRegistry {
         if self.profiles.iter().any(|p| p == profile) {
             Ok(())
         } else {
-            Err(RobeError::message(format!(
+            Err(RobeError::NotFound(format!(
                 "Profile {}/{} not found.",
                 &self.name, &profile
             )))
         }
     }
+
+    /// Fails if `profile` cannot be stored under its name, e.g. `meta` or `.backups`.
+    pub fn assert_profile_name(&self, profile: &str) -> Result<(), RobeError> {
+        let stored = self.profile_file_name(profile);
+        if profile.is_empty() || profile.starts_with('.') || RESERVED.contains(&stored.as_str()) {
+            Err(RobeError::BadUsage(format!(
+                "{}/{} is not a valid profile name.",
+                &self.name, &profile
+            )))
+        } else {
+            Ok(())
+        }
+    }
 }
 
+/// `meta.toml`. Unknown keys are refused, so that a misspelled `validate` or hook is
+/// reported rather than silently doing nothing.
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct TargetMetadata {
     pub real_path: String,
+    #[serde(default)]
+    pub mode: Mode,
+    /// How profiles of a directory are switched to, unless `use --apply` says otherwise.
+    #[serde(default, skip_serializing_if = "Apply::is_replace")]
+    pub apply: Apply,
+    /// Name of the registered file, unset for directories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Command checking a config before it goes live, `{path}` standing for the config,
+    /// e.g. `nginx -t -c {path}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validate: Option<String>,
+    /// Gitignore-style patterns of entries of a directory robe leaves alone, e.g. `.git/`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// `[hooks]`, run for every profile of the target.
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// `[profiles.<profile>]`, settings of single profiles.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileMetadata>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileMetadata {
+    /// `[profiles.<profile>.hooks]`, run after the hooks of the target.
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+}
+
+/// Shell commands run around switching to or saving a profile, e.g.
+/// `post-use = "tmux source-file ~/.tmux.conf"`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_add: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_add: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn get(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::PreUse => self.pre_use.as_deref(),
+            Event::PostUse => self.post_use.as_deref(),
+            Event::PreAdd => self.pre_add.as_deref(),
+            Event::PostAdd => self.post_add.as_deref(),
+        }
+    }
+}
+
+/// When a hook runs. A failing `pre-` hook aborts the command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    PreUse,
+    PostUse,
+    PreAdd,
+    PostAdd,
+}
+
+impl Event {
+    /// Key of the hook in `meta.toml`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PreUse => "pre-use",
+            Self::PostUse => "post-use",
+            Self::PreAdd => "pre-add",
+            Self::PostAdd => "post-add",
+        }
+    }
+
+    pub fn is_pre(&self) -> bool {
+        matches!(self, Self::PreUse | Self::PreAdd)
+    }
 }
 
 impl TargetMetadata {
     pub fn create(path: &Path) -> Result<Self, RobeError> {
-        let tp = path.canonicalize()?;
+        let tp = path
+            .canonicalize()
+            .map_err(RobeError::io("resolve", path))?;
+        let file_name = if tp.is_file() {
+            tp.file_name().map(|f| f.to_string_lossy().to_string())
+        } else {
+            None
+        };
         Ok(Self {
             real_path: tp.to_string_lossy().to_string(),
+            file_name,
+            ..Default::default()
         })
     }
 }
 
+/// How a profile is worn at `real_path`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// `real_path` holds a copy of the profile.
+    #[default]
+    Copy,
+    /// `real_path` is a symlink to the profile, so edits land in the wardrobe.
+    Symlink,
+}
+
+impl Mode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "copy" => Some(Self::Copy),
+            "symlink" => Some(Self::Symlink),
+            _ => None,
+        }
+    }
+}
+
+/// How a profile of a directory is written over the live one.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Apply {
+    /// The live directory ends up holding exactly the profile.
+    #[default]
+    Replace,
+    /// Only the entries of the profile are written; the others of the live directory stay.
+    Overlay,
+}
+
+impl Apply {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "replace" => Some(Self::Replace),
+            "overlay" => Some(Self::Overlay),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Replace => "replace",
+            Self::Overlay => "overlay",
+        }
+    }
+
+    pub fn is_replace(&self) -> bool {
+        *self == Self::Replace
+    }
+}
+
+/// Profiles worn together, by target, stored as `<wardrobe>/.outfits/<name>.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutfitManifest {
+    pub profiles: BTreeMap<String, String>,
+}
+
+/// Last activation of a target, stored as `state.toml` next to `meta.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TargetState {
+    pub profile: String,
+    pub activated_at: u64,
+    pub fingerprint: String,
+    /// With `overlay`, the fingerprint only covers the entries of the profile.
+    #[serde(default, skip_serializing_if = "Apply::is_replace")]
+    pub apply: Apply,
+}
+
 impl Registry {
     pub fn get_target_registry(&self, target: &str) -> Option<TargetRegistry> {
         self.targets.get(target).cloned()
@@ -65,13 +340,36 @@ impl Registry {
 
     pub fn target_registry(&self, target: &str) -> Result<TargetRegistry, RobeError> {
         match self.get_target_registry(target) {
-            None => Err(RobeError::message(format!("Target {} not found.", target))),
+            None => Err(RobeError::NotFound(format!("Target {} not found.", target))),
             Some(tr) => Ok(tr),
         }
     }
+
+    /// `path/to/wardrobe/.outfits/<outfit>.toml`
+    pub fn outfit_path(&self, outfit: &str) -> PathBuf {
+        self.base_path
+            .join(OUTFITS_DIR)
+            .join(format!("{}.toml", outfit))
+    }
+
+    /// `path/to/wardrobe/<target>/<profile>[.<ext>]`
+    pub fn profile_path(&self, target_registry: &TargetRegistry, profile: &str) -> PathBuf {
+        self.base_path
+            .join(&target_registry.name)
+            .join(target_registry.profile_file_name(profile))
+    }
+
+    /// `path/to/wardrobe/<target>/.history/<profile>`, the earlier versions of a profile.
+    pub fn history_path(&self, target_registry: &TargetRegistry, profile: &str) -> PathBuf {
+        self.base_path
+            .join(&target_registry.name)
+            .join(HISTORY_DIR)
+            .join(profile)
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
     use std::fs;
@@ -89,23 +387,112 @@ mod tests {
 
         let meta = TargetMetadata {
             real_path: "/real/path".to_string(),
+            mode: Mode::Symlink,
+            ..Default::default()
         };
 
         let tr = TargetRegistry::new("tmux", &meta, &vec![p1.clone(), p2.clone()]);
 
         assert_eq!(tr.name, "tmux");
         assert_eq!(tr.real_path, PathBuf::from("/real/path"));
+        assert_eq!(tr.mode, Mode::Symlink);
         assert_eq!(tr.profiles.len(), 2);
         assert!(tr.profiles.contains(&"work".to_string()));
         assert!(tr.profiles.contains(&"clean".to_string()));
     }
 
+    #[test]
+    fn test_hooks_from_metadata() {
+        let meta: TargetMetadata = toml::from_str(
+            r#"
+real_path = "/home/me/.tmux.conf"
+
+[hooks]
+post-use = "tmux source-file ~/.tmux.conf"
+
+[profiles.work.hooks]
+pre-use = "true"
+post-use = "echo work"
+"#,
+        )
+        .unwrap();
+
+        let tr = TargetRegistry::new("tmux", &meta, &[]);
+
+        assert_eq!(
+            tr.hooks_for("work", Event::PostUse),
+            vec!["tmux source-file ~/.tmux.conf", "echo work"]
+        );
+        assert_eq!(tr.hooks_for("work", Event::PreUse), vec!["true"]);
+        assert_eq!(
+            tr.hooks_for("home", Event::PostUse),
+            vec!["tmux source-file ~/.tmux.conf"]
+        );
+        assert!(tr.hooks_for("home", Event::PreAdd).is_empty());
+        assert_eq!(tr.metadata(), meta);
+    }
+
+    #[test]
+    fn test_hooks_reject_unknown_events() {
+        let err = toml::from_str::<TargetMetadata>(
+            "real_path = \"/tmp\"\n[hooks]\npost-switch = \"true\"\n",
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_metadata_without_hooks_is_unchanged() {
+        let meta = TargetMetadata {
+            real_path: "/tmp/x".to_string(),
+            ..Default::default()
+        };
+        let written = toml::to_string_pretty(&meta).unwrap();
+        assert!(!written.contains("hooks"));
+        assert!(!written.contains("profiles"));
+    }
+
+    #[test]
+    fn test_target_registry_new_strips_extension() {
+        let meta = TargetMetadata {
+            real_path: "/home/me/.tmux.conf".to_string(),
+            file_name: Some(".tmux.conf".to_string()),
+            ..Default::default()
+        };
+        let profiles = [PathBuf::from("work.conf"), PathBuf::from("home.v2.conf")];
+
+        let tr = TargetRegistry::new("tmux", &meta, &profiles);
+
+        assert_eq!(tr.profiles, vec!["work".to_string(), "home.v2".to_string()]);
+        assert_eq!(tr.profile_file_name("work"), "work.conf");
+
+        let registry = Registry {
+            base_path: PathBuf::from("/wardrobe"),
+            ..Default::default()
+        };
+        assert_eq!(
+            registry.profile_path(&tr, "home.v2"),
+            PathBuf::from("/wardrobe/tmux/home.v2.conf")
+        );
+    }
+
+    #[test]
+    fn test_profile_file_name_without_extension() {
+        let tr = TargetRegistry {
+            name: "bash".to_string(),
+            file_name: Some(".bashrc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(tr.profile_file_name("work"), "work");
+        assert_eq!(TargetRegistry::default().profile_file_name("work"), "work");
+    }
+
     #[test]
     fn test_assert_profile_exists_ok() {
         let tr = TargetRegistry {
             name: "tmux".to_string(),
             real_path: PathBuf::from("/tmp"),
             profiles: vec!["work".to_string(), "clean".to_string()],
+            ..Default::default()
         };
 
         assert!(tr.assert_profile_exists("work").is_ok());
@@ -117,6 +504,7 @@ mod tests {
             name: "tmux".to_string(),
             real_path: PathBuf::from("/tmp"),
             profiles: vec!["work".to_string()],
+            ..Default::default()
         };
 
         let err = tr.assert_profile_exists("missing").unwrap_err();
@@ -125,6 +513,36 @@ mod tests {
         assert!(msg.contains("Profile tmux/missing not found"));
     }
 
+    #[test]
+    fn test_assert_profile_name() {
+        let tr = TargetRegistry {
+            name: "starship".to_string(),
+            file_name: Some("starship.toml".to_string()),
+            ..Default::default()
+        };
+
+        assert!(tr.assert_profile_name("work").is_ok());
+        assert!(tr.assert_profile_name("meta").is_err());
+        assert!(tr.assert_profile_name("state").is_err());
+        assert!(tr.assert_profile_name(".backups").is_err());
+        assert!(tr.assert_profile_name("").is_err());
+        assert!(
+            TargetRegistry::default()
+                .assert_profile_name("meta")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_assert_name() {
+        assert!(assert_name("target", "tmux").is_ok());
+        assert!(assert_name("target", "nvim-nightly").is_ok());
+        assert!(assert_name("target", "").is_err());
+        assert!(assert_name("target", ".outfits").is_err());
+        let err = assert_name("outfit", "a/b").unwrap_err();
+        assert!(format!("{}", err).contains("a/b is not a valid outfit name."));
+    }
+
     #[test]
     fn test_target_metadata_create() {
         let dir = tempdir().unwrap();
@@ -133,6 +551,28 @@ mod tests {
         let meta = TargetMetadata::create(path).unwrap();
 
         assert!(meta.real_path.contains(path.to_string_lossy().as_ref()));
+        assert_eq!(meta.file_name, None);
+
+        fs::write(path.join("tmux.conf"), "").unwrap();
+        let meta = TargetMetadata::create(&path.join("tmux.conf")).unwrap();
+        assert_eq!(meta.file_name.as_deref(), Some("tmux.conf"));
+    }
+
+    #[test]
+    fn test_target_metadata_mode_defaults_to_copy() {
+        let meta: TargetMetadata = toml::from_str(r#"real_path = "/tmp/x""#).unwrap();
+        assert_eq!(meta.mode, Mode::Copy);
+
+        let meta: TargetMetadata =
+            toml::from_str("real_path = \"/tmp/x\"\nmode = \"symlink\"").unwrap();
+        assert_eq!(meta.mode, Mode::Symlink);
+    }
+
+    #[test]
+    fn test_mode_parse() {
+        assert_eq!(Mode::parse("copy"), Some(Mode::Copy));
+        assert_eq!(Mode::parse("symlink"), Some(Mode::Symlink));
+        assert_eq!(Mode::parse("hardlink"), None);
     }
 
     #[test]
@@ -143,6 +583,7 @@ mod tests {
             name: "tmux".to_string(),
             real_path: PathBuf::from("/tmp"),
             profiles: vec!["work".to_string()],
+            ..Default::default()
         };
 
         reg.targets.insert("tmux".to_string(), tr.clone());
@@ -159,5 +600,6 @@ mod tests {
         let msg = format!("{}", err);
 
         assert!(msg.contains("Target missing not found"));
+        assert_eq!(err.exit_code(), 3);
     }
 }
@@ -1,19 +1,85 @@
+use crate::errors::RobeError;
+use crate::utils;
 use serde::Deserialize;
 use std::path::PathBuf;
 
+/// Config file to read instead of the default one.
+pub const CONFIG_ENV: &str = "ROBE_CONFIG";
+/// Wardrobe to use, over the one in the config file.
+pub const WARDROBE_ENV: &str = "ROBE_WARDROBE";
+
+/// Keys of the config file, in the order they are listed.
+pub const KEYS: [&str; 3] = ["wardrobe", "backups", "history"];
+
+/// Where the effective config file or wardrobe comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Source {
+    #[default]
+    Default,
+    File,
+    Env(&'static str),
+    Flag(&'static str),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::File => f.write_str("from config file"),
+            Self::Env(var) => f.write_fmt(format_args!("from ${}", var)),
+            Self::Flag(flag) => f.write_fmt(format_args!("from {}", flag)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct Settings {
     pub wardrobe: String,
+    /// How many backups of the live config to keep per target.
+    pub backups: usize,
+    /// How many earlier versions of each profile to keep.
+    pub history: usize,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
             wardrobe: default_data_location(),
+            backups: DEFAULT_BACKUPS,
+            history: DEFAULT_HISTORY,
         }
     }
 }
 
+impl Settings {
+    /// The settings `robe` runs with: the config file at `$ROBE_CONFIG` or the default
+    /// location, with `$ROBE_WARDROBE` over its wardrobe.
+    pub fn load() -> Result<Self, RobeError> {
+        let (fp, source) = utils::config_file_path(None, std::env::var(CONFIG_ENV).ok());
+        let mut settings = utils::get_settings(&fp, source)?;
+        utils::override_wardrobe(&mut settings, std::env::var(WARDROBE_ENV).ok());
+        Ok(settings)
+    }
+}
+
+/// Keys of the config file `contents` robe does not know, sorted.
+/// They are kept in the file, e.g. for a newer version of robe, but otherwise ignored.
+pub fn unknown_keys(contents: &str) -> Vec<String> {
+    toml::from_str::<toml::Table>(contents)
+        .map(|table| {
+            table
+                .keys()
+                .filter(|key| !KEYS.contains(&key.as_str()))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+const DEFAULT_BACKUPS: usize = 5;
+const DEFAULT_HISTORY: usize = 10;
+
 fn default_data_location() -> String {
     dirs::data_local_dir()
         .map(|mut p| {
@@ -58,11 +124,47 @@ wardrobe = "/tmp/test"
 
         let expected = Settings {
             wardrobe: "/tmp/test".to_string(),
+            backups: DEFAULT_BACKUPS,
+            history: DEFAULT_HISTORY,
         };
 
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn test_deserialize_ignores_unknown_fields() {
+        let toml = "wardobe = \"/tmp/test\"\nbackups = 2\ntheme = \"dark\"\n";
+
+        let parsed: Settings = toml::from_str(toml).unwrap();
+
+        assert_eq!(parsed.wardrobe, Settings::default().wardrobe);
+        assert_eq!(parsed.backups, 2);
+        assert_eq!(unknown_keys(toml), vec!["theme", "wardobe"]);
+        assert!(unknown_keys("history = 3").is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_backups() {
+        let toml = r#"
+wardrobe = "/tmp/test"
+backups = 2
+history = 0
+"#;
+
+        let parsed: Settings = toml::from_str(toml).unwrap();
+
+        assert_eq!(parsed.backups, 2);
+        assert_eq!(parsed.history, 0);
+    }
+
+    #[test]
+    fn test_display_source() {
+        assert_eq!(Source::Default.to_string(), "default");
+        assert_eq!(Source::File.to_string(), "from config file");
+        assert_eq!(Source::Env(WARDROBE_ENV).to_string(), "from $ROBE_WARDROBE");
+        assert_eq!(Source::Flag("--config").to_string(), "from --config");
+    }
+
     #[test]
     fn test_default_data_location_contains_robe() {
         let path = default_data_location();
@@ -1,9 +1,10 @@
 use crate::errors::RobeError;
-use crate::registry::{Registry, TargetRegistry};
-use crate::settings::Settings;
+use crate::registry::{META_FILE, RESERVED, Registry, STATE_FILE, TargetMetadata, TargetRegistry};
+use crate::settings::{self, CONFIG_ENV, Settings, Source, WARDROBE_ENV};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn settings_file_path() -> String {
     let maybe_fp = dirs::config_local_dir().map(|mut p| {
@@ -17,30 +18,105 @@ pub fn settings_file_path() -> String {
         .unwrap_or_else(|| "$HOME/.config/robe/config.toml".to_string())
 }
 
-pub fn get_settings(fp: &String) -> Settings {
-    if let Ok(string) = fs::read_to_string(PathBuf::from(fp))
-        && let Ok(settings) = toml::from_str(&string)
-    {
-        return settings;
+/// The config file to read: `--config <file>`, else `$ROBE_CONFIG`, else the default one.
+pub fn config_file_path(flag: Option<&Path>, env: Option<String>) -> (String, Source) {
+    if let Some(fp) = flag {
+        return (fp.to_string_lossy().to_string(), Source::Flag("--config"));
+    }
+    match env.filter(|fp| !fp.is_empty()) {
+        Some(fp) => (fp, Source::Env(CONFIG_ENV)),
+        None => (settings_file_path(), Source::Default),
+    }
+}
+
+/// Let `$ROBE_WARDROBE` take precedence over the wardrobe of the config file.
+/// Returns where the effective wardrobe comes from.
+pub fn override_wardrobe(settings: &mut Settings, env: Option<String>) -> Source {
+    if let Some(wardrobe) = env.filter(|w| !w.is_empty()) {
+        settings.wardrobe = wardrobe;
+        return Source::Env(WARDROBE_ENV);
+    }
+    if settings.wardrobe == Settings::default().wardrobe {
+        Source::Default
+    } else {
+        Source::File
     }
-    Settings::default()
 }
 
+/// Settings from the config file at `fp`.
+/// A missing file means the defaults, unless it was asked for with `--config` or `$ROBE_CONFIG`.
+pub fn get_settings(fp: &str, source: Source) -> Result<Settings, RobeError> {
+    let contents = match fs::read_to_string(fp) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if source == Source::Default {
+                return Ok(Settings::default());
+            }
+            return Err(RobeError::Config {
+                file: fp.to_string(),
+                location: None,
+                message: "file not found".to_string(),
+            });
+        }
+        Err(e) => return Err(RobeError::io("read", Path::new(fp))(e)),
+    };
+    parse_settings(fp, &contents)
+}
+
+/// Settings from `contents`, the text of the config file at `fp`.
+/// Unknown keys are only warned about, so that a config of a newer version still loads.
+pub fn parse_settings(fp: &str, contents: &str) -> Result<Settings, RobeError> {
+    let settings: Settings = toml::from_str(contents)
+        .map_err(|e| RobeError::config(fp, contents, e.span(), e.message()))?;
+    for key in settings::unknown_keys(contents) {
+        eprintln!("robe: warning: {}: unknown key `{}` is ignored.", fp, key);
+    }
+    if settings.wardrobe.trim().is_empty() {
+        return Err(RobeError::Config {
+            file: fp.to_string(),
+            location: None,
+            message: "wardrobe must not be empty".to_string(),
+        });
+    }
+    Ok(settings)
+}
+
+/// The registry of the wardrobe, creating the wardrobe if missing and migrating
+/// file profiles stored without their extension, see `migrate_file_names`.
 pub fn get_registry(settings: &Settings) -> Result<Registry, RobeError> {
     let fp: PathBuf = PathBuf::from(&settings.wardrobe);
 
-    fs::create_dir_all(&fp)?;
+    fs::create_dir_all(&fp).map_err(RobeError::io("create", &fp))?;
+    for target in get_subdirs(&fp)? {
+        if let Some(mut meta) = read_metadata(&target)? {
+            migrate_file_names(&target, &mut meta)?;
+        }
+    }
+
+    read_registry(settings)
+}
+
+/// The registry of the wardrobe as it is on disk, changing nothing:
+/// a missing wardrobe is an empty one, and profiles are not migrated.
+pub fn read_registry(settings: &Settings) -> Result<Registry, RobeError> {
+    let fp: PathBuf = PathBuf::from(&settings.wardrobe);
 
     let mut registered: HashMap<String, TargetRegistry> = HashMap::new();
 
-    for target in get_subdirs(&fp)? {
-        if let Ok(str) = fs::read_to_string(Path::join(&target, "meta.toml"))
-            && let Ok(meta) = toml::from_str(&str)
-        {
-            let profiles = get_profiles_from_dir(&target, "meta.toml")?;
+    let targets = if fp.is_dir() {
+        get_subdirs(&fp)?
+    } else {
+        Vec::new()
+    };
+    for target in targets {
+        if let Some(meta) = read_metadata(&target)? {
+            let profiles = get_profiles_from_dir(&target, RESERVED)?;
             if let Some(target_name_os) = target.file_name() {
                 let target_name = target_name_os.to_string_lossy().to_string();
-                let target_registry = TargetRegistry::new(&target_name, &meta, &profiles);
+                let mut target_registry = TargetRegistry::new(&target_name, &meta, &profiles);
+                target_registry.state = fs::read_to_string(Path::join(&target, STATE_FILE))
+                    .ok()
+                    .and_then(|s| toml::from_str(&s).ok());
                 registered.insert(target_name, target_registry);
             }
         }
@@ -52,11 +128,93 @@ pub fn get_registry(settings: &Settings) -> Result<Registry, RobeError> {
     })
 }
 
+/// The `meta.toml` of the target directory `target`, `None` if it has none.
+/// A `meta.toml` that cannot be parsed is an error, reported with its location,
+/// rather than a target that silently goes missing.
+fn read_metadata(target: &Path) -> Result<Option<TargetMetadata>, RobeError> {
+    let path = target.join(META_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(RobeError::io("read", &path)(e)),
+    };
+    let meta = toml::from_str(&contents).map_err(|e| {
+        RobeError::config(&path.to_string_lossy(), &contents, e.span(), e.message())
+    })?;
+    Ok(Some(meta))
+}
+
+/// Profiles of a file used to be stored without its extension.
+/// Record the file name in `meta.toml` and rename each `<profile>` to `<profile>.<ext>`,
+/// re-pointing a live symlink to the renamed profile. Each rename is reported on stderr.
+/// Scratch entries such as `.work.robe-new` are not profiles and are left as they are.
+fn migrate_file_names(target: &PathBuf, meta: &mut TargetMetadata) -> Result<(), RobeError> {
+    if meta.file_name.is_some() {
+        return Ok(());
+    }
+    let real_path = PathBuf::from(&meta.real_path);
+    let mut profiles = get_profiles_from_dir(target, RESERVED)?;
+    let is_file =
+        real_path.is_file() || (!profiles.is_empty() && profiles.iter().all(|p| p.is_file()));
+    let Some(file_name) = real_path.file_name().filter(|_| is_file) else {
+        return Ok(());
+    };
+    meta.file_name = Some(file_name.to_string_lossy().to_string());
+
+    if let Some(ext) = Path::new(file_name).extension() {
+        let linked = fs::read_link(&real_path).ok();
+        // longest first, so `work` is never renamed onto a `work.conf` that is still to be renamed
+        profiles.sort_by_key(|p| std::cmp::Reverse(p.as_os_str().len()));
+        for old in profiles {
+            let mut new = old.clone().into_os_string();
+            new.push(".");
+            new.push(ext);
+            let new = PathBuf::from(new);
+            fs::rename(&old, &new)
+                .map_err(RobeError::io(&format!("rename {} to", old.display()), &new))?;
+            eprintln!(
+                "robe: renamed {} to {}, as profiles of {} keep its extension.",
+                old.display(),
+                new.display(),
+                file_name.to_string_lossy()
+            );
+
+            if let Some(link) = &linked
+                && (*link == old || std::path::absolute(&old).is_ok_and(|abs| *link == abs))
+            {
+                relink(&real_path, &new)?;
+            }
+        }
+    }
+
+    let meta_path = target.join(META_FILE);
+    fs::write(&meta_path, toml::to_string_pretty(meta)?)
+        .map_err(RobeError::io("write", &meta_path))?;
+    Ok(())
+}
+
+/// Point the symlink at `link` to `dest`, swapping the new link in with a rename.
+fn relink(link: &Path, dest: &Path) -> Result<(), RobeError> {
+    let name = link
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let staged = link.with_file_name(format!(".{}.robe-new", name));
+    let dest = std::path::absolute(dest).map_err(RobeError::io("resolve", dest))?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&dest, &staged).map_err(RobeError::io("create symlink", &staged))?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_file(&dest, &staged)
+        .map_err(RobeError::io("create symlink", &staged))?;
+    fs::rename(&staged, link).map_err(RobeError::io("replace", link))?;
+    Ok(())
+}
+
 pub fn get_subdirs(dir: &PathBuf) -> Result<Vec<PathBuf>, RobeError> {
     let mut dirs = Vec::new();
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
         let path = entry.path();
 
         if path.is_dir() {
@@ -70,8 +228,8 @@ pub fn get_subdirs(dir: &PathBuf) -> Result<Vec<PathBuf>, RobeError> {
 pub fn _get_files_in_dir(dir: &PathBuf) -> Result<Vec<PathBuf>, RobeError> {
     let mut dirs = Vec::new();
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
         let path = entry.path();
 
         if path.is_file() {
@@ -82,15 +240,18 @@ pub fn _get_files_in_dir(dir: &PathBuf) -> Result<Vec<PathBuf>, RobeError> {
     Ok(dirs)
 }
 
-pub fn get_profiles_from_dir(dir: &PathBuf, file_name: &str) -> Result<Vec<PathBuf>, RobeError> {
+/// The profiles stored in a target directory: every entry but `excluded` ones
+/// and those starting with `.`, which are robe's scratch, e.g. `.work.robe-new`,
+/// or the system's, e.g. `.DS_Store`.
+pub fn get_profiles_from_dir(dir: &PathBuf, excluded: &[&str]) -> Result<Vec<PathBuf>, RobeError> {
     let mut dirs = Vec::new();
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
+    for entry in fs::read_dir(dir).map_err(RobeError::io("list", dir))? {
+        let entry = entry.map_err(RobeError::io("list", dir))?;
         let path = entry.path();
         let f = entry.file_name().to_string_lossy().to_string();
 
-        if f != file_name {
+        if !f.starts_with('.') && !excluded.contains(&f.as_str()) {
             dirs.push(path);
         }
     }
@@ -98,6 +259,46 @@ pub fn get_profiles_from_dir(dir: &PathBuf, file_name: &str) -> Result<Vec<PathB
     Ok(dirs)
 }
 
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    now_millis() / 1000
+}
+
+/// Milliseconds since the unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Formats unix seconds as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,6 +313,38 @@ mod tests {
         assert!(path.ends_with("robe/config.toml"));
     }
 
+    #[test]
+    fn test_config_file_path_precedence() {
+        let flag = PathBuf::from("/flag/config.toml");
+        let env = Some("/env/config.toml".to_string());
+
+        assert_eq!(
+            config_file_path(Some(&flag), env.clone()),
+            ("/flag/config.toml".to_string(), Source::Flag("--config"))
+        );
+        assert_eq!(
+            config_file_path(None, env),
+            ("/env/config.toml".to_string(), Source::Env(CONFIG_ENV))
+        );
+        assert_eq!(
+            config_file_path(None, Some("".to_string())),
+            (settings_file_path(), Source::Default)
+        );
+    }
+
+    #[test]
+    fn test_override_wardrobe() {
+        let mut settings = Settings::default();
+        assert_eq!(override_wardrobe(&mut settings, None), Source::Default);
+
+        settings.wardrobe = "/from/file".to_string();
+        assert_eq!(override_wardrobe(&mut settings, None), Source::File);
+
+        let source = override_wardrobe(&mut settings, Some("/from/env".to_string()));
+        assert_eq!(source, Source::Env(WARDROBE_ENV));
+        assert_eq!(settings.wardrobe, "/from/env");
+    }
+
     #[test]
     fn test_get_settings_valid_file() {
         let dir = tempdir().unwrap();
@@ -120,29 +353,52 @@ mod tests {
         let mut file = File::create(&file_path).unwrap();
         writeln!(file, "wardrobe = \"/tmp/robe\"").unwrap();
 
-        let settings = get_settings(&file_path.to_string_lossy().to_string());
+        let settings = get_settings(&file_path.to_string_lossy(), Source::Default).unwrap();
 
         assert_eq!(settings.wardrobe, "/tmp/robe");
     }
 
     #[test]
-    fn test_get_settings_invalid_file_returns_default() {
+    fn test_get_settings_invalid_file_fails() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("config.toml");
 
-        fs::write(&file_path, "invalid toml :::").unwrap();
+        fs::write(&file_path, "backups = 3\nwardrobe = /tmp/robe\n").unwrap();
 
-        let settings = get_settings(&file_path.to_string_lossy().to_string());
+        let err = get_settings(&file_path.to_string_lossy(), Source::Default).unwrap_err();
 
-        assert_eq!(settings, Settings::default());
+        match err {
+            RobeError::Config { file, location, .. } => {
+                assert_eq!(file, file_path.to_string_lossy());
+                assert_eq!(location, Some((2, 12)));
+            }
+            _ => panic!("Expected Config"),
+        }
+    }
+
+    #[test]
+    fn test_get_settings_empty_wardrobe_fails() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("config.toml");
+
+        fs::write(&file_path, "wardrobe = \"\"\n").unwrap();
+
+        let err = get_settings(&file_path.to_string_lossy(), Source::Default).unwrap_err();
+        assert!(format!("{}", err).contains("wardrobe must not be empty"));
     }
 
     #[test]
     fn test_get_settings_missing_file_returns_default() {
-        let settings = get_settings(&"nonexistent.toml".to_string());
+        let settings = get_settings("nonexistent.toml", Source::Default).unwrap();
         assert_eq!(settings, Settings::default());
     }
 
+    #[test]
+    fn test_get_settings_missing_explicit_file_fails() {
+        let err = get_settings("nonexistent.toml", Source::Flag("--config")).unwrap_err();
+        assert!(format!("{}", err).ends_with("nonexistent.toml: file not found"));
+    }
+
     #[test]
     fn test_get_subdirs() {
         let dir = tempdir().unwrap();
@@ -189,15 +445,42 @@ mod tests {
 
         let keep = dir.path().join("profile1");
         let exclude = dir.path().join("meta.toml");
+        let state = dir.path().join("state.toml");
 
         fs::create_dir(&keep).unwrap();
         File::create(&exclude).unwrap();
+        File::create(&state).unwrap();
 
-        let result = get_profiles_from_dir(&dir.path().to_path_buf(), "meta.toml").unwrap();
+        let result =
+            get_profiles_from_dir(&dir.path().to_path_buf(), &["meta.toml", "state.toml"]).unwrap();
 
         assert_eq!(result.len(), 1);
         assert!(result.contains(&keep));
         assert!(!result.contains(&exclude));
+        assert!(!result.contains(&state));
+    }
+
+    #[test]
+    fn test_get_profiles_from_dir_skips_dot_entries() {
+        let dir = tempdir().unwrap();
+
+        let keep = dir.path().join("work");
+        File::create(&keep).unwrap();
+        for name in [".work.robe-new", ".robe-old", ".robe-journal", ".DS_Store"] {
+            File::create(dir.path().join(name)).unwrap();
+        }
+        fs::create_dir(dir.path().join(".1.robe-rename")).unwrap();
+
+        let result = get_profiles_from_dir(&dir.path().to_path_buf(), RESERVED).unwrap();
+
+        assert_eq!(result, vec![keep]);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_760_791_530), "2025-10-18 12:45:30 UTC");
     }
 
     #[test]
@@ -206,6 +489,7 @@ mod tests {
 
         let settings = Settings {
             wardrobe: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
         };
 
         let registry = get_registry(&settings).unwrap();
@@ -233,6 +517,7 @@ real_path = "/tmp/tmux.conf"
 
         let settings = Settings {
             wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
         };
 
         let registry = get_registry(&settings).unwrap();
@@ -244,10 +529,162 @@ real_path = "/tmp/tmux.conf"
         assert_eq!(tmux.profiles.len(), 2);
         assert!(tmux.profiles.contains(&"work".to_string()));
         assert!(tmux.profiles.contains(&"clean".to_string()));
+        assert!(tmux.state.is_none());
     }
 
     #[test]
-    fn test_get_registry_ignores_invalid_meta() {
+    fn test_get_registry_migrates_file_names() {
+        let dir = tempdir().unwrap();
+        let wardrobe = dir.path().join("wardrobe");
+        let target_dir = wardrobe.join("tmux");
+        let real_path = dir.path().join("tmux.conf");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(
+            target_dir.join("meta.toml"),
+            format!("real_path = {:?}\n", real_path.to_string_lossy()),
+        )
+        .unwrap();
+        fs::write(target_dir.join("work"), "work").unwrap();
+        // a profile that already looks like it has the extension keeps its name
+        fs::write(target_dir.join("work.conf"), "work.conf").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target_dir.join("work"), &real_path).unwrap();
+        #[cfg(not(unix))]
+        fs::write(&real_path, "work").unwrap();
+
+        let settings = Settings {
+            wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let registry = get_registry(&settings).unwrap();
+
+        let tmux = registry.targets.get("tmux").unwrap();
+        let mut profiles = tmux.profiles.clone();
+        profiles.sort();
+        assert_eq!(profiles, vec!["work".to_string(), "work.conf".to_string()]);
+        assert_eq!(tmux.file_name.as_deref(), Some("tmux.conf"));
+        assert_eq!(
+            fs::read_to_string(target_dir.join("work.conf")).unwrap(),
+            "work"
+        );
+        assert_eq!(
+            fs::read_to_string(target_dir.join("work.conf.conf")).unwrap(),
+            "work.conf"
+        );
+        assert_eq!(fs::read_to_string(&real_path).unwrap(), "work");
+
+        let meta = fs::read_to_string(target_dir.join("meta.toml")).unwrap();
+        assert!(meta.contains("file_name = \"tmux.conf\""));
+
+        // a second load leaves the migrated wardrobe alone
+        let again = get_registry(&settings).unwrap();
+        assert_eq!(again.targets.get("tmux").unwrap().profiles.len(), 2);
+        assert!(target_dir.join("work.conf").exists());
+    }
+
+    #[test]
+    fn test_get_registry_migrates_around_scratch_entries() {
+        let dir = tempdir().unwrap();
+        let wardrobe = dir.path().join("wardrobe");
+        let target_dir = wardrobe.join("tmux");
+        // the live config is missing, so the kind of the profiles decides
+        let real_path = dir.path().join("tmux.conf");
+        fs::create_dir_all(target_dir.join(".robe-old")).unwrap();
+        fs::write(
+            target_dir.join("meta.toml"),
+            format!("real_path = {:?}\n", real_path.to_string_lossy()),
+        )
+        .unwrap();
+        fs::write(target_dir.join("work"), "work").unwrap();
+        fs::write(target_dir.join(".work.robe-new"), "staged").unwrap();
+
+        let settings = Settings {
+            wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let registry = get_registry(&settings).unwrap();
+
+        let tmux = registry.targets.get("tmux").unwrap();
+        assert_eq!(tmux.profiles, vec!["work".to_string()]);
+        assert_eq!(tmux.file_name.as_deref(), Some("tmux.conf"));
+        assert!(target_dir.join("work.conf").is_file());
+        assert!(target_dir.join(".robe-old").is_dir());
+        assert_eq!(
+            fs::read_to_string(target_dir.join(".work.robe-new")).unwrap(),
+            "staged"
+        );
+        assert!(!target_dir.join(".work.robe-new.conf").exists());
+    }
+
+    #[test]
+    fn test_read_registry_changes_nothing() {
+        let dir = tempdir().unwrap();
+        let wardrobe = dir.path().join("wardrobe");
+        let settings = Settings {
+            wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let registry = read_registry(&settings).unwrap();
+        assert!(registry.targets.is_empty());
+        assert!(!wardrobe.exists());
+
+        // a file target from before profiles kept their extension
+        let target_dir = wardrobe.join("tmux");
+        fs::create_dir_all(&target_dir).unwrap();
+        let meta = format!(
+            "real_path = {:?}\n",
+            dir.path().join("tmux.conf").to_string_lossy()
+        );
+        fs::write(target_dir.join("meta.toml"), &meta).unwrap();
+        fs::write(target_dir.join("work"), "work").unwrap();
+
+        let registry = read_registry(&settings).unwrap();
+        assert_eq!(registry.targets.get("tmux").unwrap().profiles, vec!["work"]);
+        assert!(target_dir.join("work").exists());
+        assert_eq!(
+            fs::read_to_string(target_dir.join("meta.toml")).unwrap(),
+            meta
+        );
+    }
+
+    #[test]
+    fn test_get_registry_reads_state() {
+        let dir = tempdir().unwrap();
+        let wardrobe = dir.path();
+
+        let target_dir = wardrobe.join("tmux");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(
+            target_dir.join("meta.toml"),
+            r#"real_path = "/tmp/tmux.conf""#,
+        )
+        .unwrap();
+        fs::write(
+            target_dir.join("state.toml"),
+            "profile = \"work\"\nactivated_at = 10\nfingerprint = \"abc\"\n",
+        )
+        .unwrap();
+        fs::write(target_dir.join("work"), "").unwrap();
+
+        let settings = Settings {
+            wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let registry = get_registry(&settings).unwrap();
+        let tmux = registry.targets.get("tmux").unwrap();
+
+        assert_eq!(tmux.profiles, vec!["work".to_string()]);
+        let state = tmux.state.as_ref().unwrap();
+        assert_eq!(state.profile, "work");
+        assert_eq!(state.activated_at, 10);
+        assert_eq!(state.fingerprint, "abc");
+    }
+
+    #[test]
+    fn test_get_registry_reports_invalid_meta() {
         let dir = tempdir().unwrap();
         let wardrobe = dir.path();
 
@@ -258,12 +695,68 @@ real_path = "/tmp/tmux.conf"
 
         let settings = Settings {
             wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
         };
 
-        let registry = get_registry(&settings).unwrap();
+        let err = get_registry(&settings).unwrap_err();
+        assert_eq!(err.exit_code(), 7);
+        assert!(format!("{}", err).contains("nvim/meta.toml:1:"));
+    }
 
-        // invalid meta should skip this target
-        assert_eq!(registry.targets.len(), 0);
+    #[test]
+    fn test_get_registry_reports_misspelled_hook() {
+        let dir = tempdir().unwrap();
+        let target_dir = dir.path().join("ng");
+        fs::create_dir_all(target_dir.join("main")).unwrap();
+        fs::write(
+            target_dir.join("meta.toml"),
+            "real_path = \"/tmp/ng\"\n\n[hooks]\npre_use = \"false\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings {
+            wardrobe: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        match get_registry(&settings).unwrap_err() {
+            RobeError::Config {
+                file,
+                location,
+                message,
+            } => {
+                assert!(file.ends_with("meta.toml"));
+                assert_eq!(location, Some((4, 1)));
+                assert!(message.contains("unknown field `pre_use`"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_get_registry_reports_misspelled_validate() {
+        let dir = tempdir().unwrap();
+        let target_dir = dir.path().join("ng");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(
+            target_dir.join("meta.toml"),
+            "real_path = \"/tmp/ng\"\nvaldiate = \"false {path}\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings {
+            wardrobe: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let err = get_registry(&settings).unwrap_err();
+        assert_eq!(err.exit_code(), 7);
+        let message = format!("{}", err);
+        assert!(
+            message.contains("ng/meta.toml:2:1: unknown field `valdiate`"),
+            "{}",
+            message
+        );
     }
 
     #[test]
@@ -285,6 +778,7 @@ real_path = "/tmp/tmux.conf"
 
         let settings = Settings {
             wardrobe: wardrobe.to_string_lossy().to_string(),
+            ..Default::default()
         };
 
         let registry = get_registry(&settings).unwrap();
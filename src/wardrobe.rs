@@ -0,0 +1,456 @@
+use crate::dispatch::{add, cp, diff, io, mv, outfit, register, rm, target, usecmd};
+use crate::domain::{
+    self, Add, Cp, Format, Mv, Outfit, OutfitAction, Register, Rm, TargetAction, Use,
+};
+use crate::errors::RobeError;
+use crate::ignore::Ignore;
+use crate::registry::{Mode, Registry, TargetRegistry};
+use crate::settings::Settings;
+use crate::utils;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A wardrobe: the registered targets and their stored profiles.
+///
+/// Every change goes through the same code as the `robe` command line, so the
+/// wardrobe stays usable from both. Warnings about a copy, e.g. a symlink
+/// pointing outside of a captured directory, are printed to stderr.
+#[derive(Debug, Clone)]
+pub struct Wardrobe {
+    settings: Settings,
+    registry: Registry,
+}
+
+/// A registered target, as seen when its wardrobe was last read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub name: String,
+    /// where the target's config lives, e.g. `~/.tmux.conf`
+    pub real_path: PathBuf,
+    pub mode: Mode,
+    /// names of the stored profiles, sorted
+    pub profiles: Vec<String>,
+    /// the profile linked at `real_path`, or else the last activated one
+    pub active: Option<String>,
+}
+
+/// Contents of a profile or live config.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Contents {
+    File(Vec<u8>),
+    /// files by their path relative to the directory
+    Dir(BTreeMap<PathBuf, Vec<u8>>),
+}
+
+impl Wardrobe {
+    /// Open the wardrobe of `settings`, creating its directory if needed and renaming file
+    /// profiles stored without their extension, each rename reported on stderr.
+    pub fn open(settings: &Settings) -> Result<Self, RobeError> {
+        Ok(Self {
+            settings: settings.clone(),
+            registry: utils::get_registry(settings)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.registry.base_path
+    }
+
+    /// All targets, sorted by name.
+    pub fn targets(&self) -> Vec<Target> {
+        let mut targets: Vec<Target> = self
+            .registry
+            .targets
+            .values()
+            .map(|tr| self.describe(tr))
+            .collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        targets
+    }
+
+    pub fn target(&self, target: &str) -> Result<Target, RobeError> {
+        Ok(self.describe(&self.registry.target_registry(target)?))
+    }
+
+    /// Names of the profiles of `target`, sorted.
+    pub fn profiles(&self, target: &str) -> Result<Vec<String>, RobeError> {
+        Ok(self.target(target)?.profiles)
+    }
+
+    /// Register the file or directory at `path` as `target`, storing it as its first `profile`.
+    /// In symlink mode, `path` is replaced with a link to the stored profile.
+    pub fn register(
+        &mut self,
+        target: &str,
+        profile: &str,
+        path: &Path,
+        mode: Mode,
+    ) -> Result<(), RobeError> {
+        let cmd = Register {
+            target: target.to_string(),
+            profile: profile.to_string(),
+            register_file_path: path.to_path_buf(),
+            mode,
+            dereference: false,
+        };
+        register::register(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Store the live config of `target` as `profile`.
+    /// An existing profile is only overwritten with `force`.
+    pub fn add(&mut self, target: &str, profile: &str, force: bool) -> Result<(), RobeError> {
+        let cmd = Add {
+            target: target.to_string(),
+            profile: profile.to_string(),
+            force,
+            dereference: false,
+        };
+        add::add(&cmd, &self.registry, &self.settings)?;
+        self.reload()
+    }
+
+    /// Activate `profile` of `target`, backing up the live config first.
+    /// Fails with `RobeError::Drift` if the live config changed since the last activation,
+    /// unless `force` discards the changes.
+    pub fn use_profile(
+        &mut self,
+        target: &str,
+        profile: &str,
+        force: bool,
+    ) -> Result<(), RobeError> {
+        let cmd = Use {
+            target: target.to_string(),
+            profile: profile.to_string(),
+            force,
+            save: false,
+            apply: None,
+        };
+        usecmd::usecmd(&cmd, &self.registry, &self.settings)?;
+        self.reload()
+    }
+
+    /// Remove `profile` of `target`, or the whole target when `profile` is `None`.
+    /// The live config is left in place.
+    pub fn rm(&mut self, target: &str, profile: Option<&str>) -> Result<(), RobeError> {
+        let cmd = Rm {
+            target: target.to_string(),
+            profile: profile.map(str::to_string),
+        };
+        rm::rm(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Rename `profile` of `target` to `new_profile`, leaving the live config as it is.
+    /// An existing profile is only overwritten with `force`.
+    pub fn mv(
+        &mut self,
+        target: &str,
+        profile: &str,
+        new_profile: &str,
+        force: bool,
+    ) -> Result<(), RobeError> {
+        let cmd = Mv {
+            target: target.to_string(),
+            profile: profile.to_string(),
+            new_profile: new_profile.to_string(),
+            force,
+        };
+        mv::mv(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Copy `from_profile` of `from_target` to `profile` of `target`, which must hold
+    /// the same kind of config. An existing profile is only overwritten with `force`.
+    pub fn cp(
+        &mut self,
+        from_target: &str,
+        from_profile: &str,
+        target: &str,
+        profile: &str,
+        force: bool,
+    ) -> Result<(), RobeError> {
+        let cmd = Cp {
+            from_target: from_target.to_string(),
+            from_profile: from_profile.to_string(),
+            target: target.to_string(),
+            profile: profile.to_string(),
+            force,
+        };
+        cp::cp(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Rename `target` to `name`, re-linking a live config that links into the wardrobe.
+    pub fn rename_target(&mut self, target: &str, name: &str) -> Result<(), RobeError> {
+        let cmd = domain::Target {
+            target: target.to_string(),
+            action: TargetAction::Rename(name.to_string()),
+        };
+        target::target(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Manage the config at `path` as `target` from now on, leaving the live config in place.
+    /// An existing `path` must be of the same kind as the profiles, a file or a directory.
+    pub fn set_path(&mut self, target: &str, path: &Path) -> Result<(), RobeError> {
+        let cmd = domain::Target {
+            target: target.to_string(),
+            action: TargetAction::SetPath(path.to_path_buf()),
+        };
+        target::target(&cmd, &self.registry)?;
+        self.reload()
+    }
+
+    /// Names of the outfits, sorted.
+    pub fn outfits(&self) -> Result<Vec<String>, RobeError> {
+        outfit::names(&self.registry)
+    }
+
+    /// The profile worn with each target in `outfit`, by target.
+    pub fn outfit(&self, outfit: &str) -> Result<BTreeMap<String, String>, RobeError> {
+        Ok(outfit::load(&self.registry, outfit)?.profiles)
+    }
+
+    /// Record `profiles`, `(target, profile)` pairs, as `outfit`.
+    /// An existing outfit is only overwritten with `force`.
+    pub fn save_outfit(
+        &mut self,
+        outfit: &str,
+        profiles: &[(&str, &str)],
+        force: bool,
+    ) -> Result<(), RobeError> {
+        self.outfit_action(OutfitAction::Save {
+            name: outfit.to_string(),
+            profiles: profiles
+                .iter()
+                .map(|(t, p)| (t.to_string(), p.to_string()))
+                .collect(),
+            force,
+        })
+    }
+
+    /// Activate every profile of `outfit` like `use_profile`, or none of them.
+    pub fn wear_outfit(&mut self, outfit: &str, force: bool) -> Result<(), RobeError> {
+        self.outfit_action(OutfitAction::Wear {
+            name: outfit.to_string(),
+            force,
+            save: false,
+        })
+    }
+
+    /// Delete `outfit`, keeping its profiles.
+    pub fn rm_outfit(&mut self, outfit: &str) -> Result<(), RobeError> {
+        self.outfit_action(OutfitAction::Rm(outfit.to_string()))
+    }
+
+    fn outfit_action(&mut self, action: OutfitAction) -> Result<(), RobeError> {
+        let cmd = Outfit { action };
+        outfit::outfit(&cmd, &self.registry, &self.settings, Format::Plain)?;
+        self.reload()
+    }
+
+    /// Contents of `profile` of `target`, or of its live config when `profile` is `None`.
+    /// Symlinks inside a directory are read through; those to directories are left out,
+    /// and so are the entries the ignore patterns of the target match.
+    pub fn view(&self, target: &str, profile: Option<&str>) -> Result<Contents, RobeError> {
+        let tr = self.registry.target_registry(target)?;
+        let path = match profile {
+            Some(profile) => {
+                tr.assert_profile_exists(profile)?;
+                self.registry.profile_path(&tr, profile)
+            }
+            None => tr.real_path.clone(),
+        };
+
+        if !path.is_dir() {
+            return Ok(Contents::File(
+                fs::read(&path).map_err(RobeError::io("read", &path))?,
+            ));
+        }
+        let mut files = BTreeMap::new();
+        for (rel, file) in diff::collect_files(&path, &Ignore::for_target(&tr)?)? {
+            if !file.is_dir() {
+                let contents = fs::read(&file).map_err(RobeError::io("read", &file))?;
+                files.insert(rel, contents);
+            }
+        }
+        Ok(Contents::Dir(files))
+    }
+
+    fn describe(&self, tr: &TargetRegistry) -> Target {
+        let mut profiles = tr.profiles.clone();
+        profiles.sort();
+        Target {
+            name: tr.name.clone(),
+            real_path: tr.real_path.clone(),
+            mode: tr.mode,
+            profiles,
+            active: io::linked_profile(&self.registry, tr)
+                .or_else(|| tr.state.as_ref().map(|s| s.profile.clone())),
+        }
+    }
+
+    fn reload(&mut self) -> Result<(), RobeError> {
+        self.registry = utils::get_registry(&self.settings)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn open(dir: &Path) -> Wardrobe {
+        let settings = Settings {
+            wardrobe: dir.join("wardrobe").to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        Wardrobe::open(&settings).unwrap()
+    }
+
+    #[test]
+    fn test_register_add_use_rm() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "work")?;
+
+        let mut wardrobe = open(dir.path());
+        wardrobe.register("tmux", "work", &live, Mode::Copy)?;
+        fs::write(&live, "home")?;
+        wardrobe.add("tmux", "home", false)?;
+
+        let target = wardrobe.target("tmux")?;
+        assert_eq!(target.profiles, vec!["home", "work"]);
+        assert_eq!(target.active.as_deref(), Some("home"));
+        assert_eq!(target.real_path, live);
+
+        wardrobe.use_profile("tmux", "work", false)?;
+        assert_eq!(fs::read_to_string(&live)?, "work");
+        assert_eq!(
+            wardrobe.view("tmux", Some("home"))?,
+            Contents::File(b"home".to_vec())
+        );
+
+        wardrobe.cp("tmux", "home", "tmux", "office", false)?;
+        wardrobe.mv("tmux", "office", "travel", false)?;
+        assert_eq!(wardrobe.profiles("tmux")?, vec!["home", "travel", "work"]);
+        assert_eq!(
+            wardrobe.view("tmux", Some("travel"))?,
+            Contents::File(b"home".to_vec())
+        );
+
+        wardrobe.rm("tmux", Some("home"))?;
+        wardrobe.rm("tmux", Some("travel"))?;
+        assert_eq!(wardrobe.profiles("tmux")?, vec!["work"]);
+        wardrobe.rm("tmux", None)?;
+        assert!(wardrobe.targets().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_are_typed() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "work")?;
+
+        let mut wardrobe = open(dir.path());
+        assert!(matches!(
+            wardrobe.target("tmux"),
+            Err(RobeError::NotFound(_))
+        ));
+
+        wardrobe.register("tmux", "work", &live, Mode::Copy)?;
+        assert!(matches!(
+            wardrobe.add("tmux", "work", false),
+            Err(RobeError::AlreadyExists(_))
+        ));
+
+        wardrobe.add("tmux", "home", false)?;
+        fs::write(&live, "edited")?;
+        assert!(matches!(
+            wardrobe.use_profile("tmux", "work", false),
+            Err(RobeError::Drift(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_target_and_set_path() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("tmux.conf");
+        fs::write(&live, "work")?;
+
+        let mut wardrobe = open(dir.path());
+        wardrobe.register("tmux", "work", &live, Mode::Copy)?;
+        wardrobe.rename_target("tmux", "tmux3")?;
+
+        let moved = dir.path().join("tmux3.conf");
+        fs::rename(&live, &moved)?;
+        wardrobe.set_path("tmux3", &moved)?;
+
+        let target = wardrobe.target("tmux3")?;
+        assert_eq!(target.real_path, moved.canonicalize()?);
+        assert_eq!(target.profiles, vec!["work"]);
+        assert!(wardrobe.target("tmux").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_outfits() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let tmux = dir.path().join("tmux.conf");
+        let git = dir.path().join("gitconfig");
+        fs::write(&tmux, "tmux home")?;
+        fs::write(&git, "git home")?;
+
+        let mut wardrobe = open(dir.path());
+        wardrobe.register("tmux", "home", &tmux, Mode::Copy)?;
+        wardrobe.register("git", "home", &git, Mode::Copy)?;
+        fs::write(&tmux, "tmux work")?;
+        wardrobe.add("tmux", "work", false)?;
+        fs::write(&git, "git work")?;
+        wardrobe.add("git", "work", false)?;
+
+        wardrobe.save_outfit("home", &[("tmux", "home"), ("git", "home")], false)?;
+        assert_eq!(wardrobe.outfits()?, vec!["home"]);
+        assert_eq!(
+            wardrobe.outfit("home")?.get("git").map(String::as_str),
+            Some("home")
+        );
+
+        wardrobe.wear_outfit("home", false)?;
+        assert_eq!(fs::read_to_string(&tmux)?, "tmux home");
+        assert_eq!(fs::read_to_string(&git)?, "git home");
+        assert_eq!(wardrobe.target("git")?.active.as_deref(), Some("home"));
+
+        wardrobe.rm_outfit("home")?;
+        assert!(wardrobe.outfits()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_view_dir() -> Result<(), RobeError> {
+        let dir = tempdir()?;
+        let live = dir.path().join("nvim");
+        fs::create_dir_all(live.join("lua"))?;
+        fs::write(live.join("init.lua"), "init")?;
+        fs::write(live.join("lua").join("a.lua"), "a")?;
+
+        let mut wardrobe = open(dir.path());
+        wardrobe.register("nvim", "minimal", &live, Mode::Copy)?;
+
+        let expected = BTreeMap::from([
+            (PathBuf::from("init.lua"), b"init".to_vec()),
+            (PathBuf::from("lua").join("a.lua"), b"a".to_vec()),
+        ]);
+        assert_eq!(
+            wardrobe.view("nvim", Some("minimal"))?,
+            Contents::Dir(expected.clone())
+        );
+        assert_eq!(wardrobe.view("nvim", None)?, Contents::Dir(expected));
+        Ok(())
+    }
+}